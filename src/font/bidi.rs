@@ -0,0 +1,248 @@
+//! 双向文本（UAX #9）
+//!
+//! 按段落解析每个字符的嵌入层级（希伯来文、阿拉伯文、拉丁文、数字混排），
+//! 换行后再按行重排为视觉顺序（规则L1、L2）
+//! 层级为奇数表示从右到左， 偶数表示从左到右
+
+use std::ops::Range;
+
+use unicode_bidi::{BidiClass, BidiInfo, Level};
+
+/// 段落的双向分析结果
+///
+/// 所有索引均为字符索引（逻辑顺序）
+#[derive(Debug, Clone, Default)]
+pub struct BidiText {
+    /// 每个字符在原文本中的字节偏移
+    offsets: Vec<usize>,
+    /// 原文本字节长度
+    len: usize,
+    /// 每个字符的嵌入层级
+    levels: Vec<u8>,
+    /// 每个字符的原始双向类别， 用于按行重置层级（规则L1）
+    classes: Vec<BidiClass>,
+    /// 每个段落的字符范围及段落层级
+    paragraphs: Vec<(Range<usize>, u8)>,
+}
+
+impl BidiText {
+    /// 解析文本的嵌入层级
+    ///
+    /// # 参数
+    /// - `text`: 文本， 可包含多个段落（以换行符分隔）
+    /// - `rtl`: 段落方向， None表示按段落中第一个强方向字符自动判断（规则P2、P3）
+    pub fn new(text: &str, rtl: Option<bool>) -> Self {
+        let level = rtl.map(|r| if r { Level::rtl() } else { Level::ltr() });
+        let info = BidiInfo::new(text, level);
+
+        let mut r = Self { len: text.len(), ..Default::default() };
+        for (i, _) in text.char_indices() {
+            r.offsets.push(i);
+            r.levels.push(info.levels[i].number());
+            r.classes.push(info.original_classes[i]);
+        }
+        r.paragraphs = info.paragraphs.iter().map(|p| {
+            (r.char_index(p.range.start)..r.char_index(p.range.end), p.level.number())
+        }).collect();
+        r
+    }
+
+    /// 每个字符的嵌入层级
+    pub fn levels(&self) -> &[u8] {
+        &self.levels
+    }
+
+    /// 是否含有从右到左的字符
+    pub fn has_rtl(&self) -> bool {
+        self.levels.iter().any(|r| r & 1 == 1)
+    }
+
+    /// 字符所在段落的层级
+    pub fn paragraph_level(&self, char_index: usize) -> u8 {
+        self.paragraphs.iter()
+            .find(|(range, _)| range.contains(&char_index))
+            .or(self.paragraphs.last())
+            .map_or(0, |r| r.1)
+    }
+
+    /// 字节偏移对应的字符索引
+    pub fn char_index(&self, byte: usize) -> usize {
+        match self.offsets.binary_search(&byte) {
+            Ok(i) | Err(i) => i,
+        }
+    }
+
+    /// 层级相同的连续字符（逻辑顺序）， 每段使用同一方向塑形
+    ///
+    /// # 返回值
+    /// 每段在原文本中的字节范围及其层级
+    pub fn level_runs(&self) -> Vec<(Range<usize>, u8)> {
+        let mut runs: Vec<(Range<usize>, u8)> = Vec::new();
+        for (i, level) in self.levels.iter().enumerate() {
+            let end = self.offsets.get(i + 1).copied().unwrap_or(self.len);
+            match runs.last_mut() {
+                Some(r) if r.1 == *level => r.0.end = end,
+                _ => runs.push((self.offsets[i]..end, *level)),
+            }
+        }
+        runs
+    }
+
+    /// 一行中每个字符用于重排的层级
+    ///
+    /// 行尾的空白、段落及分段分隔符（及其之前的空白）重置为段落层级（规则L1）
+    ///
+    /// # 参数
+    /// - `line`: 行的字符范围， 换行后得到
+    pub fn line_levels(&self, line: Range<usize>) -> Vec<u8> {
+        let para_level = self.paragraph_level(line.start);
+        let mut levels = self.levels[line.clone()].to_vec();
+        let mut trailing = true;
+        for (level, class) in levels.iter_mut().zip(&self.classes[line]).rev() {
+            match class {
+                BidiClass::B | BidiClass::S => {
+                    *level = para_level;
+                    trailing = true;
+                }
+                BidiClass::WS | BidiClass::FSI | BidiClass::LRI | BidiClass::RLI | BidiClass::PDI
+                | BidiClass::BN | BidiClass::LRE | BidiClass::RLE | BidiClass::LRO | BidiClass::RLO | BidiClass::PDF => {
+                    if trailing {
+                        *level = para_level;
+                    }
+                }
+                _ => trailing = false,
+            }
+        }
+        levels
+    }
+
+    /// 一行的逻辑与视觉索引映射
+    ///
+    /// # 参数
+    /// - `line`: 行的字符范围， 换行后得到
+    pub fn line_map(&self, line: Range<usize>) -> BidiMap {
+        BidiMap::new(self.line_levels(line))
+    }
+}
+
+/// 一行中逻辑顺序与视觉顺序（从左到右）的索引映射
+///
+/// 索引相对于行首
+#[derive(Debug, Clone, Default)]
+pub struct BidiMap {
+    /// 每个位置的层级（逻辑顺序）
+    pub levels: Vec<u8>,
+    /// 视觉位置 -> 逻辑位置
+    pub visual_to_logical: Vec<usize>,
+    /// 逻辑位置 -> 视觉位置
+    pub logical_to_visual: Vec<usize>,
+}
+
+impl BidiMap {
+    /// 由一行中每个位置的层级（已应用规则L1）创建映射， 反转层级不低于各奇数层级的连续段（规则L2）
+    pub fn new(levels: Vec<u8>) -> Self {
+        let visual_to_logical = if levels.iter().any(|r| r & 1 == 1) {
+            BidiInfo::reorder_visual(&Level::vec(&levels))
+        } else {
+            (0..levels.len()).collect()
+        };
+        let mut logical_to_visual = vec![0; visual_to_logical.len()];
+        for (visual, logical) in visual_to_logical.iter().enumerate() {
+            logical_to_visual[*logical] = visual;
+        }
+        Self { levels, visual_to_logical, logical_to_visual }
+    }
+
+    /// 长度
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// 逻辑位置对应的视觉位置
+    pub fn visual(&self, logical: usize) -> usize {
+        self.logical_to_visual[logical]
+    }
+
+    /// 视觉位置对应的逻辑位置
+    pub fn logical(&self, visual: usize) -> usize {
+        self.visual_to_logical[visual]
+    }
+
+    /// 逻辑位置是否从右到左
+    pub fn is_rtl(&self, logical: usize) -> bool {
+        self.levels[logical] & 1 == 1
+    }
+
+    /// 按视觉顺序排列的方向相同的连续段
+    ///
+    /// # 返回值
+    /// 每段的逻辑范围及是否从右到左
+    pub fn visual_runs(&self) -> Vec<(Range<usize>, bool)> {
+        let mut runs: Vec<(Range<usize>, bool)> = Vec::new();
+        for logical in self.visual_to_logical.iter().copied() {
+            let rtl = self.is_rtl(logical);
+            match runs.last_mut() {
+                Some(r) if r.1 == rtl && !rtl && r.0.end == logical => r.0.end += 1,
+                Some(r) if r.1 == rtl && rtl && r.0.start == logical + 1 => r.0.start -= 1,
+                _ => runs.push((logical..logical + 1, rtl)),
+            }
+        }
+        runs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visual(text: &str, rtl: Option<bool>) -> String {
+        let bidi = BidiText::new(text, rtl);
+        let chars = text.chars().collect::<Vec<char>>();
+        bidi.line_map(0..chars.len()).visual_to_logical.iter().map(|r| chars[*r]).collect()
+    }
+
+    #[test]
+    fn reorder_mixed() {
+        assert_eq!(visual("abc", None), "abc");
+        assert_eq!(visual("אבג", None), "גבא");
+        assert_eq!(visual("ab אבג cd", Some(false)), "ab גבא cd");
+        // 从右到左的段落中， 拉丁文保持从左到右
+        assert_eq!(visual("אב cd גד", Some(true)), "דג cd בא");
+        // 数字在从右到左的文字中保持从左到右
+        assert_eq!(visual("א 12 ב", Some(true)), "ב 12 א");
+    }
+
+    #[test]
+    fn auto_direction() {
+        assert!(BidiText::new("אב cd", None).paragraph_level(0) & 1 == 1);
+        assert_eq!(BidiText::new("cd אב", None).paragraph_level(0), 0);
+        assert!(!BidiText::new("abc", None).has_rtl());
+    }
+
+    #[test]
+    fn trailing_whitespace_resets_level() {
+        let bidi = BidiText::new("אב  ", Some(false));
+        assert_eq!(bidi.line_levels(0..4), vec![1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn level_runs() {
+        let text = "ab אב";
+        let runs = BidiText::new(text, Some(false)).level_runs();
+        assert_eq!(runs, vec![(0..3, 0), (3..text.len(), 1)]);
+    }
+
+    #[test]
+    fn map() {
+        let map = BidiMap::new(vec![0, 1, 1, 0]);
+        assert_eq!(map.visual_to_logical, vec![0, 2, 1, 3]);
+        assert_eq!(map.visual(1), 2);
+        assert_eq!(map.logical(2), 1);
+        assert!(map.is_rtl(1) && !map.is_rtl(3));
+        assert_eq!(map.visual_runs(), vec![(0..1, false), (1..3, true), (3..4, false)]);
+    }
+}
@@ -1,243 +1,493 @@
 //! 位图文字实现
-//! 与平台相关， 在web平台依赖于canvas来测量、绘制文字
+//! 在cpu上将字体轮廓按文字的实际像素大小光栅化为覆盖率位图（单通道，0~255），并使用TextPacker装箱到纹理中
+//! 小字号（10~14px）时，sdf文字边缘发虚，位图文字更清晰
+//! 彩色字形（COLR、CBDT、sbix）按实际像素大小光栅化到单独的RGBA图集中（见`color_glyph`）
 
 use std::collections::hash_map::Entry;
 
+use ab_glyph_rasterizer::{point, Point, Rasterizer};
 use pi_hash::XHashMap;
-use pi_share::ThreadSync;
-use pi_slotmap::{SlotMap, DefaultKey};
-use crate::font_brush::Brush;
+use pi_null::Null;
+use pi_share::Share;
+use pi_slotmap::{SecondaryMap, SlotMap, DefaultKey};
+use ttf_parser::{Face, OutlineBuilder};
 
-use super::{text_pack::TextPacker, font::{Size, FontId, GlyphId, GlyphIdDesc, Glyph, FontInfo, BLOD_WEIGHT, BLOD_FACTOR, BASE_FONT_SIZE, FontFamilyId, Block, FontImage, Await, DrawBlock}};
+use super::{bidi::BidiText, color_glyph::{is_color_glyph, ColorAtlas}, font::{Block, Font, FontFaceId, FontId, FontImage, FontInfo, FontStyle, Glyph, GlyphId, GlyphIdDesc, Size}, sdf_table::MetricsInfo, shape::{pair_kerning, shape_text, ShapedText}, synth::{FaceStyle, SynthOutline}, text_pack::TextPacker, text_split::SplitChar2};
+
+/// 字形四周留出的空白像素，避免采样到相邻字形
+const PADDING: i32 = 1;
 
 pub struct BitmapTable {
-	glyph_id_map: XHashMap<(FontId, char), GlyphId>,
-	glyphs: SlotMap<DefaultKey, GlyphIdDesc>,
-	// base_glyphs: SlotMap<DefaultKey, BaseCharDesc>,
-	base_glyph_id_map: XHashMap<(FontFamilyId, char), BaseCharDesc>,
+	fonts: SecondaryMap<DefaultKey, (Share<Vec<u8>>, u32)>, // DefaultKey为FontFaceId， 值为(字体文件数据, 在字体集合中的索引)
+	pub metrics: SecondaryMap<DefaultKey, MetricsInfo>, // DefaultKey为FontFaceId
 
-	pub(crate) text_packer: TextPacker,
+	// (字体, 字形索引, 字号, (字重, 样式, 宽度))， 位图与字号及样式相关， 需要分别光栅化
+	glyph_id_map: XHashMap<(FontFaceId, u16, usize, (usize, FontStyle, usize)), GlyphId>,
+	pub glyphs: SlotMap<DefaultKey, GlyphIdDesc>,
+	// (字体, 左侧字形索引, 右侧字形索引)， 字距调整缓存， 与字号无关
+	kerns: XHashMap<(FontFaceId, u16, u16), f32>,
+	// (字体, 字符)， cmap查询结果缓存， 避免每次查询字形都重新解析字体
+	cmap: XHashMap<(FontFaceId, char), Option<u16>>,
 
-	pub brush: Brush,
+	pub(crate) text_packer: TextPacker,
+	// 彩色字形的RGBA图集
+	pub(crate) color: ColorAtlas,
 }
 
 impl BitmapTable{
 	pub fn new(width: usize, height: usize) -> Self {
-		Self { 
+		Self {
+			fonts: SecondaryMap::default(),
+			metrics: SecondaryMap::default(),
 			glyph_id_map: XHashMap::default(),
 			glyphs: SlotMap::default(),
-			// base_glyphs: SlotMap<DefaultKey, BaseCharDesc>,
-
+			kerns: XHashMap::default(),
+			cmap: XHashMap::default(),
 			text_packer: TextPacker::new(width, height),
-			base_glyph_id_map: XHashMap::default(),
-			brush: Brush::new(),
+			color: ColorAtlas::new(width, height),
 		}
 	}
-	pub fn glyph_id(&mut self, f: FontId, font_info: &mut FontInfo, char: char) -> Option<GlyphId> {
-		// let id = match self.glyph_id_map.entry((f, char)) {
-		// 	Entry::Occupied(r) => {
-		// 		let id = r.get().clone();
-		// 		return Some(id);
-		// 	},
-		// 	Entry::Vacant(r) => {
-		// 		// 分配GlyphId
-		// 		let id = GlyphId(self.glyphs.insert(GlyphIdDesc{
-		// 			font_id: f,
-		// 			char,
-		// 			font_face_index: 0,
-		// 			glyph: Glyph {
-		// 				x: 0.0, 
-		// 				y: 0.0, 
-		// 				plane_min_x: 0.0,
-		// 				plane_min_y: 0.0,
-		// 				plane_max_x: 0.0,
-		// 				plane_max_y: 0.0,
-		// 				width: 0.0, 
-		// 				height: 0.0,
-		// 				advance: 0.0,},
-		// 		}));
-
-		// 		r.insert(id).clone()
-		// 	}
-		// };
-
-		// // let ff = font.font.font_family_string.clone();
-		// let max_height = font_info.max_height;
-		// let char_texture_size: Size<f32> = {
-		// 	font_info.await_info.wait_list.push(id);
-
-		// 	let (base_w, index) = self.measure_base(font_info, char);
-		// 	let glyph = &mut self.glyphs[id.0];
-		// 	glyph.font_face_index = index;
-
-		// 	Size {
-		// 		width: base_w * (font_info.font.font_size as f32 / BASE_FONT_SIZE as f32) + *font_info.font.stroke + 2.0, 
-		// 		height: font_info.height,
-		// 	}
-		// };
-
-		// // 放入等待队列, 并统计等待队列的总宽度
-		// font_info.await_info.size.width += char_texture_size.width.ceil() as usize;
-		// font_info.await_info.size.height += char_texture_size.height.ceil() as usize;
-
-		// // 在纹理中分配一个位置
-		// let tex_position = self.text_packer.alloc(
-		// 	char_texture_size.width.ceil() as usize, 
-		// 	max_height.ceil() as usize);
-		// let tex_position = match tex_position {
-		// 	Some(r) => r,
-		// 	None => return None,
-		// };
-		// // log::warn!("char_texture_size====={:?}, {:?}, {:?}, {:?}", &char, &char_texture_size, tex_position, ff);
-		// let g = &mut self.glyphs[*id];
-		// g.glyph.width = char_texture_size.width.round();
-		// g.glyph.height = char_texture_size.height;
-		// g.glyph.x = tex_position.x as f32;
-		// g.glyph.y = tex_position.y as f32;
-
-		// log::warn!("g1============={:?}, {:?}", id, char);
-
-		// Some(id)
-		todo!()
-	}
-	
-	/// 取到字形信息
-	pub fn glyph(&self, id: GlyphId) -> &Glyph {
-		if self.glyphs.get(*id).is_none() {
-			panic!("glyph is not exist, {:?}", id);
-		}
-		&self.glyphs[*id].glyph
+
+	/// 清空纹理中的所有文字， 已加载的字体保留， 之前分配的GlyphId全部失效
+	pub fn clear(&mut self) {
+		self.text_packer.clear();
+		self.color.clear();
+		self.glyph_id_map.clear();
+		self.glyphs.clear();
 	}
 
-	/// 绘制文字
-	pub fn draw<F: FnMut(Block, FontImage) + Clone + ThreadSync + 'static>(&mut self, fonts: &mut SlotMap<DefaultKey, FontInfo>, update: F) {
-		// // let (fonts, glyphs) = (&mut self.fonts, &self.glyphs);
-		// let width = self.text_packer.width;
-		// // let (sheet, brush) = (&mut self.sheet, &mut self.brush);
-		// // let (glyphs, fonts) = (&sheet.glyphs, &mut sheet.fonts);
-
-		// // let mut sdf_all_draw = Vec::new();
-		// let mut native_all_draw = Vec::new();
-		// // let mut sdf_all_draw_slotmap = Vec::new();
-		// // let mut f = Vec::new();
-		// // 遍历所有支持的字体，如果其对应的绘制等待列表不为空，则进行绘制
-		// for (k, font_info) in fonts.iter_mut() {
-		// 	let await_info = &font_info.await_info;
-		// 	if await_info.wait_list.len() == 0 {
-		// 		continue;
-		// 	}
-
-		// 	log::warn!("await_info.wait_list========={:?}", &await_info.wait_list);
-
-		// 	let offset = *font_info.font.stroke/2.0;
-
-		// 	// let g_0 = &glyphs[*await_info.wait_list[0]];
-		// 	let mut start_pos;
-		// 	let (mut y, mut height, mut font_face_index);
-
-		// 	let (mut start, mut end) = (0, 0.0);
-		// 	let mut x_c;
-
-		// 	while start < await_info.wait_list.len() {
-		// 		let g = &self.glyphs[*await_info.wait_list[start]];
-		// 		log::warn!("g============={:?}, {:?}", &g.glyph, g.char);
-		// 		start_pos = (g.glyph.x, g.glyph.y);
-		// 		y = g.glyph.y as f32;
-		// 		font_face_index = g.font_face_index;
-		// 		height = g.glyph.height;
-
-		// 		x_c = Vec::new();
-
-		// 		// 每一批次绘制，只绘制同一行的字符
-		// 		for i in start..await_info.wait_list.len() {
-		// 			let g = &self.glyphs[
-		// 				*await_info.wait_list[i]
-		// 			];
-		// 			log::warn!("g============={:?}, {:?}", &g.glyph, g.char);
-
-		// 			// y不相同的字符（不在同一行）, 或者fontface不同，在下一批次绘制，因此结束本批次字符的收集
-		// 			if g.glyph.y as f32 != y || g.font_face_index != font_face_index {
-		// 				break;
-		// 			} else if g.glyph.x as f32 - end > 1.0 && x_c.len() > 0 {
-		// 				// y相同， 但x间有空位，也在下批次处理
-		// 				break;
-		// 			}
-		// 			// 否则y相同，则加入当前批次
-		// 			x_c.push(Await {
-		// 				x_pos: g.glyph.x as f32 - start_pos.0 as f32 + offset, // 如果有描边，需要偏移一定位置，否则可能无法容纳描边
-		// 				char: g.char,
-		// 				width: g.glyph.width as u32,
-		// 				height: g.glyph.height as u32,
-		// 			});
-		// 			end = g.glyph.x as f32 + g.glyph.width;
-		// 		}
-		// 		start += x_c.len();
-
-		// 		let mut end = end + 1.0;
-		// 		if end as usize > width {
-		// 			end = width as f32;
-		// 		}
-				
-		// 		let block = DrawBlock {
-		// 			chars: x_c,
-		// 			font_id: FontId(k),
-		// 			font_size: font_info.font.font_size,
-		// 			// font_stroke:  font_info.font.stroke,
-		// 			block: Block {
-		// 				x: start_pos.0 as f32,
-		// 				y: start_pos.1 as f32,
-		// 				width: end - start_pos.0 as f32,
-		// 				height,
-		// 			},
-		// 			font_face_index,
-		// 			font_family: font_info.font.font_family.clone(),
-		// 		};
-		// 		native_all_draw.push(block);
-				
-		// 	}
-
-		// 	font_info.await_info.wait_list.clear();
-		// 	font_info.await_info.size = Size {width: 0, height: 0};// 似乎没有作用？
-		// }
-
-		// if native_all_draw.len() > 0 {
-		// 	// 绘制一个批次的字符
-		// 	self.brush.draw(native_all_draw, update);
-		// }
-	}
-
-
-	fn measure_base(&mut self, font_info: &FontInfo, char: char) -> (f32, usize) {
-		match self.base_glyph_id_map.entry((font_info.font_family_id, char)) {
-			Entry::Occupied(r) => {
-				let g = r.get();
-				(g.width, g.font_face_index)
+	// 添加字体， index为字体在字体集合中的索引
+	pub fn add_font(&mut self, face_id: FontFaceId, buffer: Share<Vec<u8>>, index: u32) {
+		let metrics = match Face::parse(&buffer, index) {
+			Ok(face) => {
+				let units_per_em = face.units_per_em() as f32;
+				let ascender = face.ascender() as f32 / units_per_em;
+				let descender = face.descender() as f32 / units_per_em;
+				let height = ascender - descender;
+				let (underline_y, underline_thickness) = match face.underline_metrics() {
+					Some(r) => (r.position as f32 / units_per_em, r.thickness as f32 / units_per_em),
+					None => (0.0, 0.0),
+				};
+				MetricsInfo {
+					font_size: 1.0, // 位图按实际字号光栅化， 度量信息均为font_size的百分比
+					distance_range: 0.0,
+					line_height: height,
+					max_height: height,
+					ascender,
+					descender,
+					underline_y,
+					underline_thickness,
+					em_size: 1.0,
+				}
 			},
-			Entry::Vacant(r) => {
-				let (mut width, index) = self.brush.base_width(font_info, char);
-
-				r.insert(BaseCharDesc{
-					font_family_id: font_info.font_family_id,
-					char,
-					width,
-					font_face_index: index,
-				});
-
-				// 如果是ascii字符， 其粗体文字的宽度会适当加宽（浏览器实验所得结果）
-				let is_blod = char.is_ascii() && font_info.font.font_weight >= BLOD_WEIGHT;
-				if is_blod {
-					width = width * BLOD_FACTOR;
+			Err(e) => {
+				log::warn!("bitmap font parse fail, face_id: {:?}, err: {:?}", face_id, e);
+				return;
+			}
+		};
+		self.metrics.insert(face_id.0, metrics);
+		self.fonts.insert(face_id.0, (buffer, index));
+	}
+
+	/// 是否已添加字体数据
+	pub fn has_font(&self, face_id: FontFaceId) -> bool {
+		self.fonts.contains_key(face_id.0)
+	}
+
+	/// 字体在字体外观上的样式（可变轴坐标及需要合成的效果）
+	pub fn face_style(&self, face_id: FontFaceId, font: &Font) -> Option<FaceStyle> {
+		let (data, index) = self.fonts.get(face_id.0)?;
+		let face = Face::parse(data, *index).ok()?;
+		Some(FaceStyle::new(&face, font))
+	}
+
+	// 文字高度
+	pub fn height(&self, font: &FontInfo) -> (f32, f32 /*max_height*/) {
+		let mut ret = (0.0, 0.0);
+		for font_id in font.font_ids.iter() {
+			if let Some(r) = self.metrics.get(font_id.0) {
+				if r.max_height > ret.0 {
+					ret.0 = r.max_height;
+				}
+			}
+		}
+		ret.1 = ret.0;
+		ret
+	}
+
+	pub fn metrics(&self, glyph_id: GlyphId, font: &FontInfo) -> Option<&MetricsInfo> {
+		let glyph = self.glyphs.get(glyph_id.0)?;
+		if glyph.font_face_index.is_null() {
+			return None;
+		}
+		self.metrics.get(font.font_ids.get(glyph.font_face_index)?.0)
+	}
+
+	pub fn fontface_metrics(&self, face_id: FontFaceId) -> Option<&MetricsInfo> {
+		self.metrics.get(face_id.0)
+	}
+
+	/// 字形描述， GlyphId为空或已失效（如调用过`clear`）时返回None
+	pub fn glyph_id_desc(&self, glyph_id: GlyphId) -> Option<&GlyphIdDesc> {
+		self.glyphs.get(glyph_id.0)
+	}
+
+	/// 取到字形信息， GlyphId为空或已失效时返回None
+	pub fn glyph(&self, id: GlyphId) -> Option<&Glyph> {
+		self.glyphs.get(*id).map(|r| &r.glyph)
+	}
+
+	// 文字宽度
+	pub fn width(&mut self, font_id: FontId, font: &mut FontInfo, char: char) -> (f32, GlyphId) {
+		match self.glyph_id(font_id, font, char) {
+			Some(glyph_id) => (self.width_of_glyph_id(font_id, font, glyph_id), glyph_id),
+			None => (0.0, GlyphId(DefaultKey::null())),
+		}
+	}
+
+	// 文字宽度
+	pub fn width_of_glyph_id(&mut self, _font_id: FontId, font: &mut FontInfo, glyph_id: GlyphId) -> f32 {
+		match self.glyphs.get(glyph_id.0) {
+			Some(r) => r.glyph.advance * font.font.font_size as f32,
+			None => 0.5 * font.font.font_size as f32,
+		}
+	}
+
+	/// 两个相邻字形之间的字距调整（像素）
+	///
+	/// 两个字形不在同一字体中时为0
+	pub fn kern(&mut self, font: &FontInfo, left: GlyphId, right: GlyphId) -> f32 {
+		let (l, r) = match (self.glyphs.get(left.0), self.glyphs.get(right.0)) {
+			(Some(l), Some(r)) if l.font_face_index == r.font_face_index => (l, r),
+			_ => return 0.0,
+		};
+		let face_id = match font.font_ids.get(l.font_face_index) {
+			Some(r) => *r,
+			None => return 0.0,
+		};
+		let key = (face_id, l.glyph_index, r.glyph_index);
+		let kern = match self.kerns.get(&key) {
+			Some(r) => *r,
+			None => {
+				let kern = self.fonts.get(face_id.0).map_or(0.0, |(data, index)| pair_kerning(data, *index, key.1, key.2));
+				self.kerns.insert(key, kern);
+				kern
+			}
+		};
+		kern * font.font.font_size as f32
+	}
+
+	/// 字形id
+	///
+	/// 按该字符所属文字的后备顺序（见`FaceFallback`）查找含有该字符的字体， 都不存在时， 依次使用'□'、' '代替
+	/// 字体都未加载时返回None； 字体中不存在替代字符时， 返回空的GlyphId
+	pub fn glyph_id(&mut self, font_id: FontId, font_info: &mut FontInfo, char: char) -> Option<GlyphId> {
+		let mut has_face = false;
+		let order = font_info.fallback.order(char, font_info.font_ids.len());
+		for c in [char, '□', ' '] {
+			for index in order.iter().copied() {
+				let face_id = font_info.font_ids[index];
+				let glyph_index = match self.cmap_index(face_id, c) {
+					Some(r) => {
+						has_face = true;
+						r
+					},
+					None => continue,
+				};
+				if let Some(glyph_index) = glyph_index {
+					return self.glyph_id_of_index(font_id, font_info, index, c, glyph_index.0);
 				}
+			}
+			if !has_face {
+				return None;
+			}
+			if c == char {
+				log::warn!("{:?} is not have {}", font_info.font.font_family_string.as_str(), char);
+			}
+		}
+		log::warn!("{:?} is not have ' ' or '□'", font_info.font.font_family_string.as_str());
+		Some(GlyphId(DefaultKey::null()))
+	}
+
+	// 字符在字体中的字形索引， 字体未加载时返回None
+	// 结果按(字体, 字符)缓存， 只有首次查询时解析字体
+	fn cmap_index(&mut self, face_id: FontFaceId, char: char) -> Option<Option<u16>> {
+		if let Some(r) = self.cmap.get(&(face_id, char)) {
+			return Some(*r);
+		}
+		let (data, index) = self.fonts.get(face_id.0)?;
+		let face = Face::parse(data, *index).ok()?;
+		let r = face.glyph_index(char).map(|r| r.0);
+		self.cmap.insert((face_id, char), r);
+		Some(r)
+	}
+
+	/// 取到字体列表中第`font_face_index`个字体的`glyph_index`字形对应的GlyphId
+	///
+	/// 首次取时， 在纹理中为字形分配位置， 并放入等待队列， 在draw时光栅化
+	/// 纹理空间不足时返回None
+	pub fn glyph_id_of_index(&mut self, font_id: FontId, font_info: &mut FontInfo, font_face_index: usize, char: char, glyph_index: u16) -> Option<GlyphId> {
+		let face_id = *font_info.font_ids.get(font_face_index)?;
+		let font_size = font_info.font.font_size.max(1);
+		let BitmapTable { fonts, glyph_id_map, glyphs, text_packer, color, .. } = self;
+
+		let font = &font_info.font;
+		let r = match glyph_id_map.entry((face_id, glyph_index, font_size, (font.font_weight, font.font_style, font.font_stretch))) {
+			Entry::Occupied(r) => return Some(*r.get()),
+			Entry::Vacant(r) => r,
+		};
+
+		// 只有首次取该字形时才解析字体， 之后命中上面的缓存
+		let (data, collection_index) = fonts.get(face_id.0)?;
+		let mut face = Face::parse(data, *collection_index).ok()?;
+		let style = FaceStyle::new(&face, font);
+		style.apply(&mut face);
+		let units_per_em = face.units_per_em() as f32;
+		let scale = font_size as f32 / units_per_em;
+		let index = ttf_parser::GlyphId(glyph_index);
+
+		// 合成粗体时， 步进宽度增加外扩量
+		let mut glyph = Glyph {
+			advance: face.glyph_hor_advance(index).unwrap_or(0) as f32 / units_per_em + style.embolden,
+			font_size: font_size as f32,
+			..Default::default()
+		};
+
+		// 彩色字形在彩色图集中分配位置， 由`draw_color`光栅化
+		if is_color_glyph(&face, glyph_index) {
+			let glyph = color.alloc(data, *collection_index, glyph_index, font_size, glyph.advance)?;
+			let id = GlyphId(glyphs.insert(GlyphIdDesc {
+				font_id,
+				char,
+				glyph_index,
+				glyph,
+				font_face_index,
+			}));
+			r.insert(id);
+			return Some(id);
+		}
+
+		let mut need_draw = false;
+		// 可变轴坐标及合成效果作用后的轮廓包围盒
+		let mut outline = SynthOutline::new();
+		let rect = match char.is_whitespace() {
+			false => face.outline_glyph(index, &mut outline).and_then(|_| outline.bounds(&style, units_per_em)),
+			true => None,
+		};
+		// 空白符或没有轮廓的字形， 只需要步进宽度， 不需要在纹理中分配位置
+		if let Some([x_min, y_min, x_max, y_max]) = rect {
+			// 包围盒对齐到像素
+			let min_x = (x_min * scale).floor() as i32 - PADDING;
+			let min_y = (y_min * scale).floor() as i32 - PADDING;
+			let max_x = (x_max * scale).ceil() as i32 + PADDING;
+			let max_y = (y_max * scale).ceil() as i32 + PADDING;
+			let (width, height) = ((max_x - min_x) as usize, (max_y - min_y) as usize);
+
+			let offset = text_packer.alloc(width, height)?;
+			glyph.plane_min_x = min_x as f32 / font_size as f32;
+			glyph.plane_min_y = min_y as f32 / font_size as f32;
+			glyph.plane_max_x = max_x as f32 / font_size as f32;
+			glyph.plane_max_y = max_y as f32 / font_size as f32;
+			glyph.x = offset.x as f32;
+			glyph.y = offset.y as f32;
+			glyph.page = offset.page;
+			glyph.width = width as f32;
+			glyph.height = height as f32;
+			need_draw = true;
+		}
+
+		let id = GlyphId(glyphs.insert(GlyphIdDesc {
+			font_id,
+			char,
+			glyph_index,
+			glyph,
+			font_face_index,
+		}));
+		r.insert(id);
+
+		if need_draw {
+			font_info.await_info.wait_list.push(id);
+		}
+		Some(id)
+	}
 
-				(width, index)
+	/// 塑形
+	///
+	/// 使用字体的GSUB/GPOS表将文本转换为字形序列， 并为每个字形分配GlyphId
+	/// 所有字体中都不存在的字形， 使用'□'代替
+	pub fn shape(&mut self, font_id: FontId, font_info: &mut FontInfo, text: &str, is_reverse: bool) -> ShapedText {
+		let bidi = BidiText::new(text, Some(is_reverse));
+		let raw = {
+			let faces = font_info.font_ids.iter().map(|r| self.fonts.get(r.0).map(|(data, index)| (data.as_slice(), *index))).collect::<Vec<_>>();
+			shape_text(&faces, text, &bidi, &font_info.fallback, &font_info.font, &[])
+		};
+		ShapedText::new(text, is_reverse, bidi, raw, |r, char| {
+			if r.glyph_index == 0 {
+				let id = self.glyph_id(font_id, font_info, '□');
+				r.x_advance = match id.and_then(|id| self.glyphs.get(id.0)) {
+					Some(g) => g.glyph.advance,
+					None => 0.5,
+				};
+				return id;
+			}
+			self.glyph_id_of_index(font_id, font_info, r.font_face_index, char, r.glyph_index)
+		})
+	}
+
+	// 字形id， 每个字形簇一个字符及其第一个字形的id， 按视觉顺序排列（每个段落一行）
+	pub fn glyph_indexs(&mut self, font_id: FontId, font_info: &mut FontInfo, text: &str, is_reverse: bool) -> (String, Vec<Option<GlyphId>>) {
+		self.shape(font_id, font_info, text, is_reverse).visual_glyph_ids()
+	}
+
+	/// 劈分文字， 按塑形后的字形簇劈分， 劈分结果中的索引为字形簇的索引（逻辑顺序）
+	/// 换行后通过`SplitChar2::line_map`取得每行的视觉顺序
+	pub fn split<'a>(&mut self, font_id: FontId, font_info: &mut FontInfo, text: &'a str, word_split: bool, merge_whitespace: bool, is_reverse: bool) -> SplitChar2<'a> {
+		let shaped = self.shape(font_id, font_info, text, is_reverse);
+		let (text2, glyph_ids) = shaped.cluster_glyph_ids();
+		let mut i = text.chars();
+		let last = i.next();
+		SplitChar2 {
+			text: text2.chars().collect::<Vec<char>>(),
+			glyph_ids,
+			cur_index: 0,
+			iter: i,
+			word_split,
+			merge_whitespace,
+			last,
+			type_id: 0,
+			shaped: Some(shaped),
+			line_breaks: None,
+		}
+	}
+
+	/// 绘制文字
+	///
+	/// 同步光栅化所有等待队列中的字形， 每个字形调用一次update，
+	/// FontImage为单通道覆盖率数据（width * height字节）， Block为其在纹理中的位置
+	pub fn draw<F: FnMut(Block, FontImage)>(&mut self, fonts: &mut SlotMap<DefaultKey, FontInfo>, mut update: F) {
+		for (_, font_info) in fonts.iter_mut() {
+			if font_info.await_info.wait_list.len() == 0 {
+				continue;
+			}
+			let font_size = font_info.font.font_size.max(1);
+			// 每个字体外观只解析一次并设置样式， 同一字体的所有等待字形共用
+			let mut faces: Vec<Option<Option<(Face, FaceStyle)>>> = vec![None; font_info.font_ids.len()];
+
+			for glyph_id in font_info.await_info.wait_list.drain(..) {
+				let g = match self.glyphs.get(glyph_id.0) {
+					Some(r) => r,
+					None => continue,
+				};
+				let slot = match faces.get_mut(g.font_face_index) {
+					Some(r) => r,
+					None => continue,
+				};
+				let (face, style) = match slot.get_or_insert_with(|| {
+					let (data, index) = self.fonts.get(font_info.font_ids[g.font_face_index].0)?;
+					let mut face = Face::parse(data, *index).ok()?;
+					let style = FaceStyle::new(&face, &font_info.font);
+					style.apply(&mut face);
+					Some((face, style))
+				}) {
+					Some(r) => r,
+					None => continue,
+				};
+
+				if let Some(image) = rasterize(face, style, g.glyph_index, &g.glyph, font_size) {
+					update(Block {
+						x: g.glyph.x,
+						y: g.glyph.y,
+						width: g.glyph.width,
+						height: g.glyph.height,
+						page: g.glyph.page,
+					}, image);
+				}
 			}
+			font_info.await_info.size = Size {width: 0, height: 0};
 		}
 	}
+
+	/// 绘制彩色字形
+	///
+	/// 光栅化所有等待中的彩色字形， 每个字形调用一次update，
+	/// FontImage为RGBA8数据（非预乘， width * height * 4字节）， Block为其在彩色图集中的位置
+	pub fn draw_color<F: FnMut(Block, FontImage)>(&mut self, update: F) {
+		self.color.draw(update);
+	}
 }
 
-pub struct BaseCharDesc {
-	pub font_family_id: FontFamilyId,
-	pub char: char,
-	pub width: f32,
-	pub font_face_index: usize,
-}
\ No newline at end of file
+/// 将字形光栅化为覆盖率位图， 位图范围为字形在纹理中分配的区域
+///
+/// 字体外观需已设置可变轴坐标， 合成粗体、斜体在轮廓上完成
+fn rasterize(face: &Face, style: &FaceStyle, glyph_index: u16, glyph: &Glyph, font_size: usize) -> Option<FontImage> {
+	let (width, height) = (glyph.width as usize, glyph.height as usize);
+	let mut builder = GlyphRasterizer {
+		rasterizer: Rasterizer::new(width, height),
+		scale: font_size as f32 / face.units_per_em() as f32,
+		origin_x: (glyph.plane_min_x * font_size as f32).round(),
+		origin_y: (glyph.plane_max_y * font_size as f32).round(),
+		start: Point::default(),
+		last: Point::default(),
+	};
+	let mut outline = SynthOutline::new();
+	face.outline_glyph(ttf_parser::GlyphId(glyph_index), &mut outline)?;
+	outline.emit(style, face.units_per_em() as f32, &mut builder);
+
+	let mut buffer = vec![0; width * height];
+	builder.rasterizer.for_each_pixel(|index, alpha| {
+		buffer[index] = (alpha.clamp(0.0, 1.0) * 255.0).round() as u8;
+	});
+	Some(FontImage {
+		buffer,
+		width,
+		height,
+	})
+}
+
+/// 将字体轮廓（字体单位，y轴向上）转换到位图空间（像素，y轴向下）并绘制
+struct GlyphRasterizer {
+	rasterizer: Rasterizer,
+	scale: f32,
+	origin_x: f32, // 位图左边界（像素）
+	origin_y: f32, // 位图上边界（像素）
+	start: Point,
+	last: Point,
+}
+
+impl GlyphRasterizer {
+	fn point(&self, x: f32, y: f32) -> Point {
+		point(x * self.scale - self.origin_x, self.origin_y - y * self.scale)
+	}
+}
+
+impl OutlineBuilder for GlyphRasterizer {
+	fn move_to(&mut self, x: f32, y: f32) {
+		self.start = self.point(x, y);
+		self.last = self.start;
+	}
+
+	fn line_to(&mut self, x: f32, y: f32) {
+		let p = self.point(x, y);
+		self.rasterizer.draw_line(self.last, p);
+		self.last = p;
+	}
+
+	fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+		let (p1, p) = (self.point(x1, y1), self.point(x, y));
+		self.rasterizer.draw_quad(self.last, p1, p);
+		self.last = p;
+	}
+
+	fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+		let (p1, p2, p) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+		self.rasterizer.draw_cubic(self.last, p1, p2, p);
+		self.last = p;
+	}
+
+	fn close(&mut self) {
+		if self.last != self.start {
+			self.rasterizer.draw_line(self.last, self.start);
+		}
+		self.last = self.start;
+	}
+}
@@ -0,0 +1,294 @@
+//! 光标定位及点击测试
+//!
+//! 基于排版结果（`TextLayout`）计算光标位置、点击位置对应的文本索引、选区矩形，
+//! 以及按字形簇（UAX #29）、单词移动光标时的边界
+//! 所有索引均为段落（所有文字段按顺序相连的文本）中的字节偏移
+
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::layout::{GlyphQuad, TextLayout};
+
+/// 光标
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Caret {
+    /// X坐标（像素）
+    pub x: f32,
+    /// 所在行顶部Y坐标（像素）
+    pub y: f32,
+    /// 所在行行高（像素）
+    pub height: f32,
+    /// 所在行的索引
+    pub line: usize,
+}
+
+/// 矩形
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl GlyphQuad {
+    // 文本索引在字形中的X坐标， 连字等多字符字形簇按字节比例插值
+    fn caret_x(&self, index: usize) -> f32 {
+        let len = self.range.len().max(1);
+        let t = (index.clamp(self.range.start, self.range.end) - self.range.start) as f32 / len as f32;
+        if self.rtl {
+            self.x + self.width * (1.0 - t)
+        } else {
+            self.x + self.width * t
+        }
+    }
+}
+
+impl TextLayout {
+    /// 文本索引处的光标
+    ///
+    /// 自动换行处的索引既是上一行的末尾， 也是下一行的开头， 光标位于下一行的开头
+    /// 双向文本中， 光标位于该索引处字形簇的前沿（从左到右时为左边， 从右到左时为右边）
+    ///
+    /// # 参数
+    /// - `index`: 字节偏移
+    pub fn caret(&self, index: usize) -> Caret {
+        let line = self.line_of(index);
+        let line_box = match self.lines.get(line) {
+            Some(r) => r,
+            None => return Caret { x: 0.0, y: 0.0, height: 0.0, line: 0 },
+        };
+        let glyphs = &self.glyphs[line_box.glyphs.clone()];
+        let x = glyphs.iter().find(|g| g.range.start == index)
+            .or_else(|| glyphs.iter().find(|g| g.range.start < index && index < g.range.end))
+            .or_else(|| glyphs.iter().find(|g| g.range.end == index))
+            .map(|g| g.caret_x(index))
+            .or_else(|| {
+                // 被合并或丢弃的空白， 取其后第一个字形的前沿
+                glyphs.iter().filter(|g| g.range.start > index)
+                    .min_by_key(|g| g.range.start)
+                    .map(|g| g.caret_x(g.range.start))
+            })
+            .unwrap_or_else(|| {
+                // 行尾（段落方向的结束端）
+                match glyphs.iter().max_by_key(|g| g.range.end) {
+                    Some(g) => g.caret_x(g.range.end),
+                    None => line_box.x,
+                }
+            });
+        Caret { x, y: line_box.y, height: line_box.height, line }
+    }
+
+    /// 点击位置对应的文本索引（光标应放置的位置）
+    ///
+    /// Y坐标超出范围时取第一行或最后一行， X坐标超出行的范围时取行的开头或末尾
+    /// 在单词内或CJK文字间自动换行时， 行末尾的索引与下一行开头相同， 光标显示在下一行开头
+    ///
+    /// # 参数
+    /// - `x`、`y`: 相对于排版结果左上角的坐标（像素）
+    pub fn hit_test(&self, x: f32, y: f32) -> usize {
+        let line = match self.lines.iter().position(|r| y < r.y + r.height) {
+            Some(r) => r,
+            None if self.lines.is_empty() => return 0,
+            None => self.lines.len() - 1,
+        };
+        let line_box = &self.lines[line];
+        let glyphs = &self.glyphs[line_box.glyphs.clone()];
+        let (first, last) = match (glyphs.first(), glyphs.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return line_box.range.start,
+        };
+
+        let glyph = if x < first.x {
+            first
+        } else {
+            glyphs.iter().find(|g| x < g.x + g.width).unwrap_or(last)
+        };
+        // 点击位置在字形的左半边或右半边
+        let left = x < glyph.x + glyph.width / 2.0;
+        if left != glyph.rtl { glyph.range.start } else { glyph.range.end }
+    }
+
+    /// 选区矩形
+    ///
+    /// 每行一个或多个矩形（双向文本中， 选区在视觉上可能不连续）， 按行及从左到右排列
+    ///
+    /// # 参数
+    /// - `range`: 选区的字节范围（逻辑顺序）
+    pub fn selection_rects(&self, range: Range<usize>) -> Vec<Rect> {
+        let mut rects: Vec<Rect> = Vec::new();
+        if range.is_empty() {
+            return rects;
+        }
+        for line_box in self.lines.iter() {
+            let start = rects.len();
+            for g in self.glyphs[line_box.glyphs.clone()].iter() {
+                let (s, e) = (g.range.start.max(range.start), g.range.end.min(range.end));
+                if s >= e {
+                    continue;
+                }
+                let (a, b) = (g.caret_x(s), g.caret_x(e));
+                let (left, right) = (a.min(b), a.max(b));
+                let same_line = rects.len() > start;
+                match rects.last_mut() {
+                    // 与同一行中视觉上相邻的矩形合并
+                    Some(r) if same_line && (r.x + r.width - left).abs() < 0.01 => r.width = right - r.x,
+                    _ => rects.push(Rect { x: left, y: line_box.y, width: right - left, height: line_box.height }),
+                }
+            }
+        }
+        rects
+    }
+
+    // 文本索引所在的行
+    fn line_of(&self, index: usize) -> usize {
+        // 自动换行处的索引属于下一行
+        self.lines.iter()
+            .rposition(|r| r.range.start <= index)
+            .unwrap_or(0)
+    }
+}
+
+/// 下一个字形簇边界（向右移动光标一个“字符”）
+///
+/// # 参数
+/// - `text`: 段落文本
+/// - `index`: 字节偏移
+pub fn next_grapheme(text: &str, index: usize) -> usize {
+    text.grapheme_indices(true)
+        .map(|(i, s)| i + s.len())
+        .find(|r| *r > index)
+        .unwrap_or(text.len())
+}
+
+/// 上一个字形簇边界
+pub fn prev_grapheme(text: &str, index: usize) -> usize {
+    text.grapheme_indices(true)
+        .map(|(i, _)| i)
+        .take_while(|r| *r < index)
+        .last()
+        .unwrap_or(0)
+}
+
+/// 下一个单词的末尾（UAX #29单词边界， 跳过空白及标点）
+pub fn next_word(text: &str, index: usize) -> usize {
+    text.split_word_bound_indices()
+        .map(|(i, s)| (i + s.len(), s))
+        .find(|(end, s)| *end > index && is_word(s))
+        .map_or(text.len(), |r| r.0)
+}
+
+/// 上一个单词的开头
+pub fn prev_word(text: &str, index: usize) -> usize {
+    text.split_word_bound_indices()
+        .rev()
+        .find(|(i, s)| *i < index && is_word(s))
+        .map_or(0, |r| r.0)
+}
+
+// 是否为单词（含字母、数字或表意文字）
+fn is_word(s: &str) -> bool {
+    s.chars().any(|c| c.is_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::layout::LineBox;
+
+    fn quad(range: Range<usize>, x: f32, width: f32, rtl: bool) -> GlyphQuad {
+        GlyphQuad { glyph_id: None, glyphs: Vec::new(), char: 'a', span: 0, range, rtl, index: 0, x, y: 0.0, width, height: 10.0 }
+    }
+
+    fn line(glyphs: Range<usize>, range: Range<usize>, y: f32) -> LineBox {
+        LineBox { glyphs, range, x: 0.0, y, width: 0.0, height: 10.0, baseline: y + 8.0 }
+    }
+
+    // "ab"在第一行， "cd"在第二行， 每个字形宽10像素
+    fn two_lines() -> TextLayout {
+        TextLayout {
+            glyphs: vec![quad(0..1, 0.0, 10.0, false), quad(1..2, 10.0, 10.0, false), quad(3..4, 0.0, 10.0, false), quad(4..5, 10.0, 10.0, false)],
+            lines: vec![line(0..2, 0..2, 0.0), line(2..4, 3..5, 10.0)],
+            width: 20.0,
+            height: 20.0,
+        }
+    }
+
+    #[test]
+    fn caret_ltr() {
+        let layout = two_lines();
+        assert_eq!(layout.caret(0), Caret { x: 0.0, y: 0.0, height: 10.0, line: 0 });
+        assert_eq!(layout.caret(1).x, 10.0);
+        assert_eq!(layout.caret(2).x, 20.0);
+        // 换行处的索引位于下一行的开头
+        assert_eq!(layout.caret(3), Caret { x: 0.0, y: 10.0, height: 10.0, line: 1 });
+        assert_eq!(layout.caret(5).x, 20.0);
+    }
+
+    #[test]
+    fn caret_rtl() {
+        // 视觉顺序为"ba"， 两个字形都从右到左
+        let layout = TextLayout {
+            glyphs: vec![quad(1..2, 0.0, 10.0, true), quad(0..1, 10.0, 10.0, true)],
+            lines: vec![line(0..2, 0..2, 0.0)],
+            width: 20.0,
+            height: 10.0,
+        };
+        assert_eq!(layout.caret(0).x, 20.0);
+        assert_eq!(layout.caret(1).x, 10.0);
+        assert_eq!(layout.caret(2).x, 0.0);
+        assert_eq!(layout.hit_test(19.0, 5.0), 0);
+        assert_eq!(layout.hit_test(1.0, 5.0), 2);
+    }
+
+    #[test]
+    fn caret_inside_ligature() {
+        // "ffi"连字， 按字节比例插值
+        let layout = TextLayout {
+            glyphs: vec![quad(0..3, 0.0, 30.0, false)],
+            lines: vec![line(0..1, 0..3, 0.0)],
+            width: 30.0,
+            height: 10.0,
+        };
+        assert_eq!(layout.caret(1).x, 10.0);
+        assert_eq!(layout.caret(2).x, 20.0);
+    }
+
+    #[test]
+    fn hit_test() {
+        let layout = two_lines();
+        assert_eq!(layout.hit_test(2.0, 5.0), 0);
+        assert_eq!(layout.hit_test(7.0, 5.0), 1);
+        assert_eq!(layout.hit_test(16.0, 5.0), 2);
+        assert_eq!(layout.hit_test(12.0, 15.0), 4);
+        // 超出范围时取行的开头或末尾、第一行或最后一行
+        assert_eq!(layout.hit_test(-5.0, 5.0), 0);
+        assert_eq!(layout.hit_test(100.0, 5.0), 2);
+        assert_eq!(layout.hit_test(5.0, 100.0), 4);
+        assert_eq!(TextLayout::default().hit_test(0.0, 0.0), 0);
+    }
+
+    #[test]
+    fn selection() {
+        let layout = two_lines();
+        let rects = layout.selection_rects(1..4);
+        assert_eq!(rects, vec![
+            Rect { x: 10.0, y: 0.0, width: 10.0, height: 10.0 },
+            Rect { x: 0.0, y: 10.0, width: 10.0, height: 10.0 },
+        ]);
+        assert!(layout.selection_rects(2..2).is_empty());
+    }
+
+    #[test]
+    fn grapheme_and_word_bounds() {
+        let text = "e\u{301}x ab, cd";
+        assert_eq!(next_grapheme(text, 0), 3);
+        assert_eq!(prev_grapheme(text, 3), 0);
+        assert_eq!(next_grapheme(text, text.len()), text.len());
+        assert_eq!(next_word(text, 0), 4);
+        assert_eq!(next_word(text, 4), 7);
+        assert_eq!(prev_word(text, 7), 5);
+        assert_eq!(prev_word(text, 5), 0);
+    }
+}
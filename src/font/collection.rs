@@ -0,0 +1,194 @@
+//! 字体集合（ttc、otc）
+//!
+//! 枚举字体集合中的字体， 按索引或PostScript名称选择字体，
+//! 以及将集合中的一个字体提取为单独的sfnt（sdf2的字体外观只能读取数据中的第一个字体）
+
+use ttf_parser::{name_id, Face};
+
+use super::woff::build_sfnt;
+
+/// 字体集合中的一个字体
+#[derive(Debug, Clone)]
+pub struct CollectionFace {
+    /// 在字体集合中的索引
+    pub index: u32,
+    /// 字体族名（含本地化名称， 第一个为英文名称或首个名称）
+    pub families: Vec<String>,
+    /// PostScript名称
+    pub post_script_name: String,
+}
+
+impl CollectionFace {
+    /// 字体的唯一名称， 用作`FontMgr::add_font_collection`注册的字体名
+    pub fn name(&self) -> String {
+        if !self.post_script_name.is_empty() {
+            return self.post_script_name.clone();
+        }
+        format!("{}-{}", self.families.first().map_or("", |r| r.as_str()), self.index)
+    }
+}
+
+/// 字体数据中的字体数量， 不是字体集合时为1
+pub fn face_count(data: &[u8]) -> u32 {
+    ttf_parser::fonts_in_collection(data).unwrap_or(1)
+}
+
+/// 字体数据中的所有字体， 不是字体集合时只有索引为0的字体
+pub fn collection_faces(data: &[u8]) -> Vec<CollectionFace> {
+    (0..face_count(data)).filter_map(|index| {
+        let face = Face::parse(data, index).ok()?;
+        let (families, post_script_name) = face_names(&face);
+        Some(CollectionFace { index, families, post_script_name })
+    }).collect()
+}
+
+/// 按PostScript名称查找字体在字体集合中的索引
+pub fn find_face(data: &[u8], post_script_name: &str) -> Option<u32> {
+    collection_faces(data).into_iter()
+        .find(|r| r.post_script_name == post_script_name)
+        .map(|r| r.index)
+}
+
+/// 从name表中取得字体族名（优先使用排版字体族名， name ID 16）及PostScript名称
+pub fn face_names(face: &Face) -> (Vec<String>, String) {
+    let mut families: Vec<String> = Vec::new();
+    let mut post_script_name = String::new();
+    let has_typographic = face.names().into_iter().any(|r| r.name_id == name_id::TYPOGRAPHIC_FAMILY);
+    let family_id = if has_typographic { name_id::TYPOGRAPHIC_FAMILY } else { name_id::FAMILY };
+    for name in face.names() {
+        if name.name_id == family_id {
+            if let Some(r) = name.to_string() {
+                if !families.contains(&r) {
+                    // 英文名称放在最前
+                    if name.language_id == 0x0409 {
+                        families.insert(0, r);
+                    } else {
+                        families.push(r);
+                    }
+                }
+            }
+        } else if name.name_id == name_id::POST_SCRIPT_NAME && post_script_name.is_empty() {
+            if let Some(r) = name.to_string() {
+                post_script_name = r;
+            }
+        }
+    }
+    (families, post_script_name)
+}
+
+/// 将字体集合中的一个字体提取为单独的sfnt， 只复制该字体引用的表
+///
+/// 不是字体集合时， 索引为0返回原数据的副本
+pub fn extract_face(data: &[u8], index: u32) -> Option<Vec<u8>> {
+    if data.get(0..4)? != b"ttcf" {
+        return (index == 0).then(|| data.to_vec());
+    }
+    if index >= read_u32(data, 8)? {
+        return None;
+    }
+    let offset = read_u32(data, 12 + 4 * index as usize)? as usize;
+    let flavor = read_u32(data, offset)?;
+    let num_tables = read_u16(data, offset + 4)? as usize;
+    let mut tables = Vec::with_capacity(num_tables);
+    for i in 0..num_tables {
+        let record = offset + 12 + 16 * i;
+        let tag: [u8; 4] = data.get(record..record + 4)?.try_into().ok()?;
+        let start = read_u32(data, record + 8)? as usize;
+        let len = read_u32(data, record + 12)? as usize;
+        tables.push((tag, data.get(start..start.checked_add(len)?)?.to_vec()));
+    }
+    Some(build_sfnt(&[(flavor, (0..num_tables).collect())], &tables, false))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|r| u16::from_be_bytes([r[0], r[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|r| u32::from_be_bytes([r[0], r[1], r[2], r[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::GlyphId;
+
+    use super::*;
+
+    const TTC: &[u8] = include_bytes!("../../tests/fonts/collection.ttc");
+    const MINI: &[u8] = include_bytes!("../../tests/fonts/mini.ttf");
+
+    fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    #[test]
+    fn enumerate_faces() {
+        assert_eq!(face_count(TTC), 3);
+        assert_eq!(face_count(MINI), 1);
+        assert_eq!(face_count(b"not a font"), 1);
+
+        let faces = collection_faces(TTC);
+        assert_eq!(faces.iter().map(|r| r.index).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(faces[0].families, vec!["Mini".to_string()]);
+        assert_eq!(faces[0].name(), "Mini-Regular");
+        // 英文名称排在最前
+        assert_eq!(faces[1].families, vec!["Mini Bold".to_string(), "迷你粗体".to_string()]);
+        assert_eq!(faces[1].name(), "Mini-Bold");
+        // 没有PostScript名称时， 使用字体族名及索引
+        assert_eq!(faces[2].post_script_name, "");
+        assert_eq!(faces[2].name(), "Mini-2");
+
+        assert_eq!(collection_faces(MINI).len(), 1);
+        assert!(collection_faces(b"not a font").is_empty());
+    }
+
+    #[test]
+    fn find_by_name() {
+        assert_eq!(find_face(TTC, "Mini-Regular"), Some(0));
+        assert_eq!(find_face(TTC, "Mini-Bold"), Some(1));
+        assert_eq!(find_face(TTC, "Mini-Italic"), None);
+    }
+
+    #[test]
+    fn extract() {
+        let data = extract_face(TTC, 1).unwrap();
+        assert_eq!(face_count(&data), 1);
+        let face = Face::parse(&data, 0).unwrap();
+        assert_eq!(face_names(&face).1, "Mini-Bold");
+        let mini = Face::parse(MINI, 0).unwrap();
+        assert_eq!(face.number_of_glyphs(), mini.number_of_glyphs());
+        assert_eq!(face.glyph_bounding_box(GlyphId(1)), mini.glyph_bounding_box(GlyphId(1)));
+
+        // 不是字体集合时， 只有索引0
+        assert_eq!(extract_face(MINI, 0).as_deref(), Some(MINI));
+        assert!(extract_face(MINI, 1).is_none());
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert!(extract_face(TTC, 3).is_none());
+        assert!(extract_face(TTC, u32::MAX).is_none());
+        assert!(extract_face(b"ttc", 0).is_none());
+
+        // 字体数量超出偏移数组
+        let mut data = TTC.to_vec();
+        write_u32(&mut data, 8, 0x1000_0000);
+        assert!(extract_face(&data, 0x0FFF_FFFF).is_none());
+
+        // 字体偏移超出数据
+        let mut data = TTC.to_vec();
+        write_u32(&mut data, 16, TTC.len() as u32);
+        assert!(extract_face(&data, 1).is_none());
+        assert!(extract_face(&data, 0).is_some());
+
+        // 表的范围超出数据
+        let mut data = TTC.to_vec();
+        let record = read_u32(TTC, 12).unwrap() as usize + 12;
+        write_u32(&mut data, record + 12, u32::MAX);
+        assert!(extract_face(&data, 0).is_none());
+
+        // 截断的表目录
+        let end = read_u32(TTC, 20).unwrap() as usize + 20;
+        assert!(extract_face(&TTC[..end], 2).is_none());
+    }
+}
@@ -0,0 +1,872 @@
+//! 彩色字形（表情符号）
+//!
+//! COLR（v0、v1）的分层轮廓在CPU上合成为RGBA位图， CBDT、sbix中内嵌的PNG位图用image解码后缩放到目标字号，
+//! 彩色字形放在单独的RGBA纹理图集（`ColorAtlas`）中， `Glyph::color`为true时，
+//! `Glyph`的纹理坐标位于彩色图集， 渲染时应直接采样颜色， 而不是距离场或覆盖率
+
+use ab_glyph_rasterizer::{point, Point, Rasterizer};
+use pi_share::Share;
+use ttf_parser::{
+    colr::{ClipBox, CompositeMode, GradientExtend, Paint, Painter},
+    Face, GlyphId as TtfGlyphId, OutlineBuilder, RasterImageFormat, RgbaColor, Transform,
+};
+
+use super::{font::{Block, FontImage, Glyph}, text_pack::TextPacker};
+
+/// 与字号无关的字体表（sdf）中， 彩色字形光栅化的字号
+pub const COLOR_FONT_SIZE: usize = 64;
+
+/// 字形四周留出的空白像素， 避免采样到相邻字形
+const PADDING: i32 = 1;
+
+/// 彩色字形的纹理图集
+///
+/// 图集中的图像为RGBA8（非预乘）， 通过`draw`取得
+pub struct ColorAtlas {
+    pub(crate) text_packer: TextPacker,
+    wait_list: Vec<ColorWait>,
+}
+
+// 等待光栅化的彩色字形
+struct ColorWait {
+    data: Share<Vec<u8>>,
+    index: u32,
+    glyph_index: u16,
+    font_size: usize,
+    glyph: Glyph,
+}
+
+impl ColorAtlas {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            text_packer: TextPacker::new(width, height),
+            wait_list: Vec::new(),
+        }
+    }
+
+    /// 清空图集， 之前分配的彩色字形全部失效
+    pub fn clear(&mut self) {
+        self.text_packer.clear();
+        self.wait_list.clear();
+    }
+
+    /// 为彩色字形在图集中分配位置， 并放入等待队列
+    ///
+    /// # 参数
+    /// - `data`、`index`: 字体数据及字体在字体集合中的索引
+    /// - `glyph_index`: 字形在字体中的索引， 需为彩色字形（见`is_color_glyph`）
+    /// - `font_size`: 光栅化的字号（像素）
+    /// - `advance`: 步进宽度（相对于字号的比例）
+    ///
+    /// # 返回值
+    /// 字形信息（`color`为true）， 纹理空间不足时返回None
+    pub fn alloc(&mut self, data: &Share<Vec<u8>>, index: u32, glyph_index: u16, font_size: usize, advance: f32) -> Option<Glyph> {
+        let face = Face::parse(data, index).ok()?;
+        let mut glyph = Glyph { advance, font_size: font_size as f32, color: true, ..Default::default() };
+        let [x_min, y_min, x_max, y_max] = match color_bounds(&face, TtfGlyphId(glyph_index)) {
+            Some(r) => r,
+            // 没有可见内容， 只需要步进宽度
+            None => return Some(glyph),
+        };
+
+        // 包围盒对齐到像素
+        let scale = font_size as f32 / face.units_per_em() as f32;
+        let min_x = (x_min * scale).floor() as i32 - PADDING;
+        let min_y = (y_min * scale).floor() as i32 - PADDING;
+        let max_x = (x_max * scale).ceil() as i32 + PADDING;
+        let max_y = (y_max * scale).ceil() as i32 + PADDING;
+        let (width, height) = ((max_x - min_x) as usize, (max_y - min_y) as usize);
+
+        let offset = self.text_packer.alloc(width, height)?;
+        glyph.plane_min_x = min_x as f32 / font_size as f32;
+        glyph.plane_min_y = min_y as f32 / font_size as f32;
+        glyph.plane_max_x = max_x as f32 / font_size as f32;
+        glyph.plane_max_y = max_y as f32 / font_size as f32;
+        glyph.x = offset.x as f32;
+        glyph.y = offset.y as f32;
+        glyph.page = offset.page;
+        glyph.width = width as f32;
+        glyph.height = height as f32;
+
+        self.wait_list.push(ColorWait { data: data.clone(), index, glyph_index, font_size, glyph: glyph.clone() });
+        Some(glyph)
+    }
+
+    /// 是否有等待光栅化的彩色字形
+    pub fn is_dirty(&self) -> bool {
+        !self.wait_list.is_empty()
+    }
+
+    /// 光栅化所有等待中的彩色字形， 每个字形通过`update`回调一次， FontImage为RGBA8（非预乘）
+    pub fn draw<F: FnMut(Block, FontImage)>(&mut self, mut update: F) {
+        for r in self.wait_list.drain(..) {
+            let face = match Face::parse(&r.data, r.index) {
+                Ok(r) => r,
+                Err(_) => continue,
+            };
+            if let Some(image) = rasterize_color(&face, r.glyph_index, &r.glyph, r.font_size) {
+                update(Block {
+                    x: r.glyph.x,
+                    y: r.glyph.y,
+                    width: r.glyph.width,
+                    height: r.glyph.height,
+                    page: r.glyph.page,
+                }, image);
+            }
+        }
+    }
+}
+
+/// 字形是否为彩色字形（COLR分层轮廓， 或CBDT、sbix中的PNG位图）
+pub fn is_color_glyph(face: &Face, glyph_index: u16) -> bool {
+    let id = TtfGlyphId(glyph_index);
+    face.is_color_glyph(id) || face.glyph_raster_image(id, u16::MAX).is_some_and(|r| is_color_format(r.format))
+}
+
+fn is_color_format(format: RasterImageFormat) -> bool {
+    matches!(format, RasterImageFormat::PNG | RasterImageFormat::BitmapPremulBgra32)
+}
+
+/// 彩色字形的包围盒， [x_min, y_min, x_max, y_max]， 字体单位
+///
+/// COLR优先使用ClipList中的裁剪框， 不存在时为所有图层轮廓包围盒的并集
+pub fn color_bounds(face: &Face, id: TtfGlyphId) -> Option<[f32; 4]> {
+    if face.is_color_glyph(id) {
+        if let Some(r) = face.tables().colr.and_then(|colr| colr.clip_box(id, face.variation_coordinates())) {
+            return Some([r.x_min, r.y_min, r.x_max, r.y_max]);
+        }
+        let mut painter = BoundsPainter { face, transforms: vec![Transform::default()], bounds: None };
+        face.paint_color_glyph(id, 0, RgbaColor::new(0, 0, 0, 255), &mut painter)?;
+        return painter.bounds;
+    }
+    let image = face.glyph_raster_image(id, u16::MAX).filter(|r| is_color_format(r.format))?;
+    // 位图的坐标以pixels_per_em为单位
+    let scale = face.units_per_em() as f32 / image.pixels_per_em as f32;
+    let (x, y) = (image.x as f32 * scale, image.y as f32 * scale);
+    Some([x, y, x + image.width as f32 * scale, y + image.height as f32 * scale])
+}
+
+/// 将彩色字形光栅化为RGBA8（非预乘）位图， 位图范围为字形在图集中分配的区域
+fn rasterize_color(face: &Face, glyph_index: u16, glyph: &Glyph, font_size: usize) -> Option<FontImage> {
+    let (width, height) = (glyph.width as usize, glyph.height as usize);
+    let canvas = Canvas {
+        width,
+        height,
+        scale: font_size as f32 / face.units_per_em() as f32,
+        origin_x: (glyph.plane_min_x * font_size as f32).round(),
+        origin_y: (glyph.plane_max_y * font_size as f32).round(),
+    };
+    let id = TtfGlyphId(glyph_index);
+    let pixels = if face.is_color_glyph(id) {
+        let mut painter = ColrPainter::new(face, canvas);
+        // 前景色（调色板索引0xFFFF）使用黑色
+        face.paint_color_glyph(id, 0, RgbaColor::new(0, 0, 0, 255), &mut painter)?;
+        painter.finish()
+    } else {
+        let image = face.glyph_raster_image(id, font_size as u16)?;
+        raster_image(face, &image, canvas)?
+    };
+
+    let mut buffer = Vec::with_capacity(width * height * 4);
+    for [r, g, b, a] in pixels {
+        // 反预乘
+        let k = if a > 0.0 { 255.0 / a } else { 0.0 };
+        buffer.extend_from_slice(&[
+            (r * k).round().clamp(0.0, 255.0) as u8,
+            (g * k).round().clamp(0.0, 255.0) as u8,
+            (b * k).round().clamp(0.0, 255.0) as u8,
+            (a * 255.0).round().clamp(0.0, 255.0) as u8,
+        ]);
+    }
+    Some(FontImage { buffer, width, height })
+}
+
+// 位图空间： 左上角为原点， y轴向下， 单位为像素
+#[derive(Debug, Clone, Copy)]
+struct Canvas {
+    width: usize,
+    height: usize,
+    // 字体单位到像素的缩放
+    scale: f32,
+    // 位图左边界、上边界（像素， y轴向上）
+    origin_x: f32,
+    origin_y: f32,
+}
+
+impl Canvas {
+    // 字体单位的坐标转换到位图空间
+    fn to_pixel(self, x: f32, y: f32) -> (f32, f32) {
+        (x * self.scale - self.origin_x, self.origin_y - y * self.scale)
+    }
+
+    // 位图空间的坐标转换到字体单位
+    fn to_font(self, x: f32, y: f32) -> (f32, f32) {
+        ((x + self.origin_x) / self.scale, (self.origin_y - y) / self.scale)
+    }
+}
+
+// 内嵌位图（PNG或预乘BGRA）缩放到位图空间， 结果为预乘的RGBA（0~1）
+fn raster_image(face: &Face, image: &ttf_parser::RasterGlyphImage, canvas: Canvas) -> Option<Vec<[f32; 4]>> {
+    let (w, h) = (image.width as usize, image.height as usize);
+    // 源位图的像素， 预乘RGBA
+    let src: Vec<[f32; 4]> = match image.format {
+        RasterImageFormat::PNG => {
+            let decoded = image::load_from_memory_with_format(image.data, image::ImageFormat::Png).ok()?.to_rgba8();
+            if decoded.width() as usize != w || decoded.height() as usize != h {
+                return None;
+            }
+            decoded.pixels().map(|p| {
+                let a = p[3] as f32 / 255.0;
+                [p[0] as f32 / 255.0 * a, p[1] as f32 / 255.0 * a, p[2] as f32 / 255.0 * a, a]
+            }).collect()
+        }
+        RasterImageFormat::BitmapPremulBgra32 => {
+            if image.data.len() < w * h * 4 {
+                return None;
+            }
+            image.data.chunks_exact(4).take(w * h).map(|p| {
+                [p[2] as f32 / 255.0, p[1] as f32 / 255.0, p[0] as f32 / 255.0, p[3] as f32 / 255.0]
+            }).collect()
+        }
+        _ => return None,
+    };
+
+    // 源位图的像素坐标（左上角为原点）与字体单位的换算
+    let unit = face.units_per_em() as f32 / image.pixels_per_em as f32;
+    let (left, top) = (image.x as f32 * unit, (image.y as f32 + image.height as f32) * unit);
+    let sample = |x: isize, y: isize| -> [f32; 4] {
+        if x < 0 || y < 0 || x as usize >= w || y as usize >= h {
+            return [0.0; 4];
+        }
+        src[y as usize * w + x as usize]
+    };
+
+    // 双线性采样
+    let mut out = vec![[0.0; 4]; canvas.width * canvas.height];
+    for py in 0..canvas.height {
+        for px in 0..canvas.width {
+            let (fx, fy) = canvas.to_font(px as f32 + 0.5, py as f32 + 0.5);
+            let sx = (fx - left) / unit - 0.5;
+            let sy = (top - fy) / unit - 0.5;
+            let (x0, y0) = (sx.floor(), sy.floor());
+            let (tx, ty) = (sx - x0, sy - y0);
+            let (x0, y0) = (x0 as isize, y0 as isize);
+            let (a, b, c, d) = (sample(x0, y0), sample(x0 + 1, y0), sample(x0, y0 + 1), sample(x0 + 1, y0 + 1));
+            let pixel = &mut out[py * canvas.width + px];
+            for k in 0..4 {
+                pixel[k] = (a[k] * (1.0 - tx) + b[k] * tx) * (1.0 - ty) + (c[k] * (1.0 - tx) + d[k] * tx) * ty;
+            }
+        }
+    }
+    Some(out)
+}
+
+// 计算COLR字形所有图层轮廓包围盒的并集
+struct BoundsPainter<'f, 'a> {
+    face: &'f Face<'a>,
+    transforms: Vec<Transform>,
+    bounds: Option<[f32; 4]>,
+}
+
+impl<'a> Painter<'a> for BoundsPainter<'_, 'a> {
+    fn outline_glyph(&mut self, glyph_id: TtfGlyphId) {
+        let mut builder = PointCollector { transform: *self.transforms.last().unwrap(), points: Vec::new() };
+        if self.face.outline_glyph(glyph_id, &mut builder).is_none() {
+            return;
+        }
+        for (x, y) in builder.points {
+            self.bounds = Some(match self.bounds {
+                Some(r) => [r[0].min(x), r[1].min(y), r[2].max(x), r[3].max(y)],
+                None => [x, y, x, y],
+            });
+        }
+    }
+
+    fn paint(&mut self, _: Paint<'a>) {}
+
+    fn push_clip(&mut self) {}
+
+    fn push_clip_box(&mut self, _: ClipBox) {}
+
+    fn pop_clip(&mut self) {}
+
+    fn push_layer(&mut self, _: CompositeMode) {}
+
+    fn pop_layer(&mut self) {}
+
+    fn push_transform(&mut self, transform: Transform) {
+        let current = *self.transforms.last().unwrap();
+        self.transforms.push(Transform::combine(current, transform));
+    }
+
+    fn pop_transform(&mut self) {
+        self.transforms.pop();
+    }
+}
+
+// 收集变换后的轮廓点（含控制点）
+struct PointCollector {
+    transform: Transform,
+    points: Vec<(f32, f32)>,
+}
+
+impl PointCollector {
+    fn push(&mut self, x: f32, y: f32) {
+        self.points.push(apply(&self.transform, x, y));
+    }
+}
+
+impl OutlineBuilder for PointCollector {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.push(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.push(x1, y1);
+        self.push(x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.push(x1, y1);
+        self.push(x2, y2);
+        self.push(x, y);
+    }
+
+    fn close(&mut self) {}
+}
+
+fn apply(t: &Transform, x: f32, y: f32) -> (f32, f32) {
+    (t.a * x + t.c * y + t.e, t.b * x + t.d * y + t.f)
+}
+
+fn invert(t: &Transform) -> Option<Transform> {
+    let det = t.a * t.d - t.b * t.c;
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let (a, b, c, d) = (t.d / det, -t.b / det, -t.c / det, t.a / det);
+    Some(Transform::new(a, b, c, d, -(a * t.e + c * t.f), -(b * t.e + d * t.f)))
+}
+
+// COLR字形合成， 图层为预乘的RGBA（0~1）
+struct ColrPainter<'f, 'a> {
+    face: &'f Face<'a>,
+    canvas: Canvas,
+    transforms: Vec<Transform>,
+    // 最近一次outline_glyph的覆盖率， 及其是否还未被push_clip使用（COLRv0的图层直接填充轮廓）
+    outline: Vec<f32>,
+    outline_pending: bool,
+    // 裁剪区域的覆盖率， 每一项都已与下一层相交
+    clips: Vec<Vec<f32>>,
+    layers: Vec<(Vec<[f32; 4]>, CompositeMode)>,
+}
+
+impl<'f, 'a> ColrPainter<'f, 'a> {
+    fn new(face: &'f Face<'a>, canvas: Canvas) -> Self {
+        let len = canvas.width * canvas.height;
+        Self {
+            face,
+            canvas,
+            transforms: vec![Transform::default()],
+            outline: vec![0.0; len],
+            outline_pending: false,
+            clips: Vec::new(),
+            layers: vec![(vec![[0.0; 4]; len], CompositeMode::SourceOver)],
+        }
+    }
+
+    fn finish(mut self) -> Vec<[f32; 4]> {
+        while self.layers.len() > 1 {
+            self.pop_layer();
+        }
+        self.layers.pop().unwrap().0
+    }
+
+    fn transform(&self) -> Transform {
+        *self.transforms.last().unwrap()
+    }
+
+    // 当前变换下的轮廓覆盖率
+    fn coverage<F: FnOnce(&mut PathRasterizer)>(&self, f: F) -> Vec<f32> {
+        let mut builder = PathRasterizer {
+            rasterizer: Rasterizer::new(self.canvas.width, self.canvas.height),
+            transform: self.transform(),
+            canvas: self.canvas,
+            start: Point::default(),
+            last: Point::default(),
+        };
+        f(&mut builder);
+        let mut r = vec![0.0; self.canvas.width * self.canvas.height];
+        builder.rasterizer.for_each_pixel(|i, alpha| r[i] = alpha.clamp(0.0, 1.0));
+        r
+    }
+
+    fn push_mask(&mut self, mut mask: Vec<f32>) {
+        if let Some(top) = self.clips.last() {
+            for (m, c) in mask.iter_mut().zip(top.iter()) {
+                *m *= c;
+            }
+        }
+        self.clips.push(mask);
+    }
+
+    // 位图空间中像素中心对应的绘制空间坐标（当前变换之前）
+    fn paint_space(&self) -> Option<impl Fn(usize, usize) -> (f32, f32) + '_> {
+        let inverse = invert(&self.transform())?;
+        Some(move |x: usize, y: usize| {
+            let (fx, fy) = self.canvas.to_font(x as f32 + 0.5, y as f32 + 0.5);
+            apply(&inverse, fx, fy)
+        })
+    }
+}
+
+impl<'a> Painter<'a> for ColrPainter<'_, 'a> {
+    fn outline_glyph(&mut self, glyph_id: TtfGlyphId) {
+        let face = self.face;
+        self.outline = self.coverage(|builder| {
+            face.outline_glyph(glyph_id, builder);
+        });
+        self.outline_pending = true;
+    }
+
+    fn paint(&mut self, paint: Paint<'a>) {
+        let width = self.canvas.width;
+        let len = width * self.canvas.height;
+        // 填充区域： 裁剪区域， COLRv0时再与图层轮廓相交
+        let mut coverage = match self.clips.last() {
+            Some(r) => r.clone(),
+            None => vec![1.0; len],
+        };
+        if self.outline_pending {
+            for (c, o) in coverage.iter_mut().zip(self.outline.iter()) {
+                *c *= o;
+            }
+        }
+
+        // 渐变色标使用默认实例（ttf_parser要求坐标与字体数据同生命周期）
+        let color_at: Box<dyn Fn(usize) -> [f32; 4]> = match paint {
+            Paint::Solid(color) => {
+                let c = premultiply(color);
+                Box::new(move |_| c)
+            }
+            Paint::LinearGradient(g) => {
+                let stops = ColorStops::new(g.stops(0, &[]).map(|r| (r.stop_offset, r.color)), g.extend);
+                let (p0, p1, p2) = ((g.x0, g.y0), (g.x1, g.y1), (g.x2, g.y2));
+                // 色带方向垂直于p0p2， p3为p1在该方向上的投影
+                let (dx, dy) = (p2.0 - p0.0, p2.1 - p0.1);
+                let (nx, ny) = (dy, -dx);
+                let n2 = nx * nx + ny * ny;
+                let p3 = if n2 > 0.0 {
+                    let k = ((p1.0 - p0.0) * nx + (p1.1 - p0.1) * ny) / n2;
+                    (p0.0 + nx * k, p0.1 + ny * k)
+                } else {
+                    p1
+                };
+                let (vx, vy) = (p3.0 - p0.0, p3.1 - p0.1);
+                let v2 = vx * vx + vy * vy;
+                let space = match self.paint_space() {
+                    Some(r) => r,
+                    None => return,
+                };
+                let colors = (0..len).map(|i| {
+                    let (x, y) = space(i % width, i / width);
+                    let t = if v2 > 0.0 { ((x - p0.0) * vx + (y - p0.1) * vy) / v2 } else { 0.0 };
+                    stops.at(t)
+                }).collect::<Vec<_>>();
+                Box::new(move |i| colors[i])
+            }
+            Paint::RadialGradient(g) => {
+                let stops = ColorStops::new(g.stops(0, &[]).map(|r| (r.stop_offset, r.color)), g.extend);
+                let (c0, r0, c1, r1) = ((g.x0, g.y0), g.r0, (g.x1, g.y1), g.r1);
+                let space = match self.paint_space() {
+                    Some(r) => r,
+                    None => return,
+                };
+                let colors = (0..len).map(|i| {
+                    let (x, y) = space(i % width, i / width);
+                    match radial_t(c0, r0, c1, r1, (x, y)) {
+                        Some(t) => stops.at(t),
+                        None => [0.0; 4],
+                    }
+                }).collect::<Vec<_>>();
+                Box::new(move |i| colors[i])
+            }
+            Paint::SweepGradient(g) => {
+                let stops = ColorStops::new(g.stops(0, &[]).map(|r| (r.stop_offset, r.color)), g.extend);
+                // 角度以180度为单位， 逆时针
+                let (start, end) = (g.start_angle * 180.0, g.end_angle * 180.0);
+                let (cx, cy) = (g.center_x, g.center_y);
+                let space = match self.paint_space() {
+                    Some(r) => r,
+                    None => return,
+                };
+                let colors = (0..len).map(|i| {
+                    let (x, y) = space(i % width, i / width);
+                    let angle = (y - cy).atan2(x - cx).to_degrees().rem_euclid(360.0);
+                    if end == start {
+                        return [0.0; 4];
+                    }
+                    stops.at((angle - start) / (end - start))
+                }).collect::<Vec<_>>();
+                Box::new(move |i| colors[i])
+            }
+        };
+
+        let layer = &mut self.layers.last_mut().unwrap().0;
+        for (i, (dst, c)) in layer.iter_mut().zip(coverage.iter()).enumerate() {
+            if *c <= 0.0 {
+                continue;
+            }
+            let src = color_at(i).map(|v| v * c);
+            *dst = composite(CompositeMode::SourceOver, src, *dst);
+        }
+    }
+
+    fn push_clip(&mut self) {
+        self.outline_pending = false;
+        let mask = self.outline.clone();
+        self.push_mask(mask);
+    }
+
+    fn push_clip_box(&mut self, clipbox: ClipBox) {
+        let mask = self.coverage(|builder| {
+            builder.move_to(clipbox.x_min, clipbox.y_min);
+            builder.line_to(clipbox.x_max, clipbox.y_min);
+            builder.line_to(clipbox.x_max, clipbox.y_max);
+            builder.line_to(clipbox.x_min, clipbox.y_max);
+            builder.close();
+        });
+        self.push_mask(mask);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clips.pop();
+    }
+
+    fn push_layer(&mut self, mode: CompositeMode) {
+        let len = self.canvas.width * self.canvas.height;
+        self.layers.push((vec![[0.0; 4]; len], mode));
+    }
+
+    fn pop_layer(&mut self) {
+        if self.layers.len() < 2 {
+            return;
+        }
+        let (src, mode) = self.layers.pop().unwrap();
+        let dst = &mut self.layers.last_mut().unwrap().0;
+        for (d, s) in dst.iter_mut().zip(src) {
+            *d = composite(mode, s, *d);
+        }
+    }
+
+    fn push_transform(&mut self, transform: Transform) {
+        let current = self.transform();
+        self.transforms.push(Transform::combine(current, transform));
+    }
+
+    fn pop_transform(&mut self) {
+        if self.transforms.len() > 1 {
+            self.transforms.pop();
+        }
+    }
+}
+
+// 将字体单位的轮廓经变换后光栅化为覆盖率
+struct PathRasterizer {
+    rasterizer: Rasterizer,
+    transform: Transform,
+    canvas: Canvas,
+    start: Point,
+    last: Point,
+}
+
+impl PathRasterizer {
+    fn point(&self, x: f32, y: f32) -> Point {
+        let (x, y) = apply(&self.transform, x, y);
+        let (x, y) = self.canvas.to_pixel(x, y);
+        point(x, y)
+    }
+}
+
+impl OutlineBuilder for PathRasterizer {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = self.point(x, y);
+        self.last = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.rasterizer.draw_line(self.last, p);
+        self.last = p;
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (p1, p) = (self.point(x1, y1), self.point(x, y));
+        self.rasterizer.draw_quad(self.last, p1, p);
+        self.last = p;
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (p1, p2, p) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.rasterizer.draw_cubic(self.last, p1, p2, p);
+        self.last = p;
+    }
+
+    fn close(&mut self) {
+        if self.last != self.start {
+            self.rasterizer.draw_line(self.last, self.start);
+        }
+        self.last = self.start;
+    }
+}
+
+// 渐变的色标， 按位置排序， 颜色为预乘的RGBA（0~1）
+struct ColorStops {
+    stops: Vec<(f32, [f32; 4])>,
+    extend: GradientExtend,
+}
+
+impl ColorStops {
+    fn new<I: Iterator<Item = (f32, RgbaColor)>>(stops: I, extend: GradientExtend) -> Self {
+        let mut stops = stops.map(|(t, c)| (t, premultiply(c))).collect::<Vec<_>>();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { stops, extend }
+    }
+
+    fn at(&self, t: f32) -> [f32; 4] {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return [0.0; 4],
+        };
+        // 色标范围之外按扩展方式映射
+        let (t0, t1) = (first.0, last.0);
+        let t = if t1 > t0 {
+            let u = (t - t0) / (t1 - t0);
+            let u = match self.extend {
+                GradientExtend::Pad => u.clamp(0.0, 1.0),
+                GradientExtend::Repeat => u - u.floor(),
+                GradientExtend::Reflect => {
+                    let r = u.rem_euclid(2.0);
+                    if r > 1.0 { 2.0 - r } else { r }
+                }
+            };
+            t0 + u * (t1 - t0)
+        } else {
+            t0
+        };
+
+        if t <= t0 {
+            return first.1;
+        }
+        for w in self.stops.windows(2) {
+            let ((a, ca), (b, cb)) = (w[0], w[1]);
+            if t <= b {
+                if b <= a {
+                    return cb;
+                }
+                let k = (t - a) / (b - a);
+                return [0, 1, 2, 3].map(|i| ca[i] + (cb[i] - ca[i]) * k);
+            }
+        }
+        last.1
+    }
+}
+
+// 双圆径向渐变中， 点p所在的圆的t（取半径非负的最大解）
+fn radial_t(c0: (f32, f32), r0: f32, c1: (f32, f32), r1: f32, p: (f32, f32)) -> Option<f32> {
+    let (cdx, cdy) = (c1.0 - c0.0, c1.1 - c0.1);
+    let (pdx, pdy) = (p.0 - c0.0, p.1 - c0.1);
+    let dr = r1 - r0;
+    let a = cdx * cdx + cdy * cdy - dr * dr;
+    let b = pdx * cdx + pdy * cdy + r0 * dr;
+    let c = pdx * pdx + pdy * pdy - r0 * r0;
+    let valid = |t: f32| r0 + t * dr >= 0.0;
+    if a.abs() < 1e-6 {
+        if b.abs() < 1e-6 {
+            return None;
+        }
+        let t = c / (2.0 * b);
+        return valid(t).then_some(t);
+    }
+    let disc = b * b - a * c;
+    if disc < 0.0 {
+        return None;
+    }
+    let sqrt = disc.sqrt();
+    let (t1, t2) = ((b + sqrt) / a, (b - sqrt) / a);
+    let (hi, lo) = if t1 > t2 { (t1, t2) } else { (t2, t1) };
+    if valid(hi) {
+        Some(hi)
+    } else if valid(lo) {
+        Some(lo)
+    } else {
+        None
+    }
+}
+
+fn premultiply(c: RgbaColor) -> [f32; 4] {
+    let a = c.alpha as f32 / 255.0;
+    [c.red as f32 / 255.0 * a, c.green as f32 / 255.0 * a, c.blue as f32 / 255.0 * a, a]
+}
+
+// 预乘颜色的合成（Porter-Duff及可分离的混合模式）， 不可分离的混合模式（色相、饱和度、颜色、亮度）按SourceOver处理
+fn composite(mode: CompositeMode, s: [f32; 4], d: [f32; 4]) -> [f32; 4] {
+    let (sa, da) = (s[3], d[3]);
+    let porter_duff = |fs: f32, fd: f32| [0, 1, 2, 3].map(|i| s[i] * fs + d[i] * fd);
+    let blend = |f: fn(f32, f32) -> f32| {
+        let mut r = [0.0; 4];
+        for i in 0..3 {
+            let cs = if sa > 0.0 { s[i] / sa } else { 0.0 };
+            let cd = if da > 0.0 { d[i] / da } else { 0.0 };
+            r[i] = s[i] * (1.0 - da) + d[i] * (1.0 - sa) + sa * da * f(cs, cd);
+        }
+        r[3] = sa + da - sa * da;
+        r
+    };
+    match mode {
+        CompositeMode::Clear => [0.0; 4],
+        CompositeMode::Source => s,
+        CompositeMode::Destination => d,
+        CompositeMode::DestinationOver => porter_duff(1.0 - da, 1.0),
+        CompositeMode::SourceIn => porter_duff(da, 0.0),
+        CompositeMode::DestinationIn => porter_duff(0.0, sa),
+        CompositeMode::SourceOut => porter_duff(1.0 - da, 0.0),
+        CompositeMode::DestinationOut => porter_duff(0.0, 1.0 - sa),
+        CompositeMode::SourceAtop => porter_duff(da, 1.0 - sa),
+        CompositeMode::DestinationAtop => porter_duff(1.0 - da, sa),
+        CompositeMode::Xor => porter_duff(1.0 - da, 1.0 - sa),
+        CompositeMode::Plus => [0, 1, 2, 3].map(|i| (s[i] + d[i]).min(1.0)),
+        CompositeMode::Screen => blend(|s, d| s + d - s * d),
+        CompositeMode::Overlay => blend(|s, d| hard_light(d, s)),
+        CompositeMode::Darken => blend(f32::min),
+        CompositeMode::Lighten => blend(f32::max),
+        CompositeMode::ColorDodge => blend(|s, d| if d <= 0.0 { 0.0 } else if s >= 1.0 { 1.0 } else { (d / (1.0 - s)).min(1.0) }),
+        CompositeMode::ColorBurn => blend(|s, d| if d >= 1.0 { 1.0 } else if s <= 0.0 { 0.0 } else { 1.0 - ((1.0 - d) / s).min(1.0) }),
+        CompositeMode::HardLight => blend(hard_light),
+        CompositeMode::SoftLight => blend(|s, d| {
+            if s <= 0.5 {
+                d - (1.0 - 2.0 * s) * d * (1.0 - d)
+            } else {
+                let g = if d <= 0.25 { ((16.0 * d - 12.0) * d + 4.0) * d } else { d.sqrt() };
+                d + (2.0 * s - 1.0) * (g - d)
+            }
+        }),
+        CompositeMode::Difference => blend(|s, d| (s - d).abs()),
+        CompositeMode::Exclusion => blend(|s, d| s + d - 2.0 * s * d),
+        CompositeMode::Multiply => blend(|s, d| s * d),
+        CompositeMode::SourceOver
+        | CompositeMode::Hue
+        | CompositeMode::Saturation
+        | CompositeMode::Color
+        | CompositeMode::Luminosity => porter_duff(1.0, 1.0 - sa),
+    }
+}
+
+fn hard_light(s: f32, d: f32) -> f32 {
+    if s <= 0.5 {
+        d * 2.0 * s
+    } else {
+        let s = 2.0 * s - 1.0;
+        s + d - s * d
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR: &[u8] = include_bytes!("../../tests/fonts/color.ttf");
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    // 字体单位的坐标所在像素的颜色
+    fn pixel(image: &FontImage, glyph: &Glyph, x: f32, y: f32) -> [u8; 4] {
+        let scale = glyph.font_size / 1000.0;
+        let px = (x * scale - glyph.plane_min_x * glyph.font_size).floor() as usize;
+        let py = (glyph.plane_max_y * glyph.font_size - y * scale).floor() as usize;
+        let i = (py * image.width + px) * 4;
+        image.buffer[i..i + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn color_glyph() {
+        let face = Face::parse(COLOR, 0).unwrap();
+        assert!(is_color_glyph(&face, 1));
+        assert!(is_color_glyph(&face, 4));
+        assert!(!is_color_glyph(&face, 3));
+        // 图层轮廓包围盒的并集（A的控制点位于包围盒内）
+        assert_eq!(color_bounds(&face, TtfGlyphId(1)), Some([20.0, 0.0, 580.0, 700.0]));
+        assert_eq!(color_bounds(&face, TtfGlyphId(3)), None);
+    }
+
+    #[test]
+    fn composite_layers() {
+        let data = Share::new(COLOR.to_vec());
+        let face = Face::parse(COLOR, 0).unwrap();
+        let mut atlas = ColorAtlas::new(256, 256);
+        let glyph = atlas.alloc(&data, 0, 1, 100, 0.6).unwrap();
+        assert!(glyph.color);
+        // 包围盒2..58 x 0..70像素， 四周各留出PADDING
+        assert_eq!((glyph.width, glyph.height), (58.0, 72.0));
+
+        let mut images = Vec::new();
+        atlas.draw(|block, image| images.push((block, image)));
+        assert!(!atlas.is_dirty());
+        assert_eq!(images.len(), 1);
+        let (block, image) = &images[0];
+        assert_eq!((block.width, block.height), (glyph.width, glyph.height));
+        assert_eq!((image.width, image.height), (58, 72));
+        assert_eq!(image.buffer, rasterize_color(&face, 1, &glyph, 100).unwrap().buffer);
+
+        // 只有.notdef方框
+        assert_eq!(pixel(image, &glyph, 75.0, 350.0), BLUE);
+        // 只有A（位于方框的空洞中）
+        assert_eq!(pixel(image, &glyph, 250.0, 400.0), RED);
+        assert_eq!(pixel(image, &glyph, 520.0, 100.0), RED);
+        // 上层的方框覆盖A
+        assert_eq!(pixel(image, &glyph, 425.0, 300.0), BLUE);
+        // A底部曲线下方、方框外， 及包围盒外
+        assert_eq!(pixel(image, &glyph, 500.0, 20.0)[3], 0);
+        assert_eq!(pixel(image, &glyph, 15.0, 650.0)[3], 0);
+    }
+
+    #[test]
+    fn composite_translucent_layer() {
+        let face = Face::parse(COLOR, 0).unwrap();
+        let data = Share::new(COLOR.to_vec());
+        let glyph = ColorAtlas::new(256, 256).alloc(&data, 0, 4, 100, 0.32).unwrap();
+        let image = rasterize_color(&face, 4, &glyph, 100).unwrap();
+        // 半透明的蓝色叠加在红色上
+        assert_eq!(pixel(&image, &glyph, 425.0, 300.0), [127, 0, 128, 255]);
+        assert_eq!(pixel(&image, &glyph, 75.0, 350.0), [0, 0, 255, 128]);
+        assert_eq!(pixel(&image, &glyph, 250.0, 400.0), RED);
+    }
+
+    #[test]
+    fn not_color_glyph() {
+        let data = Share::new(COLOR.to_vec());
+        let mut atlas = ColorAtlas::new(256, 256);
+        // 没有可见内容时不分配图集空间
+        let glyph = atlas.alloc(&data, 0, 3, 100, 0.32).unwrap();
+        assert_eq!((glyph.width, glyph.height, glyph.advance), (0.0, 0.0, 0.32));
+        assert!(!atlas.is_dirty());
+        assert!(atlas.alloc(&data, 1, 1, 100, 0.6).is_none());
+    }
+
+    #[test]
+    fn composite_modes() {
+        let (s, d) = ([0.0, 0.0, 0.5, 0.5], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(composite(CompositeMode::SourceOver, s, d), [0.5, 0.0, 0.5, 1.0]);
+        assert_eq!(composite(CompositeMode::DestinationOver, s, d), d);
+        assert_eq!(composite(CompositeMode::SourceIn, s, d), s);
+        assert_eq!(composite(CompositeMode::DestinationOut, s, d), [0.5, 0.0, 0.0, 0.5]);
+        assert_eq!(composite(CompositeMode::Clear, s, d), [0.0; 4]);
+        assert_eq!(composite(CompositeMode::Plus, d, d), d);
+        assert_eq!(composite(CompositeMode::Multiply, [0.5, 0.5, 0.5, 1.0], [1.0, 0.5, 0.0, 1.0]), [0.5, 0.25, 0.0, 1.0]);
+    }
+}
@@ -0,0 +1,216 @@
+//! 后备字体
+//!
+//! 字体的字体列表由请求的字体族、按文字（Script）配置的后备字体族及默认字体组成，
+//! 每个字形簇按其文字确定尝试字体的顺序： 请求的字体族 -> 该文字的后备字体族 -> 其余字体（其他文字的后备字体族、默认字体），
+//! 使中日韩文字、拉丁文字、表情、泰文等混排时， 各自从合适的字体中取得字形
+
+use std::ops::Range;
+
+use pi_atom::Atom;
+use smallvec::SmallVec;
+pub use unicode_script::Script;
+use unicode_script::UnicodeScript;
+
+/// 后备字体链的键
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontScript {
+    /// Unicode文字（如Han、Latin、Thai）
+    Script(Script),
+    /// 表情符号（其Unicode文字为Common， 单独配置）
+    Emoji,
+}
+
+/// 字符所属的文字
+///
+/// 通用字符（标点、数字、空白等）及继承字符（组合符号、变体选择符、零宽连接符）返回None， 跟随相邻字符
+pub fn font_script(c: char) -> Option<FontScript> {
+    if is_emoji(c) {
+        return Some(FontScript::Emoji);
+    }
+    match c.script() {
+        Script::Common | Script::Inherited | Script::Unknown => None,
+        r => Some(FontScript::Script(r)),
+    }
+}
+
+// 是否为表情符号（近似， 按表情符号所在的主要区块判断）
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32, 0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF)
+}
+
+/// 将文本的一段劈分为文字相同的连续段
+///
+/// # 返回值
+/// 每段的字节范围及其文字， 整段都是通用字符时文字为None
+pub fn script_runs(text: &str, range: Range<usize>) -> Vec<(Range<usize>, Option<FontScript>)> {
+    let mut runs: Vec<(Range<usize>, Option<FontScript>)> = Vec::new();
+    for (i, c) in text[range.clone()].char_indices() {
+        let (start, end) = (range.start + i, range.start + i + c.len_utf8());
+        let script = font_script(c);
+        match runs.last_mut() {
+            Some(r) if script.is_none() || r.1 == script => r.0.end = end,
+            // 段首的通用字符跟随其后的文字
+            Some(r) if r.1.is_none() => {
+                r.0.end = end;
+                r.1 = script;
+            }
+            _ => runs.push((start..end, script)),
+        }
+    }
+    runs
+}
+
+/// 按文字配置的后备字体族
+#[derive(Debug, Clone, Default)]
+pub struct FontFallback {
+    chains: Vec<(FontScript, SmallVec<[Atom; 2]>)>,
+}
+
+impl FontFallback {
+    /// 设置文字的后备字体族（按优先级排列）， 为空时删除
+    pub fn set(&mut self, script: FontScript, families: &[Atom]) {
+        self.chains.retain(|r| r.0 != script);
+        if !families.is_empty() {
+            self.chains.push((script, families.iter().cloned().collect()));
+        }
+    }
+
+    /// 文字的后备字体族
+    pub fn get(&self, script: FontScript) -> &[Atom] {
+        self.chains.iter().find(|r| r.0 == script).map_or(&[], |r| r.1.as_slice())
+    }
+
+    /// 所有后备字体族（去重， 按配置顺序）
+    pub fn families(&self) -> SmallVec<[Atom; 4]> {
+        let mut r: SmallVec<[Atom; 4]> = SmallVec::new();
+        for family in self.chains.iter().flat_map(|r| r.1.iter()) {
+            if !r.contains(family) {
+                r.push(family.clone());
+            }
+        }
+        r
+    }
+
+    /// 为字体列表创建每种文字的尝试顺序
+    ///
+    /// # 参数
+    /// - `font_family`: 字体的完整字体列表
+    /// - `primary`: 请求的字体族的数量（位于字体列表开头）
+    pub fn face_fallback(&self, font_family: &[Atom], primary: usize) -> FaceFallback {
+        let scripts = self.chains.iter().map(|(script, families)| {
+            let indexs = families.iter()
+                .filter_map(|f| font_family.iter().position(|r| r == f))
+                .filter(|i| *i >= primary)
+                .collect();
+            (*script, indexs)
+        }).collect();
+        FaceFallback { primary, scripts }
+    }
+}
+
+/// 一个字体的字体列表中， 每种文字尝试字体的顺序
+#[derive(Debug, Clone, Default)]
+pub struct FaceFallback {
+    primary: usize,
+    scripts: Vec<(FontScript, SmallVec<[usize; 2]>)>,
+}
+
+impl FaceFallback {
+    /// 字符尝试字体的顺序（字体列表中的索引）
+    ///
+    /// # 参数
+    /// - `len`: 字体列表的长度
+    pub fn order(&self, c: char, len: usize) -> SmallVec<[usize; 8]> {
+        self.order_of_script(font_script(c), len)
+    }
+
+    /// 文字尝试字体的顺序（字体列表中的索引）， 文字为None时按字体列表顺序
+    pub fn order_of_script(&self, script: Option<FontScript>, len: usize) -> SmallVec<[usize; 8]> {
+        let primary = self.primary.min(len);
+        let mut r: SmallVec<[usize; 8]> = (0..primary).collect();
+        if let Some(chain) = script.and_then(|s| self.scripts.iter().find(|r| r.0 == s)) {
+            r.extend(chain.1.iter().copied().filter(|i| *i < len));
+        }
+        for i in primary..len {
+            if !r.contains(&i) {
+                r.push(i);
+            }
+        }
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atoms(names: &[&str]) -> Vec<Atom> {
+        names.iter().map(|r| Atom::from(*r)).collect()
+    }
+
+    #[test]
+    fn script_of_char() {
+        assert_eq!(font_script('a'), Some(FontScript::Script(Script::Latin)));
+        assert_eq!(font_script('中'), Some(FontScript::Script(Script::Han)));
+        assert_eq!(font_script('😀'), Some(FontScript::Emoji));
+        assert_eq!(font_script('🇨'), Some(FontScript::Emoji));
+        assert_eq!(font_script('1'), None);
+        assert_eq!(font_script(' '), None);
+        assert_eq!(font_script('\u{301}'), None);
+    }
+
+    #[test]
+    fn split_script_runs() {
+        let text = "1中文, ab😀";
+        let han = Some(FontScript::Script(Script::Han));
+        let latin = Some(FontScript::Script(Script::Latin));
+        // 段首的数字跟随其后的汉字， 汉字后的标点、空白留在汉字段
+        assert_eq!(script_runs(text, 0..text.len()), vec![
+            (0..9, han),
+            (9..11, latin),
+            (11..15, Some(FontScript::Emoji)),
+        ]);
+        assert_eq!(script_runs(text, 7..11), vec![(7..11, latin)]);
+        assert_eq!(script_runs("12 ", 0..3), vec![(0..3, None)]);
+        assert!(script_runs(text, 4..4).is_empty());
+    }
+
+    #[test]
+    fn set_get_families() {
+        let han = FontScript::Script(Script::Han);
+        let mut fallback = FontFallback::default();
+        fallback.set(han, &atoms(&["cjk", "emoji"]));
+        fallback.set(FontScript::Emoji, &atoms(&["emoji"]));
+        assert_eq!(fallback.get(han), atoms(&["cjk", "emoji"]).as_slice());
+        assert!(fallback.get(FontScript::Script(Script::Thai)).is_empty());
+        assert_eq!(fallback.families().as_slice(), atoms(&["cjk", "emoji"]).as_slice());
+
+        fallback.set(han, &atoms(&["cjk2"]));
+        assert_eq!(fallback.get(han), atoms(&["cjk2"]).as_slice());
+        fallback.set(han, &[]);
+        assert!(fallback.get(han).is_empty());
+        assert_eq!(fallback.families().as_slice(), atoms(&["emoji"]).as_slice());
+    }
+
+    #[test]
+    fn resolve_chain() {
+        let mut fallback = FontFallback::default();
+        // "main"为请求的字体族， 不重复出现在后备链中； "missing"不在字体列表中
+        fallback.set(FontScript::Script(Script::Han), &atoms(&["missing", "cjk", "main"]));
+        fallback.set(FontScript::Emoji, &atoms(&["emoji", "cjk"]));
+        let family = atoms(&["main", "cjk", "latin", "emoji", "default"]);
+        let face = fallback.face_fallback(&family, 1);
+
+        assert_eq!(face.order('中', 5).as_slice(), &[0, 1, 2, 3, 4]);
+        assert_eq!(face.order('😀', 5).as_slice(), &[0, 3, 1, 2, 4]);
+        assert_eq!(face.order('a', 5).as_slice(), &[0, 1, 2, 3, 4]);
+        assert_eq!(face.order_of_script(None, 5).as_slice(), &[0, 1, 2, 3, 4]);
+        // 字体列表变短时， 越界的索引被忽略
+        assert_eq!(face.order('😀', 3).as_slice(), &[0, 1, 2]);
+        assert_eq!(face.order('😀', 0).as_slice(), &[] as &[usize]);
+
+        // 没有请求的字体族时， 后备链位于最前
+        let face = fallback.face_fallback(&family, 0);
+        assert_eq!(face.order('😀', 5).as_slice(), &[3, 1, 0, 2, 4]);
+    }
+}
@@ -26,7 +26,9 @@ use pi_null::Null;
 use pi_atom::Atom;
 use smallvec::SmallVec;
 
-use super::{sdf_table::{FontCfg, MetricsInfo}, tables::FontTable, text_split::{SplitChar, SplitChar2}};
+#[cfg(not(target_arch = "wasm32"))]
+use super::font_db::{stretch_class, FontDb};
+use super::{collection::{collection_faces, find_face}, fallback::{FaceFallback, FontFallback, FontScript}, layout::{LayoutStyle, TextLayout, TextSpan}, line_break::LineBreakStrictness, sdf_table::{FontCfg, MetricsInfo}, shape::ShapedText, synth::FaceStyle, tables::FontTable, text_pack::PackerStats, text_split::{SplitChar, SplitChar2}, woff::to_sfnt};
 
 /// 通用尺寸结构体
 /// 
@@ -52,6 +54,7 @@ pub struct Block {
 	pub x: f32,    // 矩形左侧X坐标 
 	pub width: f32, // 矩形宽度
 	pub height: f32, // 矩形高度
+	pub page: usize, // 所在纹理页
 }
 
 pub struct FontImage {
@@ -80,6 +83,15 @@ impl FontImage {
 	}
 }
 
+/// 字体样式
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FontStyle {
+	#[default]
+	Normal,
+	Italic,
+	Oblique,
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct Font {
 	pub font_family: SmallVec<[Atom; 1]>,
@@ -90,6 +102,9 @@ pub struct Font {
 	// pub shadow: Option<(NotNan<f32>,  NotNan<f32>)>,
 	pub font_weight: usize,
 	pub stroke: NotNan<f32>,
+	pub font_style: FontStyle,
+	// 宽度（百分比， 100为正常）
+	pub font_stretch: usize,
 }
 
 impl Font {
@@ -106,6 +121,8 @@ impl Font {
 			font_type: FontType::Bitmap,
 			font_weight,
 			stroke,
+			font_style: FontStyle::Normal,
+			font_stretch: 100,
 			// is_outer_glow,
 			// shadow
 		}
@@ -197,6 +214,11 @@ pub struct GlyphSheet {
 	default_sdf_char: Vec<(Atom, char)>,
 	// 默认字体
 	default_font: Option<Atom>, 
+	// 按文字配置的后备字体族
+	fallback: FontFallback,
+	// 系统字体库
+	#[cfg(not(target_arch = "wasm32"))]
+	font_db: Option<FontDb>,
 
 }
 
@@ -238,6 +260,9 @@ impl FontMgr {
 				// size: Size {width, height},
 				default_sdf_char: Vec::default(),
 				default_font: None,
+				fallback: FontFallback::default(),
+				#[cfg(not(target_arch = "wasm32"))]
+				font_db: None,
 			},
 			table: FontTable::new(width, height, device, queue ),
 			font_type,
@@ -245,10 +270,40 @@ impl FontMgr {
 	}
 
 	/// 获取当前纹理图集尺寸
+	/// 
+	/// 纹理空间不足时图集会成倍增长（所有页尺寸相同），外部纹理尺寸与此不一致时需要重建纹理，并保留原有内容
 	pub fn size(&self) -> Size<usize> {
 		self.table.size(self.font_type)
 	}
 
+	/// 获取当前纹理图集的页数
+	/// 
+	/// 图集增长到上限后会增加新页，外部需要为每页准备一张纹理，更新回调中的`Block::page`指明要写入的页
+	pub fn page_count(&self) -> usize {
+		self.table.page_count(self.font_type)
+	}
+
+	/// 纹理图集的尺寸或页数是否发生了变化（取出后重置）
+	/// 
+	/// 分配字形时图集可能增长或增加新页，外部应在每次绘制前检查，返回true时按`size`、`page_count`重建纹理并保留原有内容
+	pub fn take_atlas_resized(&mut self) -> bool {
+		self.table.take_atlas_resized(self.font_type)
+	}
+
+	/// 设置纹理图集的增长上限
+	/// 
+	/// # 参数
+	/// - `max_size`: 单页纹理宽高上限，默认为设备的max_texture_dimension_2d
+	/// - `max_pages`: 页数上限，默认为`DEFAULT_MAX_PAGES`
+	pub fn set_atlas_limit(&mut self, max_size: usize, max_pages: usize) {
+		self.table.set_atlas_limit(max_size, max_pages);
+	}
+
+	/// 获取当前纹理图集的占用统计
+	pub fn atlas_stats(&self) -> PackerStats {
+		self.table.stats(self.font_type)
+	}
+
 	/// 设置当前字体渲染模式
 	pub fn set_font_type(&mut self, font_type: FontType) {
 		self.font_type = font_type
@@ -258,6 +313,13 @@ impl FontMgr {
 	pub fn font_type(&self) -> FontType {
 		self.font_type
 	}
+
+	/// 获取纹理图集代数
+	/// 
+	/// 每次`clear`后代数加1，外部缓存的GlyphId需要重新请求
+	pub fn generation(&self) -> usize {
+		self.table.generation()
+	}
 }
 
 impl FontMgr {
@@ -270,7 +332,34 @@ impl FontMgr {
 	/// 唯一标识该字体配置的FontId
 	pub fn font_id(&mut self, mut f: Font) -> FontId {
 		f.font_type = self.font_type;
+		// 已创建的字体直接复用， 不再重新解析字体族（系统字体库查询只在创建时进行）
+		let font_id = match self.sheet.fonts_map.get(&f) {
+			Some(r) => r.clone(),
+			None => self.insert_font(f),
+		};
+
+		let font_info = &mut self.sheet.fonts[font_id.0];
+
+		self.table.check_or_create_face(font_info, self.font_type);
+
+		let (height, max_height) = self.table.height(font_id, font_info, self.font_type);
+		font_info.height = height;
+		font_info.max_height = max_height;
+
+		font_id
+	}
+
+	// 创建新字体： 补全后备字体、默认字体， 解析系统字体， 并插入字体表
+	fn insert_font(&mut self, f: Font) -> FontId {
 		let mut font_family = f.font_family.clone();
+		let primary = font_family.len();
+
+		// 插入后备字体
+		for r in self.sheet.fallback.families() {
+			if !font_family.contains(&r) {
+				font_family.push(r);
+			}
+		}
 
 		// 插入默认字体
 		if let Some(d) = &self.default_font {
@@ -286,24 +375,74 @@ impl FontMgr {
 			}
 		}
 
+		// 未添加数据的字体族， 从系统字体库中解析
+		#[cfg(not(target_arch = "wasm32"))]
+		let font_family = self.resolve_system_fonts(font_family, &f);
+
 		let font_face_ids = font_family.iter().map(|r| {
 			self.create_font_face(r)
 		}).collect::<SmallVec<[FontFaceId; 1]>>();
 		let font_family_id = self.font_family_id(&font_family);
+		let fallback = self.sheet.fallback.face_fallback(&font_family, primary);
 		
-		let font_id = self.get_or_insert_font(f.clone(), font_face_ids.clone(), font_family_id);
-
-		// println!("aaa========={:?}", (font_id, f, font_family_id, &font_face_ids));
-
-		let font_info = &mut self.sheet.fonts[font_id.0];
+		self.get_or_insert_font(f, font_face_ids, font_family_id, fallback)
+	}
 
-		self.table.check_or_create_face(font_info, self.font_type);
+	/// 设置文字的后备字体族
+	/// 
+	/// 字符在请求的字体族中都不存在时， 按其文字（如Han、Thai、表情）依次尝试这些字体族， 最后尝试默认字体
+	/// 只对之后创建的字体（`font_id`）生效， 需在创建字体前设置
+	/// 
+	/// # 参数
+	/// - `script`: 文字
+	/// - `families`: 按优先级排列的字体族， 为空时删除该文字的后备字体族
+	pub fn set_fallback(&mut self, script: FontScript, families: &[Atom]) {
+		self.sheet.fallback.set(script, families);
+	}
 
-		let (height, max_height) = self.table.height(font_id, font_info, self.font_type);
-		font_info.height = height;
-		font_info.max_height = max_height;
+	/// 设置系统字体库（native）
+	/// 
+	/// 创建字体时， 未通过`add_font`添加数据的字体族按字重从字体库中匹配， 匹配到的字体数据在首次使用时读取并添加
+	/// 字体库需已扫描（`FontDb::scan`）
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn set_font_db(&mut self, db: FontDb) {
+		self.sheet.font_db = Some(db);
+	}
 
-		font_id
+	// 将字体列表中未添加数据的字体族替换为系统字体库中匹配的字体， 并添加其数据
+	#[cfg(not(target_arch = "wasm32"))]
+	fn resolve_system_fonts(&mut self, font_family: SmallVec<[Atom; 1]>, f: &Font) -> SmallVec<[Atom; 1]> {
+		if self.sheet.font_db.is_none() {
+			return font_family;
+		}
+		let (weight, stretch) = (f.font_weight as u16, stretch_class(f.font_stretch));
+		let mut r: SmallVec<[Atom; 1]> = SmallVec::new();
+		for family in font_family {
+			if self.sheet.font_names_map.get(&family).is_some_and(|k| self.table.has_font(FontFaceId(*k))) {
+				r.push(family);
+				continue;
+			}
+			let db = self.sheet.font_db.as_mut().unwrap();
+			let face = match db.query(family.as_str(), weight, f.font_style, stretch) {
+				Some(i) => i,
+				None => {
+					r.push(family);
+					continue;
+				}
+			};
+			let (name, index) = (Atom::from(db.faces()[face].name()), db.faces()[face].index);
+			if !self.sheet.font_names_map.get(&name).is_some_and(|k| self.table.has_font(FontFaceId(*k))) {
+				match self.sheet.font_db.as_mut().unwrap().load(face).and_then(|data| self.add_font_face(&name, data, index)) {
+					Some(_) => (),
+					None => {
+						r.push(family);
+						continue;
+					},
+				}
+			}
+			r.push(name);
+		}
+		r
 	}
 
 	/// 获取字体家族ID
@@ -350,16 +489,57 @@ impl FontMgr {
 		self.table.glyph_id(f, char, font_info, self.font_type)
 	}
 
+	/// 劈分文字
+	/// 
+	/// 劈分结果按逻辑顺序排列， 换行后通过`SplitChar2::line_map`取得每行的视觉顺序
+	/// 
+	/// # 参数
+	/// - `is_reverse`: 段落方向是否从右到左， 段落内的混排文字按双向算法（UAX #9）确定方向
 	pub fn split<'a>(&mut self, f: FontId, text: &'a str, word_split: bool, merge_whitespace: bool, is_reverse: bool) -> SplitChar2<'a> {
 		let font_info = &mut self.sheet.fonts[f.0];
 		self.table.split(f, font_info, self.font_type, text, word_split, merge_whitespace, is_reverse)
 	}
 
+	/// 按Unicode换行算法（UAX #14）劈分文字
+	/// 
+	/// 两个换行机会之间的字形簇组成一个单词（标点不与前后文字分离、CJK避头尾、不换行空格不断开等），
+	/// 必须换行处返回Newline， 通过`SplitChar2::break_before`查询每个劈分单元之前的换行机会
+	/// 
+	/// # 参数
+	/// - `strictness`: 换行严格程度（如日文的小假名、长音符之前是否可以换行）
+	/// - `is_reverse`: 段落方向是否从右到左
+	pub fn split_line_break<'a>(&mut self, f: FontId, text: &'a str, strictness: LineBreakStrictness, merge_whitespace: bool, is_reverse: bool) -> SplitChar2<'a> {
+		self.split(f, text, true, merge_whitespace, is_reverse).with_line_break(text, strictness)
+	}
+
+	/// 获取文字的字形ID
+	/// 
+	/// 不换行， 每个段落作为一行， 按双向算法重排为视觉顺序
+	/// 
+	/// # 返回值
+	/// 每个字形簇一个字符及其第一个字形的id
 	pub fn glyph_indexs<'a>(&mut self, f: FontId, text: &'a str, is_reverse: bool) -> (String, Vec<Option<GlyphId>>){
 		let font_info = &mut self.sheet.fonts[f.0];
 		self.table.glyph_indexs(f, font_info, self.font_type, text, is_reverse)
 	}
 
+	/// 塑形
+	/// 
+	/// 使用字体的GSUB/GPOS表将文本转换为字形序列（连字、上下文变形、组合标记定位等）
+	/// 
+	/// # 参数
+	/// - `f`: 字体ID
+	/// - `text`: 文本
+	/// - `is_reverse`: 段落方向是否从右到左， 每段文字按双向算法确定的方向塑形
+	/// 
+	/// # 返回值
+	/// 按逻辑顺序排列的字形簇， 换行后通过`ShapedText::line_map`取得每行的视觉顺序， 
+	/// 步进及偏移为相对于字体高度的百分比， 乘以字号得到像素值
+	pub fn shape(&mut self, f: FontId, text: &str, is_reverse: bool) -> ShapedText {
+		let font_info = &mut self.sheet.fonts[f.0];
+		self.table.shape(f, font_info, self.font_type, text, is_reverse)
+	}
+
 	/// 为字形添加阴影效果
 	/// 
 	/// # 参数
@@ -381,6 +561,27 @@ impl FontMgr {
 		self.table.sdf2_table. add_font_outer_glow(id, font_info, range);
 	}
 
+	/// 进入下一帧（SDF2）
+	/// 
+	/// 纹理空间不足时，会淘汰当前帧未使用的字形，为新字形腾出空间
+	pub fn next_frame(&mut self) {
+		self.table.sdf2_table.next_frame();
+	}
+
+	/// 标记字形在当前帧被使用（SDF2）
+	/// 
+	/// 外部缓存了GlyphId时，每帧使用前调用，避免字形被淘汰
+	pub fn mark_glyph_used(&mut self, id: GlyphId) {
+		self.table.sdf2_table.mark_used(id);
+	}
+
+	/// 取走被淘汰的字形（SDF2）
+	/// 
+	/// 被淘汰的GlyphId已失效，需要重新通过`glyph_id`获取
+	pub fn take_evicted_glyphs(&mut self) -> Vec<GlyphId> {
+		self.table.sdf2_table.take_evicted()
+	}
+
 	/// 测量字符宽度
 	/// 
 	/// # 返回值
@@ -405,9 +606,67 @@ impl FontMgr {
 		self.table.measure_width_of_glyph_id(f, font_info, glyph_id, self.font_type)
 	}
 
+	/// 两个相邻字形之间的字距调整
+	/// 
+	/// 读取字体GPOS的成对调整（kern特性）或kern表， 如"AV"、"To"
+	/// 
+	/// # 返回值
+	/// 调整量（单位：像素）， 负数表示靠近
+	pub fn kern(&mut self, f: FontId, left: GlyphId, right: GlyphId) -> f32 {
+		let font_info = match self.sheet.fonts.get(*f) {
+			Some(r) => r,
+			None => return 0.0,
+		};
+		self.table.kern(font_info, left, right, self.font_type)
+	}
+
+	/// 测量一段字形的宽度
+	/// 
+	/// 每个字形的步进之和， 并加上相邻字形之间的字距调整
+	/// 
+	/// # 返回值
+	/// 宽度（单位：像素）
+	pub fn measure_run(&mut self, f: FontId, glyph_ids: &[GlyphId]) -> f32 {
+		let mut width = 0.0;
+		for (i, glyph_id) in glyph_ids.iter().enumerate() {
+			width += self.measure_width_of_glyph_id(f, *glyph_id);
+			if let Some(next) = glyph_ids.get(i + 1) {
+				width += self.kern(f, *glyph_id, *next);
+			}
+		}
+		width
+	}
+
+	/// 段落排版
+	/// 
+	/// 换行、对齐、行高、字间距、空白处理及文本溢出省略， 见`layout::layout`
+	/// 
+	/// # 参数
+	/// - `max_width`: 最大宽度（单位：像素）， f32::INFINITY表示不限制
+	/// 
+	/// # 返回值
+	/// 每个字形的位置、每行的行框及整体尺寸
+	pub fn layout(&mut self, f: FontId, text: &str, max_width: f32, style: &LayoutStyle) -> TextLayout {
+		super::layout::layout(self, f, text, max_width, style)
+	}
+
+	/// 富文本排版
+	/// 
+	/// 一个段落中的多段文字各自使用不同的字体（字体族、字号、字重、描边）， 共享行并按基线对齐， 见`layout::layout_spans`
+	pub fn layout_spans(&mut self, spans: &[TextSpan], max_width: f32, style: &LayoutStyle) -> TextLayout {
+		super::layout::layout_spans(self, spans, max_width, style)
+	}
+
+	/// 取到字形信息
+	///
+	/// GlyphId为空或已失效（被淘汰或调用过`clear`）时返回None， 需要重新通过`glyph_id`获取
+	pub fn glyph(&self, id: GlyphId) -> Option<&Glyph> {
+		self.table.glyph_id_desc(id, self.font_type).map(|r| &r.glyph)
+	}
+
 	/// 获取字形度量信息
 	pub fn metrics(&self, id: GlyphId) -> Option<&MetricsInfo> {
-		let desc = self.table.glyph_id_desc(id, self.font_type);
+		let desc = self.table.glyph_id_desc(id, self.font_type)?;
 		let font_info = match self.sheet.fonts.get(desc.font_id.0) {
 			Some(r) => r,
 			None => return None,
@@ -415,13 +674,25 @@ impl FontMgr {
 		self.table.metrics(id, font_info, self.font_type)
 	}
 
+	/// 获取字体在其第`font_face_index`个字体外观上的样式
+	///
+	/// 包含可变字体的轴坐标， 及字体外观中没有匹配的粗体、斜体时需要合成的外扩量、错切系数
+	/// 位图字体在光栅化时、sdf2在生成距离场前已将其应用到字形轮廓， 步进宽度也已包含合成粗体的外扩量， 渲染时不需要再处理
+	pub fn face_style(&self, font_id: FontId, font_face_index: usize) -> Option<FaceStyle> {
+		let font_info = self.sheet.fonts.get(font_id.0)?;
+		let face_id = font_info.font_ids.get(font_face_index)?;
+		self.table.face_style(*face_id, &font_info.font)
+	}
+
 	/// 获取字体全局度量信息
+	/// 
+	/// 取字体列表中第一个字体外观的度量信息， 第一个字体外观不存在时返回None
 	pub fn font_metrics(&self, font_id: FontId) -> Option<&MetricsInfo> {
 		let font_info = match self.sheet.fonts.get(font_id.0) {
 			Some(r) => r,
 			None => return None,
 		};
-		if font_info.font_ids.len() > 0 &&  font_info.font_ids[0].0.is_null(){
+		if font_info.font_ids.len() > 0 &&  !font_info.font_ids[0].0.is_null(){
 			return self.table.fontface_metrics(font_info.font_ids[0], self.font_type)
 		}
 		
@@ -537,6 +808,99 @@ impl FontMgr {
 	// 	}
 	// }
 
+	/// 添加字体数据
+	/// 
+	/// 字体数据同时提供给位图和SDF2字体表，已创建的、使用该字体的FontInfo会重新计算高度
+	/// 
+	/// # 参数
+	/// - `font_face`: 字体名称
+	/// - `buffer`: 字体文件数据（ttf/otf/woff/woff2）， 字体集合（ttc/otc）时使用其中的第一个字体
+	///
+	/// # 返回值
+	/// 数据无法解析为字体时返回None
+	pub fn add_font(&mut self, font_face: &Atom, buffer: Share<Vec<u8>>) -> Option<FontFaceId> {
+		self.add_font_face(font_face, buffer, 0)
+	}
+
+	/// 添加字体集合（ttc/otc）中的所有字体
+	///
+	/// 每个字体以其PostScript名称（不存在时为"字体族名-索引"）作为字体名， 分别注册为一个FontFaceId，
+	/// 各字体共用同一份字体数据
+	///
+	/// # 返回值
+	/// 按字体在集合中的顺序， 返回(字体名, FontFaceId)， 不包括无法解析的字体
+	pub fn add_font_collection(&mut self, buffer: Share<Vec<u8>>) -> Vec<(Atom, FontFaceId)> {
+		let buffer = to_sfnt(buffer);
+		collection_faces(&buffer).into_iter().filter_map(|face| {
+			let name = Atom::from(face.name());
+			let id = self.add_font_face(&name, buffer.clone(), face.index)?;
+			Some((name, id))
+		}).collect()
+	}
+
+	/// 按PostScript名称添加字体集合中的一个字体
+	///
+	/// 集合中不存在该名称的字体或字体无法解析时返回None
+	pub fn add_font_by_post_script_name(&mut self, font_face: &Atom, buffer: Share<Vec<u8>>, post_script_name: &str) -> Option<FontFaceId> {
+		let buffer = to_sfnt(buffer);
+		let index = find_face(&buffer, post_script_name)?;
+		self.add_font_face(font_face, buffer, index)
+	}
+
+	/// 按索引添加字体集合中的一个字体
+	///
+	/// # 参数
+	/// - `font_face`: 字体名称
+	/// - `buffer`: 字体文件数据
+	/// - `index`: 字体在字体集合中的索引（见`collection::collection_faces`）， 不是字体集合时为0
+	///
+	/// # 返回值
+	/// 数据无法解析为字体（或集合中不存在该索引的字体）时返回None， 使用该字体名的FontInfo不受影响
+	pub fn add_font_face(&mut self, font_face: &Atom, buffer: Share<Vec<u8>>, index: u32) -> Option<FontFaceId> {
+		let font_face_id = self.create_font_face(font_face);
+		if !self.table.add_font(font_face_id, buffer, index) {
+			return None;
+		}
+
+		for (k, font_info) in self.sheet.fonts.iter_mut() {
+			if font_info.font_ids.contains(&font_face_id) {
+				let (height, max_height) = self.table.height(FontId(k), font_info, self.font_type);
+				font_info.height = height;
+				font_info.max_height = max_height;
+			}
+		}
+		Some(font_face_id)
+	}
+
+	/// 绘制位图文字
+	/// 
+	/// 光栅化所有等待中的位图字形，每个字形通过`update`回调一次，
+	/// FontImage为单通道覆盖率数据
+	pub fn draw_bitmap<F: FnMut(Block, FontImage)>(&mut self, update: F) {
+		self.table.bitmap_table.draw(&mut self.sheet.fonts, update);
+	}
+
+	/// 绘制彩色字形（COLR、CBDT、sbix）
+	/// 
+	/// 光栅化当前渲染模式下所有等待中的彩色字形，每个字形通过`update`回调一次，
+	/// FontImage为RGBA8数据（非预乘），Block为其在彩色图集中的位置（`Glyph::color`为true的字形）
+	pub fn draw_color<F: FnMut(Block, FontImage)>(&mut self, update: F) {
+		self.table.draw_color(self.font_type, update);
+	}
+
+	/// 获取彩色字形图集的尺寸
+	/// 
+	/// 彩色图集与文字图集分开，外部需要为其另外准备RGBA纹理（每页一张）
+	pub fn color_atlas_size(&self) -> Size<usize> {
+		let packer = &self.table.color_atlas(self.font_type).text_packer;
+		Size { width: packer.width, height: packer.height }
+	}
+
+	/// 获取彩色字形图集的页数
+	pub fn color_atlas_page_count(&self) -> usize {
+		self.table.color_atlas(self.font_type).text_packer.page_count()
+	}
+
 	/// 添加SDF配置项
 	/// 
 	/// # 参数
@@ -550,12 +914,21 @@ impl FontMgr {
 	/// 添加默认SDF字符
 	/// 
 	/// 用于预生成常用字符的SDF数据
-	pub fn add_sdf_default_char(&mut self, _font_face: Atom, _char: char) {
-		// let font_face_id = self.create_font_face(&font_face);
-		// let font_family_id = self.font_family_id(Font::new(font_face.clone(), BASE_FONT_SIZE, 500, unsafe{ NotNan::new_unchecked(0.0)}));
-		// let glyph_id = self.glyph_id(font_family_id, char).unwrap();
-		// self.brush.sdf_brush.add_default_char(font_face_id, glyph_id, font_face.clone(), char);
-		// self.default_sdf_char.push((font_face, char));
+	/// 需要先通过`add_sdf_cfg`添加该字体的配置，仅在Sdf1模式下生效
+	pub fn add_sdf_default_char(&mut self, font_face: Atom, char: char) {
+		if self.font_type == FontType::Sdf1 {
+			let font_face_id = self.create_font_face(&font_face);
+			let font_id = self.font_id(Font::new(font_face.clone(), BASE_FONT_SIZE, 500, unsafe{ NotNan::new_unchecked(0.0)}));
+			let font_info = &mut self.sheet.fonts[font_id.0];
+			if let Some(glyph_id) = self.table.sdf_table.glyph_id(font_id, font_info, char) {
+				self.table.sdf_table.add_default_char(font_face_id, glyph_id, font_face.clone(), char);
+			}
+		}
+
+		// 记录默认字符，clear后重新添加
+		if !self.sheet.default_sdf_char.iter().any(|r| r.0 == font_face && r.1 == char) {
+			self.sheet.default_sdf_char.push((font_face, char));
+		}
 	}
 
 	/// 获取可变的字体信息表
@@ -566,6 +939,7 @@ impl FontMgr {
 	/// 清理所有缓存数据
 	/// 
 	/// 重置纹理图集并保留预生成的默认字符
+	/// 之前分配的GlyphId全部失效，可通过`generation`判断是否需要重新请求
 	pub fn clear(&mut self) {
 		for  info in self.sheet.fonts.values_mut() {
 			info.await_info.size = Size {width: 0, height: 0};
@@ -593,7 +967,7 @@ impl FontMgr {
 	// }
 
 	/// 内部方法：获取或插入字体记录
-	fn get_or_insert_font(&mut self, f: Font, font_ids: SmallVec<[FontFaceId; 1]>, font_family_id: FontFamilyId) -> FontId {
+	fn get_or_insert_font(&mut self, f: Font, font_ids: SmallVec<[FontFaceId; 1]>, font_family_id: FontFamilyId, fallback: FaceFallback) -> FontId {
 		match self.sheet.fonts_map.entry(f.clone()) {
 			Entry::Occupied(r) => return r.get().clone(),
 			Entry::Vacant(r) => {
@@ -606,6 +980,7 @@ impl FontMgr {
 						wait_list: Vec::new() },
 					font_family_id,
 					font_ids,
+					fallback,
 				});
 				r.insert(FontId(id)).clone()
 			}
@@ -649,12 +1024,6 @@ pub const BASE_FONT_SIZE: usize = 32;
 /// SDF偏移量范围常量，用于坐标编码优化
 pub const OFFSET_RANGE: f32 = (2_u32).pow(15) as f32;
 
-/// 粗体字的最小字重阈值
-pub const BLOD_WEIGHT: usize = 700;
-
-/// 粗体字缩放因子，用于自动调整字符宽度
-pub const BLOD_FACTOR: f32 = 1.13;
-
 /// 字形描述信息
 #[derive(Debug)]
 pub struct GlyphIdDesc {
@@ -674,6 +1043,7 @@ pub struct FontInfo {
 	pub max_height: f32,           // 最大字符高度
 	pub await_info: AwaitInfo,      // 待渲染队列信息
 	pub font_family_id: FontFamilyId, // 字体家族ID
+	pub fallback: FaceFallback,    // 每种文字尝试字体的顺序
 }
 
 /// 待渲染队列信息
@@ -696,6 +1066,9 @@ pub struct Glyph {
 	pub width: f32,        // 纹理宽度（像素）
     pub height: f32,       // 纹理高度（像素）
 	pub advance: f32,      // 布局步进宽度（相对于字体高度的百分比）
+	pub font_size: f32,    // 纹理中字形的字号（像素）， 纹理宽高、sdf的像素范围都以该字号为准； sdf2为字号所在的sdf档位（见`sdf_font_size`）
+	pub page: usize,       // 所在纹理页
+	pub color: bool,       // 是否为彩色字形（纹理坐标位于彩色图集， 渲染时直接采样颜色）
 }
 
 #[derive(Debug)]
@@ -719,12 +1092,10 @@ pub struct DrawBlock {
 
 
 // msdf 需要修正字形信息
-pub fn fix_box(is_sdf: bool, width: f32, weight: usize, sw: f32) -> (f32/*左偏移*/, f32/*宽度*/) {
+// 粗体由字体外观（或合成粗体的轮廓）决定， 不再按字重缩放宽度
+pub fn fix_box(is_sdf: bool, width: f32, sw: f32) -> (f32/*左偏移*/, f32/*宽度*/) {
 	if is_sdf {
-		let mut w = width - sw;
-		if weight >= BLOD_WEIGHT {
-			w = w / BLOD_FACTOR;
-		}
+		let w = width - sw;
 		((width - w)/2.0,  w)
 	} else {
 		(0.0, width)
@@ -0,0 +1,378 @@
+//! 系统字体库（native）
+//!
+//! 扫描配置的目录（及Linux下fontconfig的标准字体目录）， 从字体的name、OS/2表中索引字体族、字重、样式及宽度，
+//! 按CSS字体匹配算法将`Font::font_family`中的字体族及`font_weight`解析为具体的字体， 字体数据在首次使用时才读取
+
+use std::{fs, io::{self, Read, Seek, SeekFrom}, path::{Path, PathBuf}};
+
+use pi_hash::XHashMap;
+use pi_share::Share;
+use ttf_parser::{Face, RawFaceTables, Style};
+
+use super::{collection::face_names, font::FontStyle, woff};
+
+/// 字体库中的一个字体
+#[derive(Debug, Clone)]
+pub struct FaceInfo {
+    /// 字体文件路径
+    pub path: PathBuf,
+    /// 在字体集合（ttc/otc）中的索引， 单个字体为0
+    pub index: u32,
+    /// 字体族名（含本地化名称， 第一个为英文名称或首个名称）
+    pub families: Vec<String>,
+    /// PostScript名称
+    pub post_script_name: String,
+    /// 字重（100~900）
+    pub weight: u16,
+    pub style: FontStyle,
+    /// 宽度（1~9， 5为正常）
+    pub stretch: u16,
+}
+
+impl FaceInfo {
+    /// 字体的唯一名称， 用作`FontMgr::add_font_face`的字体名
+    pub fn name(&self) -> String {
+        if !self.post_script_name.is_empty() {
+            return self.post_script_name.clone();
+        }
+        format!("{}-{}-{:?}-{}", self.families.first().map_or("", |r| r.as_str()), self.weight, self.style, self.stretch)
+    }
+
+    fn has_family(&self, family: &str) -> bool {
+        self.families.iter().any(|r| r.eq_ignore_ascii_case(family))
+    }
+}
+
+/// 字体库
+#[derive(Debug, Default)]
+pub struct FontDb {
+    dirs: Vec<PathBuf>,
+    faces: Vec<FaceInfo>,
+    // 已读取的字体文件数据
+    datas: XHashMap<PathBuf, Share<Vec<u8>>>,
+}
+
+impl FontDb {
+    /// 创建空的字体库， 通过`add_dir`添加扫描目录
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 创建使用系统字体目录的字体库
+    pub fn with_system_dirs() -> Self {
+        let mut r = Self::new();
+        for dir in system_dirs() {
+            r.add_dir(dir);
+        }
+        r
+    }
+
+    /// 添加扫描目录（递归扫描子目录）
+    pub fn add_dir<P: Into<PathBuf>>(&mut self, dir: P) {
+        let dir = dir.into();
+        if !self.dirs.contains(&dir) {
+            self.dirs.push(dir);
+        }
+    }
+
+    /// 扫描所有目录， 重建索引
+    pub fn scan(&mut self) {
+        self.faces.clear();
+        let dirs = self.dirs.clone();
+        for dir in dirs.iter() {
+            self.scan_dir(dir);
+        }
+        log::debug!("font db: {} faces in {} dirs", self.faces.len(), self.dirs.len());
+    }
+
+    /// 索引一个字体文件（ttf、otf、ttc、otc、woff、woff2）
+    ///
+    /// 只读取文件头、表目录及索引需要的表（head、hhea、maxp、name、OS/2）；
+    /// woff、woff2的表经过压缩， 需读取整个文件解码
+    pub fn add_file(&mut self, path: &Path) {
+        if let Err(e) = self.index_file(path) {
+            log::warn!("read font fail, {:?}, {:?}", path, e);
+        }
+    }
+
+    fn index_file(&mut self, path: &Path) -> io::Result<()> {
+        let mut file = fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut header = [0; 12];
+        file.read_exact(&mut header)?;
+
+        if matches!(&header[0..4], b"wOFF" | b"wOF2") {
+            let mut data = header.to_vec();
+            file.read_to_end(&mut data)?;
+            let data = woff::decode(&data).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid woff"))?;
+            let count = ttf_parser::fonts_in_collection(&data).unwrap_or(1);
+            for index in 0..count {
+                if let Ok(face) = Face::parse(&data, index) {
+                    self.faces.push(face_info(&face, path, index));
+                }
+            }
+            return Ok(());
+        }
+
+        // 字体集合的每个字体有各自的表目录
+        let offsets = if &header[0..4] == b"ttcf" {
+            let count = read_u32(&header, 8) as u64;
+            if 12 + count * 4 > len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let mut buf = vec![0; count as usize * 4];
+            file.read_exact(&mut buf)?;
+            (0..count as usize).map(|i| read_u32(&buf, i * 4) as u64).collect()
+        } else {
+            vec![0]
+        };
+
+        for (index, offset) in offsets.into_iter().enumerate() {
+            let tables = read_index_tables(&mut file, offset, len)?;
+            let [Some(head), Some(hhea), Some(maxp), name, os2] = &tables else {
+                continue;
+            };
+            let raw = RawFaceTables {
+                head,
+                hhea,
+                maxp,
+                name: name.as_deref(),
+                os2: os2.as_deref(),
+                ..Default::default()
+            };
+            if let Ok(face) = Face::from_raw_tables(raw) {
+                self.faces.push(face_info(&face, path, index as u32));
+            }
+        }
+        Ok(())
+    }
+
+    /// 所有字体
+    pub fn faces(&self) -> &[FaceInfo] {
+        &self.faces
+    }
+
+    /// 按CSS字体匹配算法查找字体
+    ///
+    /// 先匹配字体族（不区分大小写）， 再依次按宽度、样式、字重选择最接近的字体
+    ///
+    /// # 参数
+    /// - `family`: 字体族名
+    /// - `weight`: 字重（100~900）
+    /// - `style`: 样式
+    /// - `stretch`: 宽度（1~9， 5为正常）
+    ///
+    /// # 返回值
+    /// 字体在`faces`中的索引
+    pub fn query(&self, family: &str, weight: u16, style: FontStyle, stretch: u16) -> Option<usize> {
+        let mut candidates: Vec<usize> = (0..self.faces.len()).filter(|i| self.faces[*i].has_family(family)).collect();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        // 宽度： 正常及以下时优先更窄， 否则优先更宽
+        let best = candidates.iter().map(|i| self.faces[*i].stretch).min_by_key(|r| {
+            let (d, other_side) = if *r == stretch {
+                (0, 0)
+            } else if (stretch <= 5) == (*r < stretch) {
+                (r.abs_diff(stretch), 0)
+            } else {
+                (r.abs_diff(stretch), 1)
+            };
+            (other_side, d)
+        })?;
+        candidates.retain(|i| self.faces[*i].stretch == best);
+
+        // 样式
+        let order = match style {
+            FontStyle::Italic => [FontStyle::Italic, FontStyle::Oblique, FontStyle::Normal],
+            FontStyle::Oblique => [FontStyle::Oblique, FontStyle::Italic, FontStyle::Normal],
+            FontStyle::Normal => [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic],
+        };
+        let best = order.into_iter().find(|s| candidates.iter().any(|i| self.faces[*i].style == *s))?;
+        candidates.retain(|i| self.faces[*i].style == best);
+
+        // 字重： 400~500之间先找到500为止的更重字重， 再找更轻的， 最后找更重的；
+        // 小于400时先找更轻的； 大于500时先找更重的
+        candidates.into_iter().min_by_key(|i| {
+            let w = self.faces[*i].weight;
+            let heavier = w > weight;
+            let rank = if w == weight {
+                0
+            } else if (400..=500).contains(&weight) {
+                if heavier && w <= 500 { 1 } else if !heavier { 2 } else { 3 }
+            } else if weight < 400 {
+                if !heavier { 1 } else { 2 }
+            } else if heavier {
+                1
+            } else {
+                2
+            };
+            (rank, w.abs_diff(weight))
+        })
+    }
+
+    /// 读取字体文件数据（woff、woff2解码为sfnt）， 同一文件只读取一次
+    pub fn load(&mut self, index: usize) -> Option<Share<Vec<u8>>> {
+        let path = &self.faces.get(index)?.path;
+        if let Some(r) = self.datas.get(path) {
+            return Some(r.clone());
+        }
+        match fs::read(path) {
+            Ok(r) => {
+                let r = Share::new(woff::decode(&r).unwrap_or(r));
+                self.datas.insert(path.clone(), r.clone());
+                Some(r)
+            }
+            Err(e) => {
+                log::warn!("read font fail, {:?}, {:?}", path, e);
+                None
+            }
+        }
+    }
+
+    fn scan_dir(&mut self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(r) => r,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                self.scan_dir(&path);
+                continue;
+            }
+            let is_font = path.extension()
+                .and_then(|r| r.to_str())
+                .is_some_and(|r| matches!(r.to_ascii_lowercase().as_str(), "ttf" | "otf" | "ttc" | "otc" | "woff" | "woff2"));
+            if is_font {
+                self.add_file(&path);
+            }
+        }
+    }
+}
+
+/// 宽度百分比（CSS的font-stretch）对应的OS/2宽度等级（1~9）
+pub fn stretch_class(percent: usize) -> u16 {
+    const CLASSES: [f32; 9] = [50.0, 62.5, 75.0, 87.5, 100.0, 112.5, 125.0, 150.0, 200.0];
+    let p = percent as f32;
+    CLASSES.iter()
+        .enumerate()
+        .min_by(|a, b| (a.1 - p).abs().total_cmp(&(b.1 - p).abs()))
+        .map_or(5, |r| r.0 as u16 + 1)
+}
+
+/// 系统字体目录
+///
+/// Linux下为fontconfig的标准目录及/etc/fonts/fonts.conf中配置的目录， macOS、Windows下为系统字体目录
+pub fn system_dirs() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut dirs = Vec::new();
+    if cfg!(target_os = "windows") {
+        let windir = std::env::var_os("WINDIR").map_or(PathBuf::from("C:\\Windows"), PathBuf::from);
+        dirs.push(windir.join("Fonts"));
+        if let Some(r) = std::env::var_os("LOCALAPPDATA") {
+            dirs.push(PathBuf::from(r).join("Microsoft\\Windows\\Fonts"));
+        }
+    } else if cfg!(target_os = "macos") {
+        dirs.push(PathBuf::from("/System/Library/Fonts"));
+        dirs.push(PathBuf::from("/Library/Fonts"));
+        if let Some(home) = &home {
+            dirs.push(home.join("Library/Fonts"));
+        }
+    } else {
+        dirs.push(PathBuf::from("/usr/share/fonts"));
+        dirs.push(PathBuf::from("/usr/local/share/fonts"));
+        let data_home = std::env::var_os("XDG_DATA_HOME").map(PathBuf::from)
+            .or_else(|| home.as_ref().map(|r| r.join(".local/share")));
+        if let Some(r) = &data_home {
+            dirs.push(r.join("fonts"));
+        }
+        if let Some(home) = &home {
+            dirs.push(home.join(".fonts"));
+        }
+        if let Ok(conf) = fs::read_to_string("/etc/fonts/fonts.conf") {
+            for dir in fontconfig_dirs(&conf, home.as_deref(), data_home.as_deref()) {
+                if !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+            }
+        }
+    }
+    dirs
+}
+
+// fonts.conf中的<dir>配置
+fn fontconfig_dirs(conf: &str, home: Option<&Path>, data_home: Option<&Path>) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    let mut rest = conf;
+    while let Some(start) = rest.find("<dir") {
+        rest = &rest[start + 4..];
+        let (attrs, body) = match (rest.find('>'), rest.find("</dir>")) {
+            (Some(a), Some(b)) if a < b => (&rest[..a], &rest[a + 1..b]),
+            _ => continue,
+        };
+        let body = body.trim();
+        let dir = if attrs.contains("prefix=\"xdg\"") {
+            data_home.map(|r| r.join(body))
+        } else if let Some(r) = body.strip_prefix('~') {
+            home.map(|h| h.join(r.trim_start_matches('/')))
+        } else {
+            Some(PathBuf::from(body))
+        };
+        dirs.extend(dir);
+    }
+    dirs
+}
+
+// 索引需要的表
+const INDEX_TABLES: [&[u8; 4]; 5] = [b"head", b"hhea", b"maxp", b"name", b"OS/2"];
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+// 读取`offset`处的表目录， 及其中`INDEX_TABLES`的数据（超出文件的表视为不存在）
+fn read_index_tables(file: &mut fs::File, offset: u64, len: u64) -> io::Result<[Option<Vec<u8>>; 5]> {
+    let mut header = [0; 12];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut header)?;
+    let count = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let mut records = vec![0; count * 16];
+    file.read_exact(&mut records)?;
+
+    let mut tables: [Option<Vec<u8>>; 5] = Default::default();
+    for record in records.chunks_exact(16) {
+        let i = match INDEX_TABLES.iter().position(|r| record[0..4] == r[..]) {
+            Some(r) => r,
+            None => continue,
+        };
+        let (start, size) = (read_u32(record, 8) as u64, read_u32(record, 12) as u64);
+        if start + size > len {
+            continue;
+        }
+        let mut data = vec![0; size as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut data)?;
+        tables[i] = Some(data);
+    }
+    Ok(tables)
+}
+
+// 从name、OS/2表中取得字体信息
+fn face_info(face: &Face, path: &Path, index: u32) -> FaceInfo {
+    let (families, post_script_name) = face_names(face);
+    FaceInfo {
+        path: path.to_path_buf(),
+        index,
+        families,
+        post_script_name,
+        weight: face.weight().to_number(),
+        style: match face.style() {
+            Style::Normal => FontStyle::Normal,
+            Style::Italic => FontStyle::Italic,
+            Style::Oblique => FontStyle::Oblique,
+        },
+        stretch: face.width().to_number(),
+    }
+}
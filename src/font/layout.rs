@@ -0,0 +1,773 @@
+//! 段落排版
+//!
+//! 在`FontMgr::split_line_break`的基础上完成换行、对齐、行高、字间距及词间距、空白处理、文本溢出省略，
+//! 换行后按双向算法将每行重排为视觉顺序， 输出每个字形的位置、每行的行框及整体尺寸
+
+use std::{borrow::Cow, ops::Range};
+
+use super::{bidi::{BidiMap, BidiText}, font::{FontId, FontMgr, GlyphId}, line_break::{line_breaks, LineBreakStrictness}, text_split::SplitResult2};
+
+/// 水平对齐方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TextAlign {
+    /// 段落方向的起始端（从左到右时为左， 从右到左时为右）
+    #[default]
+    Start,
+    /// 段落方向的结束端
+    End,
+    Left,
+    Right,
+    Center,
+    /// 两端对齐， 将剩余空间平均分配到空白处（段落最后一行及换行符之前的行按Start对齐）
+    Justify,
+}
+
+/// 行高
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LineHeight {
+    /// 字体高度
+    #[default]
+    Normal,
+    /// 像素值
+    Px(f32),
+    /// 字号的倍数
+    Scale(f32),
+}
+
+/// 空白处理方式（对应CSS的white-space）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum WhiteSpace {
+    /// 合并空白， 换行符视为空白， 自动换行
+    #[default]
+    Normal,
+    /// 合并空白， 换行符视为空白， 不自动换行
+    NoWrap,
+    /// 保留空白及换行符， 不自动换行
+    Pre,
+    /// 保留空白及换行符， 自动换行
+    PreWrap,
+    /// 合并空白， 保留换行符， 自动换行
+    PreLine,
+}
+
+impl WhiteSpace {
+    /// 是否自动换行
+    pub fn wrap(&self) -> bool {
+        matches!(self, Self::Normal | Self::PreWrap | Self::PreLine)
+    }
+
+    /// 是否合并连续的空白
+    pub fn collapse(&self) -> bool {
+        matches!(self, Self::Normal | Self::NoWrap | Self::PreLine)
+    }
+
+    /// 是否保留换行符
+    pub fn preserve_newline(&self) -> bool {
+        !matches!(self, Self::Normal | Self::NoWrap)
+    }
+}
+
+/// 文本溢出处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TextOverflow {
+    /// 不处理（由外部裁剪）
+    #[default]
+    Clip,
+    /// 超出最大宽度的行， 截断并在末尾显示省略号
+    Ellipsis,
+}
+
+/// 排版样式
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutStyle {
+    pub align: TextAlign,
+    pub line_height: LineHeight,
+    /// 字间距（像素）， 加在每个字形之后
+    pub letter_spacing: f32,
+    /// 词间距（像素）， 加在每个空白之后
+    pub word_spacing: f32,
+    pub white_space: WhiteSpace,
+    pub text_overflow: TextOverflow,
+    /// 换行严格程度
+    pub line_break: LineBreakStrictness,
+    /// 段落方向是否从右到左
+    pub is_reverse: bool,
+}
+
+impl Default for LayoutStyle {
+    fn default() -> Self {
+        Self {
+            align: TextAlign::Start,
+            line_height: LineHeight::Normal,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+            white_space: WhiteSpace::Normal,
+            text_overflow: TextOverflow::Clip,
+            line_break: LineBreakStrictness::Normal,
+            is_reverse: false,
+        }
+    }
+}
+
+/// 字形簇中的一个字形
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacedGlyph {
+    /// 字形id， None表示纹理空间不足， 未能分配
+    pub glyph_id: Option<GlyphId>,
+    /// 相对于所在字形簇左上角（GlyphQuad::x、y）的X偏移（像素）， 含塑形得到的偏移
+    pub x: f32,
+    /// 相对于所在字形簇左上角的Y偏移（像素， 向下为正）， 如组合标记的垂直偏移
+    pub y: f32,
+}
+
+/// 排版后的字形簇
+#[derive(Debug, Clone)]
+pub struct GlyphQuad {
+    /// 字形簇第一个字形的id， None表示纹理空间不足， 未能分配
+    pub glyph_id: Option<GlyphId>,
+    /// 字形簇中的所有字形（视觉顺序）及其偏移， 连字、组合标记、复杂文字的字形簇可能有多个字形
+    pub glyphs: Vec<PlacedGlyph>,
+    /// 字形簇的第一个字符
+    pub char: char,
+    /// 所在文字段的索引
+    pub span: usize,
+    /// 在段落中的字节范围（段落为所有文字段按顺序相连的文本）， 省略号为被省略文字的范围
+    pub range: Range<usize>,
+    /// 是否从右到左
+    pub rtl: bool,
+    /// 在文字段劈分结果中的索引（字形簇的索引， 逻辑顺序）， 省略号为usize::MAX
+    pub index: usize,
+    /// 左上角X坐标（像素）
+    pub x: f32,
+    /// 左上角Y坐标（像素）， 各文字段按基线对齐
+    pub y: f32,
+    /// 字形簇的步进宽度（像素， 含字间距及词间距）
+    pub width: f32,
+    /// 所在文字段的字体高度（像素）
+    pub height: f32,
+}
+
+/// 行框
+#[derive(Debug, Clone)]
+pub struct LineBox {
+    /// 本行字形在TextLayout::glyphs中的范围（视觉顺序）
+    pub glyphs: Range<usize>,
+    /// 本行在段落中的字节范围（逻辑顺序， 不含行尾的换行符）
+    pub range: Range<usize>,
+    /// 本行内容的起始X坐标（像素）
+    pub x: f32,
+    /// 行顶部Y坐标（像素）
+    pub y: f32,
+    /// 内容宽度（像素， 不含行尾空白）
+    pub width: f32,
+    /// 行高（像素）
+    pub height: f32,
+    /// 基线Y坐标（像素）
+    pub baseline: f32,
+}
+
+/// 排版结果
+#[derive(Debug, Clone, Default)]
+pub struct TextLayout {
+    /// 所有字形， 按行排列， 行内按视觉顺序（从左到右）排列
+    pub glyphs: Vec<GlyphQuad>,
+    pub lines: Vec<LineBox>,
+    /// 包围盒宽度（像素）
+    pub width: f32,
+    /// 包围盒高度（像素）
+    pub height: f32,
+}
+
+/// 富文本中的一段文字， 整段使用同一字体（字体族、字号、字重、描边均由字体ID确定）
+#[derive(Debug, Clone, Copy)]
+pub struct TextSpan<'a> {
+    pub font: FontId,
+    pub text: &'a str,
+}
+
+// 排版单元（一个劈分结果）
+#[derive(Debug, Clone)]
+struct Unit {
+    span: usize,
+    index: usize,
+    // 在整个段落中的字符范围
+    chars: Range<usize>,
+    // 在整个段落中的字节范围
+    bytes: Range<usize>,
+    char: char,
+    glyph_id: Option<GlyphId>,
+    glyphs: Vec<PlacedGlyph>,
+    width: f32,
+    is_space: bool,
+}
+
+// 劈分单元对应的字形簇
+struct Cluster {
+    // 在整个段落中的字符范围
+    chars: Range<usize>,
+    // 在整个段落中的字节范围
+    bytes: Range<usize>,
+    // 塑形得到的步进（像素， 不含字间距）， 没有塑形结果时为None
+    advance: Option<f32>,
+    glyphs: Vec<PlacedGlyph>,
+}
+
+enum Item {
+    Word(Vec<Unit>),
+    Space(Unit),
+    Newline(usize, Range<usize>),
+}
+
+#[derive(Default)]
+struct Line {
+    units: Vec<Unit>,
+    ellipsis: Option<Unit>,
+    // 换行符结束的行
+    hard_break: bool,
+    // 空行使用的文字段
+    span: usize,
+    // 在段落中的字节范围
+    range: Range<usize>,
+}
+
+impl Line {
+    fn width(&self) -> f32 {
+        self.units.iter().map(|r| r.width).sum::<f32>() + self.ellipsis.as_ref().map_or(0.0, |r| r.width)
+    }
+
+    // 不含行尾空白的宽度
+    fn content_width(&self) -> f32 {
+        let end = self.units.iter().rposition(|r| !r.is_space).map_or(0, |i| i + 1);
+        self.units[..end].iter().map(|r| r.width).sum::<f32>() + self.ellipsis.as_ref().map_or(0.0, |r| r.width)
+    }
+}
+
+// 文字段的字体度量（像素）
+struct SpanMetrics {
+    font: FontId,
+    font_size: f32,
+    font_height: f32,
+    // 基线以上的高度
+    ascent: f32,
+    // 基线以下的高度
+    descent: f32,
+    // 行高
+    line_height: f32,
+}
+
+impl SpanMetrics {
+    fn new(mgr: &FontMgr, f: FontId, line_height: LineHeight) -> Self {
+        let font_size = mgr.font_info(f).font.font_size as f32;
+        let font_height = mgr.font_height(f, font_size as usize);
+        // 各表中升线、降线的单位不同， 按其比例分配字体高度
+        let ascent = match mgr.font_metrics(f) {
+            Some(m) if m.ascender - m.descender > 0.0 => font_height * m.ascender / (m.ascender - m.descender),
+            _ => font_height * 0.8,
+        };
+        let line_height = match line_height {
+            LineHeight::Normal => font_height,
+            LineHeight::Px(r) => r,
+            LineHeight::Scale(r) => r * font_size,
+        };
+        Self { font: f, font_size, font_height, ascent, descent: font_height - ascent, line_height }
+    }
+
+    // 行高扩展后， 基线以上及以下的高度
+    fn extent(&self) -> (f32, f32) {
+        let half_leading = (self.line_height - self.font_height) / 2.0;
+        (self.ascent + half_leading, self.descent + half_leading)
+    }
+}
+
+/// 排版
+///
+/// # 参数
+/// - `mgr`: 字体管理器
+/// - `f`: 字体ID
+/// - `text`: 文本
+/// - `max_width`: 最大宽度（像素）， f32::INFINITY表示不限制
+/// - `style`: 排版样式
+pub fn layout(mgr: &mut FontMgr, f: FontId, text: &str, max_width: f32, style: &LayoutStyle) -> TextLayout {
+    layout_spans(mgr, &[TextSpan { font: f, text }], max_width, style)
+}
+
+/// 富文本排版
+///
+/// 每段文字使用各自的字体劈分、塑形及测量， 在同一段落中共享行（跨文字段的单词不在段边界处换行），
+/// 同一行中各段按基线对齐， 行高取各段按行高扩展后基线以上及以下高度的最大值之和
+/// 双向重排在整个段落上进行
+///
+/// # 参数
+/// - `mgr`: 字体管理器
+/// - `spans`: 按逻辑顺序排列的文字段
+/// - `max_width`: 最大宽度（像素）， f32::INFINITY表示不限制
+/// - `style`: 排版样式， 字间距、词间距对所有文字段生效
+///
+/// # 返回值
+/// 每个字形的GlyphQuad::span为其所在文字段的索引
+pub fn layout_spans(mgr: &mut FontMgr, spans: &[TextSpan], max_width: f32, style: &LayoutStyle) -> TextLayout {
+    let white_space = style.white_space;
+    let texts: Vec<Cow<str>> = spans.iter().map(|r| if white_space.preserve_newline() {
+        Cow::Borrowed(r.text)
+    } else {
+        // 逐字节替换， 保持字节偏移不变
+        Cow::Owned(r.text.replace(['\n', '\r'], " "))
+    }).collect();
+    let metrics: Vec<SpanMetrics> = spans.iter().map(|r| SpanMetrics::new(mgr, r.font, style.line_height)).collect();
+
+    // 整个段落的双向分析及换行机会
+    let paragraph = texts.concat();
+    let bidi = BidiText::new(&paragraph, Some(style.is_reverse));
+    let breaks = line_breaks(&paragraph, style.line_break);
+
+    // 字间距及词间距
+    let spacing = |is_space: bool| if is_space { style.letter_spacing + style.word_spacing } else { style.letter_spacing };
+    // 没有塑形结果时（如省略号）， 按字形的步进测量
+    let measure = |mgr: &mut FontMgr, m: &SpanMetrics, glyph_id: Option<GlyphId>, is_space: bool| {
+        let width = match glyph_id {
+            Some(id) => mgr.measure_width_of_glyph_id(m.font, id),
+            None => m.font_size * 0.5,
+        };
+        width + spacing(is_space)
+    };
+
+    // 劈分并测量
+    let mut items = Vec::new();
+    let mut word: Vec<Unit> = Vec::new();
+    let (mut byte_offset, mut char_offset) = (0, 0);
+    for (s, text) in texts.iter().enumerate() {
+        let f = spans[s].font;
+        let mut split = mgr.split_line_break(f, text, style.line_break, white_space.collapse(), style.is_reverse);
+        let chars = split.text.clone();
+        // 取出塑形结果， 每个劈分单元按其字形簇的步进测量， 并输出字形簇中的所有字形
+        let shaped = split.shaped.take();
+        let font_size = metrics[s].font_size;
+        // 没有塑形结果时， 每个劈分单元为一个字符， 字节范围按字符的字节偏移计算
+        let offsets: Vec<usize> = text.char_indices().map(|(i, _)| i).chain(std::iter::once(text.len())).collect();
+        let clusters: Vec<Cluster> = (0..chars.len()).map(|i| match shaped.as_ref().and_then(|r| r.clusters.get(i).map(|c| (r, c))) {
+            Some((shaped, c)) => {
+                let mut pen = 0.0;
+                let glyphs = shaped.cluster_glyphs(c).iter().map(|g| {
+                    let r = PlacedGlyph { glyph_id: g.glyph_id, x: (pen + g.x_offset) * font_size, y: -g.y_offset * font_size };
+                    pen += g.x_advance;
+                    r
+                }).collect();
+                Cluster {
+                    chars: c.char_range.start + char_offset..c.char_range.end + char_offset,
+                    bytes: c.byte_range.start + byte_offset..c.byte_range.end + byte_offset,
+                    advance: Some(c.advance * font_size),
+                    glyphs,
+                }
+            }
+            None => Cluster {
+                chars: i + char_offset..i + 1 + char_offset,
+                bytes: match (offsets.get(i), offsets.get(i + 1)) {
+                    (Some(start), Some(end)) => start + byte_offset..end + byte_offset,
+                    _ => text.len() + byte_offset..text.len() + byte_offset,
+                },
+                advance: None,
+                glyphs: Vec::new(),
+            },
+        }).collect();
+        // 文字段边界处没有换行机会时， 与前一段的最后一个单词相连
+        let mut join = byte_offset > 0
+            && breaks.binary_search_by_key(&byte_offset, |r| r.0).is_err()
+            && matches!(items.last(), Some(Item::Word(_)));
+        let unit = |mgr: &mut FontMgr, i: isize, char: char, glyph_id: Option<GlyphId>, is_space: bool| {
+            let index = i as usize;
+            let cluster = clusters.get(index);
+            let width = match cluster.and_then(|r| r.advance) {
+                Some(advance) => advance + spacing(is_space),
+                None => measure(mgr, &metrics[s], glyph_id, is_space),
+            };
+            let glyphs = match cluster {
+                Some(r) if !r.glyphs.is_empty() => r.glyphs.clone(),
+                _ => vec![PlacedGlyph { glyph_id, x: 0.0, y: 0.0 }],
+            };
+            Unit {
+                span: s,
+                index,
+                chars: cluster.map_or(char_offset..char_offset, |r| r.chars.clone()),
+                bytes: cluster.map_or(byte_offset..byte_offset, |r| r.bytes.clone()),
+                char,
+                glyph_id,
+                glyphs,
+                width,
+                is_space,
+            }
+        };
+        for r in split.by_ref() {
+            match r {
+                SplitResult2::Newline(i) => {
+                    let bytes = clusters.get(i as usize).map_or(byte_offset..byte_offset, |r| r.bytes.clone());
+                    items.push(Item::Newline(s, bytes));
+                }
+                SplitResult2::Whitespace(i, id) => {
+                    // 跨文字段的连续空白合并为一个
+                    if white_space.collapse() && matches!(items.last(), Some(Item::Space(_))) {
+                        continue;
+                    }
+                    let char = chars.get(i as usize).copied().unwrap_or(' ');
+                    items.push(Item::Space(unit(mgr, i, char, id, true)));
+                }
+                SplitResult2::Word(i, c, id) => {
+                    let u = unit(mgr, i, c, id, false);
+                    match items.last_mut() {
+                        Some(Item::Word(w)) if join => w.push(u),
+                        _ => items.push(Item::Word(vec![u])),
+                    }
+                }
+                SplitResult2::WordStart(i, c, id) | SplitResult2::WordNext(i, c, id) => {
+                    if join && word.is_empty() {
+                        if let Some(Item::Word(w)) = items.pop() {
+                            word = w;
+                        }
+                    }
+                    let u = unit(mgr, i, c, id, false);
+                    // 单词内相邻字形的字距调整（同一字体）， 塑形得到的步进已包含字距调整
+                    if shaped.is_none() {
+                        if let (Some(prev), Some(id)) = (word.last_mut(), id) {
+                            if let (Some(prev_id), true) = (prev.glyph_id, prev.span == s) {
+                                prev.width += mgr.kern(f, prev_id, id);
+                            }
+                        }
+                    }
+                    word.push(u);
+                }
+                SplitResult2::WordEnd(_) => items.push(Item::Word(std::mem::take(&mut word))),
+            }
+            join = false;
+        }
+        byte_offset += text.len();
+        char_offset += text.chars().count();
+    }
+
+    let mut lines = wrap_lines(items, max_width, white_space);
+
+    // 文本溢出省略， 省略号使用截断处文字段的字体
+    if style.text_overflow == TextOverflow::Ellipsis && max_width.is_finite() {
+        truncate_lines(&mut lines, max_width, |line| {
+            let s = line.units.last().map_or(line.span, |r| r.span);
+            let glyph_id = mgr.glyph_id(spans[s].font, '…');
+            let width = measure(mgr, &metrics[s], glyph_id, false);
+            Unit { span: s, index: usize::MAX, chars: 0..0, bytes: 0..0, char: '…', glyph_id, glyphs: vec![PlacedGlyph { glyph_id, x: 0.0, y: 0.0 }], width, is_space: false }
+        });
+    }
+
+    // 对齐及按行重排为视觉顺序
+    let avail = if max_width.is_finite() {
+        max_width
+    } else {
+        lines.iter().map(|r| r.content_width()).fold(0.0, f32::max)
+    };
+    let mut r = TextLayout::default();
+    let line_count = lines.len();
+    for (i, line) in lines.into_iter().enumerate() {
+        let content_width = line.content_width();
+        let free = (avail - content_width).max(0.0);
+        let start = if style.is_reverse { free } else { 0.0 };
+        let (offset, justify) = match style.align {
+            TextAlign::Start => (start, 0.0),
+            TextAlign::End => (free - start, 0.0),
+            TextAlign::Left => (0.0, 0.0),
+            TextAlign::Right => (free, 0.0),
+            TextAlign::Center => (free / 2.0, 0.0),
+            TextAlign::Justify => {
+                let spaces = line.units.iter().filter(|r| r.is_space).count();
+                if line.hard_break || i + 1 == line_count || spaces == 0 {
+                    (start, 0.0)
+                } else {
+                    (0.0, free / spaces as f32)
+                }
+            }
+        };
+
+        let mut units: Vec<(Unit, bool)> = Vec::with_capacity(line.units.len() + 1);
+        if let (Some(first), Some(last)) = (line.units.first(), line.units.last()) {
+            let range = first.chars.start..last.chars.end;
+            let levels = bidi.line_levels(range.clone());
+            let map = BidiMap::new(line.units.iter().map(|u| levels[u.chars.start - range.start]).collect());
+            let mut ordered: Vec<(usize, Unit, bool)> = line.units.into_iter().enumerate().map(|(i, u)| (map.visual(i), u, map.is_rtl(i))).collect();
+            ordered.sort_by_key(|r| r.0);
+            units.extend(ordered.into_iter().map(|r| (r.1, r.2)));
+        }
+        if let Some(ellipsis) = line.ellipsis {
+            if style.is_reverse {
+                units.insert(0, (ellipsis, true));
+            } else {
+                units.push((ellipsis, false));
+            }
+        }
+
+        // 基线对齐
+        let (mut above, mut below) = (0.0f32, 0.0f32);
+        let mut used: Vec<usize> = units.iter().map(|u| u.0.span).collect();
+        if used.is_empty() {
+            used.push(line.span);
+        }
+        for s in used.iter() {
+            let (a, b) = metrics[*s].extent();
+            above = above.max(a);
+            below = below.max(b);
+        }
+        let y = r.height;
+        let baseline = y + above;
+
+        let glyph_start = r.glyphs.len();
+        let mut x = offset;
+        for (unit, rtl) in units {
+            let width = if unit.is_space { unit.width + justify } else { unit.width };
+            let m = &metrics[unit.span];
+            r.glyphs.push(GlyphQuad {
+                glyph_id: unit.glyph_id,
+                glyphs: unit.glyphs,
+                char: unit.char,
+                span: unit.span,
+                range: unit.bytes,
+                rtl,
+                index: unit.index,
+                x,
+                y: baseline - m.ascent,
+                width,
+                height: m.font_height,
+            });
+            x += width;
+        }
+        let width = if justify > 0.0 { avail } else { content_width };
+        let height = above + below;
+        r.lines.push(LineBox {
+            glyphs: glyph_start..r.glyphs.len(),
+            range: line.range,
+            x: offset,
+            y,
+            width,
+            height,
+            baseline,
+        });
+        r.width = r.width.max(width);
+        r.height += height;
+    }
+    r
+}
+
+// 换行
+//
+// 单词放不下时在单词前换行， 比最大宽度还长的单词在单词内换行； 不自动换行时只在换行符处换行
+fn wrap_lines(items: Vec<Item>, max_width: f32, white_space: WhiteSpace) -> Vec<Line> {
+    let wrap = white_space.wrap();
+    let mut lines = Vec::new();
+    let mut line = Line::default();
+    let mut soft_wrapped = false;
+    // 结束当前行， 下一行从next_start（默认为本行末尾）开始
+    let finish = |lines: &mut Vec<Line>, line: &mut Line, hard_break: bool, span: usize, next_start: Option<usize>| {
+        if white_space.collapse() {
+            while line.units.last().is_some_and(|r| r.is_space) {
+                line.units.pop();
+            }
+        }
+        line.hard_break = hard_break;
+        line.span = span;
+        // 自动换行后被丢弃的行首空白不属于本行
+        if let Some(first) = line.units.first() {
+            line.range.start = first.bytes.start;
+        }
+        line.range.end = line.units.last().map_or(line.range.start, |r| r.bytes.end);
+        let start = next_start.unwrap_or(line.range.end);
+        lines.push(std::mem::replace(line, Line { range: start..start, ..Default::default() }));
+    };
+    let mut span = 0;
+    for item in items {
+        match item {
+            Item::Newline(s, bytes) => {
+                finish(&mut lines, &mut line, true, s, Some(bytes.end));
+                soft_wrapped = false;
+            }
+            Item::Space(unit) => {
+                span = unit.span;
+                // 自动换行后， 行首的可合并空白被丢弃
+                if white_space.collapse() && soft_wrapped && line.units.is_empty() {
+                    continue;
+                }
+                line.units.push(unit);
+            }
+            Item::Word(units) => {
+                span = units.last().map_or(span, |r| r.span);
+                let width = units.iter().map(|r| r.width).sum::<f32>();
+                // 单词之前的空白也占用本行宽度（换行后才被丢弃）
+                if wrap && !line.units.is_empty() && line.width() + width > max_width {
+                    finish(&mut lines, &mut line, false, span, None);
+                    soft_wrapped = true;
+                }
+                if wrap && width > max_width {
+                    // 单词比最大宽度还长， 在单词内换行
+                    for unit in units {
+                        if !line.units.is_empty() && line.width() + unit.width > max_width {
+                            finish(&mut lines, &mut line, false, span, None);
+                            soft_wrapped = true;
+                        }
+                        line.units.push(unit);
+                    }
+                } else {
+                    line.units.extend(units);
+                }
+            }
+        }
+    }
+    finish(&mut lines, &mut line, true, span, None);
+    lines
+}
+
+// 文本溢出省略
+//
+// 超出最大宽度的行， 从行尾逐个移除排版单元（及行尾空白）， 直到放得下省略号
+// ellipsis为当前行创建省略号单元（宽度与行尾单元所在的文字段有关）， 其字节范围在此设置为被省略文字的范围
+fn truncate_lines<F: FnMut(&Line) -> Unit>(lines: &mut [Line], max_width: f32, mut ellipsis: F) {
+    for line in lines.iter_mut() {
+        if line.content_width() <= max_width {
+            continue;
+        }
+        loop {
+            let mut unit = ellipsis(line);
+            let fits = line.content_width() + unit.width <= max_width;
+            match line.units.last() {
+                Some(last) if !fits || last.is_space => {
+                    line.units.pop();
+                }
+                _ => {
+                    let start = line.units.last().map_or(line.range.start, |r| r.bytes.end);
+                    unit.bytes = start..line.range.end;
+                    line.ellipsis = Some(unit);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 排版单元， 每个字节宽10像素， 空白宽5像素
+    fn unit(bytes: Range<usize>, is_space: bool) -> Unit {
+        let width = if is_space { 5.0 } else { bytes.len() as f32 * 10.0 };
+        Unit { span: 0, index: 0, chars: bytes.clone(), bytes, char: 'a', glyph_id: None, glyphs: Vec::new(), width, is_space }
+    }
+
+    // 按空格及换行符拆分为排版单元， 单词中每个字节一个单元
+    fn items(text: &str) -> Vec<Item> {
+        let mut r = Vec::new();
+        let mut word = Vec::new();
+        for (i, c) in text.char_indices() {
+            if c == ' ' || c == '\n' {
+                if !word.is_empty() {
+                    r.push(Item::Word(std::mem::take(&mut word)));
+                }
+                r.push(if c == ' ' { Item::Space(unit(i..i + 1, true)) } else { Item::Newline(0, i..i + 1) });
+            } else {
+                word.push(unit(i..i + 1, false));
+            }
+        }
+        if !word.is_empty() {
+            r.push(Item::Word(word));
+        }
+        r
+    }
+
+    fn ranges(lines: &[Line]) -> Vec<Range<usize>> {
+        lines.iter().map(|r| r.range.clone()).collect()
+    }
+
+    #[test]
+    fn wrap_before_word() {
+        let lines = wrap_lines(items("aa bb cc"), 45.0, WhiteSpace::Normal);
+        assert_eq!(ranges(&lines), vec![0..5, 6..8]);
+        assert!(!lines[0].hard_break);
+        assert!(lines[1].hard_break);
+    }
+
+    #[test]
+    fn wrap_counts_space_before_word() {
+        // "aa bb"含空白宽45， 超过44时"bb"换到下一行
+        let lines = wrap_lines(items("aa bb"), 44.0, WhiteSpace::Normal);
+        assert_eq!(ranges(&lines), vec![0..2, 3..5]);
+        assert!(lines.iter().all(|r| r.width() <= 44.0));
+    }
+
+    #[test]
+    fn wrap_inside_long_word() {
+        let lines = wrap_lines(items("abcdef"), 25.0, WhiteSpace::Normal);
+        assert_eq!(ranges(&lines), vec![0..2, 2..4, 4..6]);
+    }
+
+    #[test]
+    fn no_wrap() {
+        let lines = wrap_lines(items("aa bb cc"), 10.0, WhiteSpace::NoWrap);
+        assert_eq!(ranges(&lines), vec![0..8]);
+        let lines = wrap_lines(items("aa bb\ncc"), 10.0, WhiteSpace::Pre);
+        assert_eq!(ranges(&lines), vec![0..5, 6..8]);
+        assert!(lines[0].hard_break);
+    }
+
+    #[test]
+    fn collapse_line_edges() {
+        // 自动换行后行首的空白被丢弃， 行尾空白被移除
+        let mut items = items("aa bb");
+        items.insert(2, Item::Space(unit(2..3, true)));
+        let lines = wrap_lines(items, 25.0, WhiteSpace::Normal);
+        assert_eq!(ranges(&lines), vec![0..2, 3..5]);
+        assert!(lines.iter().all(|r| r.units.iter().all(|u| !u.is_space)));
+    }
+
+    #[test]
+    fn pre_wrap_keeps_spaces() {
+        let lines = wrap_lines(items("aa  "), 100.0, WhiteSpace::PreWrap);
+        assert_eq!(lines[0].units.len(), 4);
+        assert_eq!(lines[0].width(), 30.0);
+        assert_eq!(lines[0].content_width(), 20.0);
+    }
+
+    #[test]
+    fn empty_lines_between_newlines() {
+        let lines = wrap_lines(items("a\n\nb"), 100.0, WhiteSpace::PreLine);
+        assert_eq!(ranges(&lines), vec![0..1, 2..2, 3..4]);
+        assert!(lines[1].units.is_empty());
+    }
+
+    #[test]
+    fn white_space_flags() {
+        assert!(WhiteSpace::Normal.wrap() && WhiteSpace::Normal.collapse() && !WhiteSpace::Normal.preserve_newline());
+        assert!(!WhiteSpace::NoWrap.wrap() && WhiteSpace::NoWrap.collapse());
+        assert!(!WhiteSpace::Pre.wrap() && !WhiteSpace::Pre.collapse() && WhiteSpace::Pre.preserve_newline());
+        assert!(WhiteSpace::PreWrap.wrap() && !WhiteSpace::PreWrap.collapse());
+        assert!(WhiteSpace::PreLine.wrap() && WhiteSpace::PreLine.collapse() && WhiteSpace::PreLine.preserve_newline());
+    }
+
+    #[test]
+    fn ellipsis() {
+        let mut lines = wrap_lines(items("abcdef gh"), 45.0, WhiteSpace::NoWrap);
+        let ellipsis = |_: &Line| Unit { char: '…', ..unit(0..1, false) };
+        truncate_lines(&mut lines, 45.0, ellipsis);
+        let line = &lines[0];
+        // 3个字符加省略号宽40， 放下第4个字符时超出
+        assert_eq!(line.units.len(), 3);
+        assert_eq!(line.ellipsis.as_ref().map(|r| r.bytes.clone()), Some(3..9));
+        assert!(line.content_width() <= 45.0);
+    }
+
+    #[test]
+    fn ellipsis_drops_trailing_space() {
+        let mut lines = wrap_lines(items("ab cdef"), 35.0, WhiteSpace::NoWrap);
+        truncate_lines(&mut lines, 35.0, |_| unit(0..1, false));
+        // 移除"cdef"后， 行尾空白也被移除， 省略号紧跟"ab"
+        assert_eq!(lines[0].units.len(), 2);
+        assert_eq!(lines[0].ellipsis.as_ref().map(|r| r.bytes.clone()), Some(2..7));
+    }
+
+    #[test]
+    fn ellipsis_skips_fitting_lines() {
+        let mut lines = wrap_lines(items("ab"), 35.0, WhiteSpace::NoWrap);
+        truncate_lines(&mut lines, 35.0, |_| unit(0..1, false));
+        assert!(lines[0].ellipsis.is_none());
+    }
+}
@@ -0,0 +1,183 @@
+//! 换行（UAX #14）
+//!
+//! 使用Unicode换行算法确定换行机会， 处理标点前后、CJK避头尾（kinsoku）、表情及不换行空格等规则
+//! 日文的严格程度可配置， 对应CSS的`line-break: strict | normal | loose`
+
+use unicode_linebreak::{break_property, linebreaks, BreakClass, BreakOpportunity};
+
+/// 换行严格程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LineBreakStrictness {
+    /// 在Normal的基础上， 允许CJK文字与迭代符号、连字符、省略号、前后缀符号之间换行
+    Loose,
+    /// 允许在小假名、长音符（CJ类）之前换行
+    #[default]
+    Normal,
+    /// 不允许在小假名、长音符之前换行（UAX #14默认规则）
+    Strict,
+}
+
+/// 换行机会类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BreakKind {
+    /// 必须换行（换行符之后， 及文本末尾）
+    Mandatory,
+    /// 可以换行
+    Allowed,
+}
+
+/// 计算换行机会
+///
+/// # 参数
+/// - `text`: 文本
+/// - `strictness`: 换行严格程度
+///
+/// # 返回值
+/// 按位置排列的换行机会， 位置为换行后下一个字符的字节偏移， 最后一项总是(text.len(), Mandatory)
+pub fn line_breaks(text: &str, strictness: LineBreakStrictness) -> Vec<(usize, BreakKind)> {
+    let mut breaks = linebreaks(text).map(|(i, r)| (i, match r {
+        BreakOpportunity::Mandatory => BreakKind::Mandatory,
+        BreakOpportunity::Allowed => BreakKind::Allowed,
+    })).collect::<Vec<(usize, BreakKind)>>();
+    if strictness == LineBreakStrictness::Strict {
+        return breaks;
+    }
+
+    // 放宽CJK文字中的规则
+    let mut prev: Option<BreakClass> = None;
+    let mut extra = Vec::new();
+    for (i, c) in text.char_indices() {
+        let class = break_property(c as u32);
+        if let Some(prev_class) = prev {
+            if allow_tailored(prev_class, c, class, strictness) {
+                extra.push(i);
+            }
+        }
+        prev = Some(class);
+    }
+    for i in extra {
+        if let Err(index) = breaks.binary_search_by_key(&i, |r| r.0) {
+            breaks.insert(index, (i, BreakKind::Allowed));
+        }
+    }
+    breaks
+}
+
+/// 由每个单元（字符或字形簇）的起始字节偏移， 生成每个单元之前的换行机会
+///
+/// # 参数
+/// - `starts`: 每个单元的起始字节偏移（递增）
+///
+/// # 返回值
+/// 长度为单元数加1， 第i项为第i个单元之前的换行机会， 最后一项为文本末尾
+pub fn break_table(text: &str, starts: impl Iterator<Item = usize>, strictness: LineBreakStrictness) -> Vec<Option<BreakKind>> {
+    let breaks = line_breaks(text, strictness);
+    let mut table = Vec::new();
+    let mut j = 0;
+    for start in starts {
+        while j < breaks.len() && breaks[j].0 < start {
+            j += 1;
+        }
+        // 第一个单元之前不换行
+        table.push(match breaks.get(j) {
+            Some(r) if r.0 == start && start > 0 => Some(r.1),
+            _ => None,
+        });
+    }
+    table.push(Some(BreakKind::Mandatory));
+    table
+}
+
+/// 是否为引起必须换行的字符（BK、CR、LF、NL类）
+pub fn is_mandatory_char(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\u{0B}' | '\u{0C}' | '\u{85}' | '\u{2028}' | '\u{2029}')
+}
+
+/// 是否为可在其后换行的空白（不换行空格等GL类空白除外）
+pub fn is_breaking_whitespace(c: char) -> bool {
+    c.is_whitespace() && !matches!(break_property(c as u32), BreakClass::NonBreakingGlue | BreakClass::WordJoiner)
+}
+
+// 是否为CJK文字（表意文字、假名、韩文音节）
+fn is_cjk_class(class: BreakClass) -> bool {
+    matches!(class, BreakClass::Ideographic | BreakClass::ConditionalJapaneseStarter
+        | BreakClass::HangulLvSyllable | BreakClass::HangulLvtSyllable)
+}
+
+// 按严格程度， 是否在两个字符之间额外允许换行
+fn allow_tailored(prev_class: BreakClass, c: char, class: BreakClass, strictness: LineBreakStrictness) -> bool {
+    if !(is_cjk_class(prev_class) || (strictness == LineBreakStrictness::Loose && is_cjk_class(class))) {
+        return false;
+    }
+    if is_cjk_class(prev_class) && class == BreakClass::ConditionalJapaneseStarter {
+        return true;
+    }
+    if strictness != LineBreakStrictness::Loose {
+        return false;
+    }
+    if is_cjk_class(prev_class) {
+        // 迭代符号、连字符、省略号、后缀符号之前
+        matches!(c, '々' | '〻' | 'ゝ' | 'ゞ' | 'ヽ' | 'ヾ' | '\u{2010}' | '\u{2013}' | '〜' | '゠')
+            || matches!(class, BreakClass::Inseparable | BreakClass::Postfix)
+    } else {
+        // 省略号、前缀符号之后
+        matches!(prev_class, BreakClass::Inseparable | BreakClass::Prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed(text: &str, strictness: LineBreakStrictness) -> Vec<usize> {
+        line_breaks(text, strictness).into_iter().filter(|r| r.1 == BreakKind::Allowed).map(|r| r.0).collect()
+    }
+
+    #[test]
+    fn breaks_after_spaces() {
+        assert_eq!(allowed("hello world", LineBreakStrictness::Normal), vec![6]);
+        assert_eq!(line_breaks("hello world", LineBreakStrictness::Normal).last(), Some(&(11, BreakKind::Mandatory)));
+        // 不换行空格不产生换行机会
+        assert!(allowed("a\u{a0}b", LineBreakStrictness::Normal).is_empty());
+    }
+
+    #[test]
+    fn mandatory_after_newline() {
+        let breaks = line_breaks("ab\ncd", LineBreakStrictness::Normal);
+        assert_eq!(breaks, vec![(3, BreakKind::Mandatory), (5, BreakKind::Mandatory)]);
+    }
+
+    #[test]
+    fn punctuation() {
+        // 逗号、句号之前不换行（避头）
+        assert_eq!(allowed("你好，世界。", LineBreakStrictness::Normal), vec![3, 9, 12]);
+        // 连字符之后可以换行
+        assert_eq!(allowed("well-known", LineBreakStrictness::Normal), vec![5]);
+    }
+
+    #[test]
+    fn strictness() {
+        // 小假名之前， Normal允许换行， Strict不允许
+        let text = "キャット";
+        assert!(allowed(text, LineBreakStrictness::Normal).contains(&3));
+        assert!(!allowed(text, LineBreakStrictness::Strict).contains(&3));
+        // 迭代符号之前只有Loose允许换行
+        let text = "人々";
+        assert!(!allowed(text, LineBreakStrictness::Normal).contains(&3));
+        assert!(allowed(text, LineBreakStrictness::Loose).contains(&3));
+    }
+
+    #[test]
+    fn table() {
+        let text = "ab cd";
+        let table = break_table(text, [0, 1, 2, 3, 4].into_iter(), LineBreakStrictness::Normal);
+        assert_eq!(table, vec![None, None, None, Some(BreakKind::Allowed), None, Some(BreakKind::Mandatory)]);
+    }
+
+    #[test]
+    fn chars() {
+        assert!(is_mandatory_char('\n') && is_mandatory_char('\u{2029}') && !is_mandatory_char(' '));
+        assert!(is_breaking_whitespace(' ') && is_breaking_whitespace('\u{3000}'));
+        assert!(!is_breaking_whitespace('\u{a0}') && !is_breaking_whitespace('a'));
+    }
+}
@@ -1,11 +1,23 @@
 pub mod font;
 pub mod text_pack;
 pub mod text_split;
+pub mod shape;
+pub mod bidi;
+pub mod line_break;
+pub mod fallback;
+pub mod synth;
+pub mod woff;
+pub mod collection;
+pub mod color_glyph;
+pub mod layout;
+pub mod caret;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod font_db;
 pub mod sdf_table;
 pub mod sdf2_table;
 pub mod blur;
 pub mod sdf_gpu;
-// pub mod bitmap_table;
+pub mod bitmap_table;
 pub(crate) mod tables;
 
 #[cfg(feature = "create_class_by_str")]
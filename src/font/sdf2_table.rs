@@ -7,7 +7,6 @@
 //! - 多线程异步渲染管线
 //! - GPU加速计算
 
-use crate::font::text_split::text_split;
 use crate::font_brush::CellInfo;
 use crate::font_brush::LayoutInfo;
 use ordered_float::NotNan;
@@ -25,7 +24,7 @@ use std::sync::atomic::AtomicUsize;
 /// 用圆弧曲线模拟字符轮廓，并用于计算距离值的方案
 use std::{
     cell::OnceCell,
-    collections::{hash_map::Entry, HashMap},
+    collections::HashMap,
     hash::{DefaultHasher, Hash, Hasher},
     mem::transmute,
     sync::{
@@ -61,22 +60,26 @@ use super::text_split::SplitChar2;
 // use super::sdf_gpu::gpu_draw;
 use super::{
     blur::{blur_box, gaussian_blur},
+    collection::extract_face,
+    color_glyph::{is_color_glyph, ColorAtlas, COLOR_FONT_SIZE},
     font::{
-        Block, FontFaceId, FontFamilyId, FontId, FontImage, FontInfo, Glyph, GlyphId, GlyphIdDesc,
+        Block, Font, FontFaceId, FontFamilyId, FontId, FontImage, FontInfo, FontStyle, Glyph, GlyphId, GlyphIdDesc,
         Size,
     },
     sdf_table::MetricsInfo,
-    text_pack::TextPacker,
+    bidi::BidiText,
+    shape::{pair_kerning, shape_text, ShapedText},
+    synth::{FaceStyle, SynthOutline},
+    text_pack::{AtlasPos, DefragResult, PackerMove, PackerStats, TextPacker},
 };
 
 pub use crate::font_brush::TexInfo;
 use crate::{
     font_brush::{FontFace, SdfInfo2},
-    runtime::MULTI_MEDIA_RUNTIME,
+    runtime,
     stroe::{self, init_local_store},
-    svg::SvgInfo,
+    svg::{Path, PathVerb, SvgInfo},
 };
-use pi_async_rt::prelude::AsyncRuntime;
 // use pi_async_rt::prelude::serial::AsyncRuntime;
 
 static INTI_STROE_VALUE: Mutex<Vec<AsyncValue<()>>> = Mutex::new(Vec::new());
@@ -85,6 +88,8 @@ static GPU: RwLock<Option<GPUState>> = RwLock::new(None);
 static INTI_STROE: AtomicBool = AtomicBool::new(false);
 static IS_FIRST: AtomicBool = AtomicBool::new(true);
 pub static FONT_SIZE: usize = 32;
+/// sdf字号的档位， 从小到大， 第一档与`FONT_SIZE`相同
+pub static SDF_FONT_SIZES: [usize; 3] = [32, 64, 128];
 // pub static PXRANGE: u32 = 7;
 // /// 二维装箱
 // pub struct Packer2D {
@@ -92,38 +97,87 @@ pub static FONT_SIZE: usize = 32;
 // }
 
 // 此函数决将绘制的字体字号映射为需要的sdf字号（因为一些文字很大时， 小的sdf纹理， 不能满足其精度需求）
-pub fn sdf_font_size(_font_size: usize) -> usize {
-    FONT_SIZE
+// 字号不超过档位的1.5倍时使用该档位， 超过最大档位的1.5倍时使用最大档位
+pub fn sdf_font_size(font_size: usize) -> usize {
+    SDF_FONT_SIZES.iter()
+        .copied()
+        .find(|r| font_size <= r * 3 / 2)
+        .unwrap_or(SDF_FONT_SIZES[SDF_FONT_SIZES.len() - 1])
 }
 
 // 拓展0.5个单位防止采样到边
 static EXPAND: f32 = 0.5;
 // 超出5，以step步进增加
 static STEP: f32 = 3.0;
+// 描边宽度换算到sdf字号下的像素范围， sdf字号由`sdf_font_size`决定
+// 像素范围以字形的sdf字号（`Glyph::font_size`）为准， 绘制时换算到屏幕像素需乘以font_size / Glyph::font_size
 pub fn compute_px_range(font_info: &FontInfo) -> u32 {
-    let pxrange = f32::from(font_info.font.stroke) / font_info.font.font_size as f32 * FONT_SIZE as f32 + EXPAND;
-    let pxrange = if pxrange < 5.0 {
-         5
+    let sdf_size = sdf_font_size(font_info.font.font_size);
+    let pxrange = f32::from(font_info.font.stroke) / font_info.font.font_size as f32 * sdf_size as f32 + EXPAND;
+    if pxrange < 5.0 {
+        5
     } else {
         (((pxrange - 5.0) / STEP).ceil() * STEP + 5.0).round() as u32
-    };
-    log::error!("compute_px_range: stroke: {:?}, font_size: {}", font_info.font.stroke, font_info.font.font_size);
-    pxrange
+    }
+}
+
+// 字形的key： (字体, 字形索引, sdf字号, 像素范围, 字重, 样式, 宽度)
+// 每个sdf字号档位分别生成； 字重、样式、宽度不同时， 可变轴坐标或合成的粗体、斜体不同， 轮廓也不同
+type GlyphKey = (FontFaceId, u16, usize, u32, usize, FontStyle, usize);
+
+// 字体轮廓转化的圆弧数据
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "empty")))]
+type Arcs = CellInfo;
+#[cfg(all(target_arch = "wasm32", not(feature = "empty")))]
+type Arcs = Vec<u8>;
+
+/// 字形轮廓
+///
+/// 字体默认实例的字形由pi_sdf直接从字体中取得轮廓；
+/// 设置了可变轴坐标或需要合成粗体、斜体的字形（见`FaceStyle`）， 由ttf-parser按可变轴取得轮廓并变换后， 作为路径生成距离场
+pub enum GlyphOutline {
+    Face(OutlineInfo),
+    Styled(StyledOutline),
+}
+
+/// 按字体样式变换后的字形轮廓
+pub struct StyledOutline {
+    info: SvgInfo,
+    // 路径的大小（像素）， 路径已按sdf字号缩放（1em为sdf字号）， 生成距离场时每em的像素数与字体轮廓相同
+    size: usize,
+    // 变换后的包围盒（em的比例）， [x_min, y_min, x_max, y_max]
+    bbox: [f32; 4],
+    // 步进宽度（字体单位）
+    advance: f32,
 }
 
 pub struct Sdf2Table {
     pub fonts: SecondaryMap<DefaultKey, FontFace>, // DefaultKey为FontFaceId
+    datas: SecondaryMap<DefaultKey, (Share<Vec<u8>>, u32)>, // DefaultKey为FontFaceId， 值为字体文件数据及字体在集合中的索引， 用于塑形、字距调整及彩色字形
     pub metrics: SecondaryMap<DefaultKey, MetricsInfo>, // DefaultKey为FontFaceId
     pub max_boxs: SecondaryMap<DefaultKey, Aabb>,  // DefaultKey为FontId
     // text_infos: SecondaryMap<DefaultKey, TexInfo>,
 
     // blob_arcs: Vec<(BlobArc, HashMap<String, u64>)>,
-    glyph_id_map: XHashMap<(FontFaceId, u16, u32), GlyphId>,
+    glyph_id_map: XHashMap<GlyphKey, GlyphId>,
+    // 彩色字形， 与描边宽度无关， 不参与淘汰
+    color_glyph_map: XHashMap<(FontFaceId, u16), GlyphId>,
     pub glyphs: SlotMap<DefaultKey, GlyphIdDesc>,
+    // (字体, 左侧字形索引, 右侧字形索引)， 字距调整缓存
+    kerns: XHashMap<(FontFaceId, u16, u16), f32>,
 
     pub(crate) index_packer: TextPacker,
     pub data_packer: TextPacker,
-    pub outline_info: XHashMap<(DefaultKey, u16, u32), OutlineInfo>,
+    // 彩色字形的RGBA图集， 以`COLOR_FONT_SIZE`光栅化
+    pub(crate) color: ColorAtlas,
+    pub outline_info: XHashMap<GlyphKey, GlyphOutline>,
+
+    // 当前帧，用于记录字形最后使用的帧
+    frame: usize,
+    // 字形使用信息， DefaultKey为GlyphId
+    glyph_usage: SecondaryMap<DefaultKey, GlyphUsage>,
+    // 被淘汰的字形，等待外部取走
+    evicted: Vec<GlyphId>,
 
     // 字体阴影参数， u32: 模糊半径; NotNan<f32>: 粗体正常和细体
     pub font_shadow: XHashMap<GlyphId, Vec<(u32, NotNan<f32>)>>,
@@ -151,6 +205,17 @@ pub struct Sdf2Table {
     pub shapes_outer_glow_tex_info: XHashMap<(u64, u32), SvgTexInfo>,
 }
 
+/// 字形使用信息，用于纹理空间不足时淘汰最久未使用的字形
+#[derive(Debug)]
+struct GlyphUsage {
+    // glyph_id_map中的key
+    key: GlyphKey,
+    // 最后使用的帧
+    last_used: usize,
+    // 在index_packer中分配的区域的位置，包括字形本身及其阴影、外发光
+    regions: Vec<AtlasPos>,
+}
+
 /// SDF类型枚举
 #[derive(Debug)]
 pub enum SdfType {
@@ -168,6 +233,8 @@ pub struct SvgTexInfo {
     pub y: f32,
     pub width: usize,
     pub height: usize,
+    /// 所在纹理页
+    pub page: usize,
     layout: LayoutInfo,
 }
 
@@ -182,7 +249,7 @@ impl Sdf2Table {
                 *GPU.write().unwrap() = Some(GPUState::init(device, queue));
             }
             
-            let _ = MULTI_MEDIA_RUNTIME.spawn(async move {
+            let _ = runtime::spawn_media("sdf2_init_store", async move {
                 if let Some(buf) = init_local_store().await {
                     let mut map: HashMap<String, Vec<u8>> = HashMap::new();
                     if !buf.is_empty(){
@@ -205,18 +272,30 @@ impl Sdf2Table {
             });
         }
 
+        Self::with_size(width, height)
+    }
+
+    // 创建空的字体表， 不初始化GPU及本地存储
+    fn with_size(width: usize, height: usize) -> Self {
         Self {
             fonts: Default::default(),
+            datas: Default::default(),
             metrics: Default::default(),
             max_boxs: Default::default(),
             // text_infos: Default::default(),
             // blob_arcs: Default::default(),
             glyph_id_map: XHashMap::default(),
+            color_glyph_map: XHashMap::default(),
             glyphs: SlotMap::default(),
+            kerns: XHashMap::default(),
             outline_info: XHashMap::default(),
+            frame: 0,
+            glyph_usage: SecondaryMap::default(),
+            evicted: Vec::new(),
             // base_glyphs: SlotMap<DefaultKey, BaseCharDesc>,
             index_packer: TextPacker::new(width, height),
             data_packer: TextPacker::new(width, height),
+            color: ColorAtlas::new(width, height),
             // size: Size {
             // 	width,
             // 	height
@@ -251,10 +330,46 @@ impl Sdf2Table {
         }
     }
 
-    // 添加字体
-    pub fn add_font(&mut self, font_id: FontFaceId, buffer: Share<Vec<u8>>) {
+    /// 清空纹理中的所有内容（文字、阴影、外发光、svg、box_shadow）
+    /// 已加载的字体及其度量信息保留，之前分配的GlyphId全部失效
+    pub fn clear(&mut self) {
+        self.index_packer.clear();
+        self.data_packer.clear();
+        self.color.clear();
+        self.glyph_id_map.clear();
+        self.color_glyph_map.clear();
+        self.glyphs.clear();
+        self.outline_info.clear();
+        self.glyph_usage.clear();
+        self.evicted.clear();
+
+        self.font_shadow.clear();
+        self.font_outer_glow.clear();
+        self.font_shadow_info.clear();
+        self.font_outer_glow_info.clear();
+
+        self.bboxs.clear();
+        self.shapes.clear();
+        self.shapes_shadow.clear();
+        self.shapes_outer_glow.clear();
+        self.shapes_tex_info.clear();
+        self.shapes_shadow_tex_info.clear();
+        self.shapes_outer_glow_tex_info.clear();
+    }
+
+    // 添加字体， index为字体在字体集合中的索引， 不是字体集合时为0
+    // pi_sdf的FontFace只能读取数据中的第一个字体， 索引不为0时， 为其提取该字体的表作为单独的字体数据， 提取失败返回false
+    pub fn add_font(&mut self, font_id: FontFaceId, buffer: Share<Vec<u8>>, index: u32) -> bool {
+        let face_buffer = match index {
+            0 => buffer.clone(),
+            _ => match extract_face(&buffer, index) {
+                Some(r) => Share::new(r),
+                None => return false,
+            },
+        };
+        self.datas.insert(font_id.0, (buffer, index));
         // #[cfg(all(not(target_arch="wasm32"), not(feature="empty")))]
-        let face = FontFace::new(buffer);
+        let face = FontFace::new(face_buffer);
         // #[cfg(all(target_arch="wasm32", not(feature="empty")))]
         let ascender = face.ascender();
         let descender = face.descender();
@@ -263,6 +378,7 @@ impl Sdf2Table {
         self.metrics.insert(
             font_id.0,
             MetricsInfo {
+                // 度量信息按字体记录， 均为font_size的百分比， 与sdf档位无关； 字形实际的sdf字号见`Glyph::font_size`
                 font_size: FONT_SIZE as f32,
                 distance_range: 0.0 as f32,
                 line_height: height,
@@ -285,6 +401,7 @@ impl Sdf2Table {
                 Point::new(max_box[2], max_box[3]),
             ),
         );
+        true
     }
 
     // 文字高度
@@ -303,11 +420,11 @@ impl Sdf2Table {
     }
 
     pub fn metrics(&self, glyph_id: GlyphId, font: &FontInfo) -> Option<&MetricsInfo> {
-        let glyph = &self.glyphs[glyph_id.0];
+        let glyph = self.glyphs.get(glyph_id.0)?;
         if glyph.font_face_index.is_null() {
             return None;
         } else {
-            let face_id = font.font_ids[glyph.font_face_index].0;
+            let face_id = font.font_ids.get(glyph.font_face_index)?.0;
             if let Some(r) = self.metrics.get(face_id) {
                 return Some(r);
             } else {
@@ -327,79 +444,55 @@ impl Sdf2Table {
     // 文字宽度
     pub fn width(&mut self, font_id: FontId, font: &mut FontInfo, char: char) -> (f32, GlyphId) {
         if let Some(glyph_id) = self.glyph_id(font_id, font, char) {
-            if glyph_id.0.is_null(){
-                return (font.font.font_size as f32 * 0.5, glyph_id,);
-            }
-            if self.glyphs[glyph_id.0].font_face_index.is_null() {
-                for (index, font_id) in font.font_ids.iter().enumerate() {
-                    if let Some(r) = self.fonts.get_mut(font_id.0) {
-                        let horizontal_advance = r.horizontal_advance(self.glyphs[glyph_id.0].char);
-                        if horizontal_advance >= 0.0 {
-                            self.glyphs[glyph_id.0].font_face_index = index;
-                            log::debug!(
-                                "mesure char width first, char: {}, font_id: {:?}, width: {:?}",
-                                char,
-                                font_id,
-                                (
-                                    horizontal_advance,
-                                    font.font.font_size as f32,
-                                    horizontal_advance * font.font.font_size as f32
-                                )
-                            );
-                            self.glyphs[glyph_id.0].glyph.advance = horizontal_advance;
-                            return (horizontal_advance * font.font.font_size as f32, glyph_id);
-                        }
-                    };
-                }
-            } else {
-                return (
-                    self.glyphs[glyph_id.0].glyph.advance * font.font.font_size as f32,
-                    glyph_id,
-                );
-            }
+            return (self.width_of_glyph_id(font_id, font, glyph_id), glyph_id);
         }
 
         return (0.0, GlyphId(DefaultKey::null()));
     }
 
-    // 文字宽度
-    pub fn width_of_glyph_id(&mut self, font_id: FontId, font: &mut FontInfo, glyph_id: GlyphId) -> f32 {
-        if glyph_id.0.is_null(){
-            return  0.5 * font.font.font_size as f32;
-        }
-        let glyph = &mut self.glyphs[glyph_id.0];
-        if glyph.font_face_index.is_null() {
-            for (index, font_id) in font.font_ids.iter().enumerate() {
-                if let Some(r) = self.fonts.get_mut(font_id.0) {
-                    
-                    let horizontal_advance = r.horizontal_advance_of_glyph_index(glyph.glyph_index);
-                    // println!("======= {} width: {}", glyph.char, horizontal_advance);
-                    if horizontal_advance >= 0.0 {
-                        glyph.font_face_index = index;
-                        log::debug!(
-                            "mesure char width first, char: {}, font_id: {:?}, width: {:?}",
-                            glyph.char,
-                            font_id,
-                            (
-                                horizontal_advance,
-                                font.font.font_size as f32,
-                                horizontal_advance * font.font.font_size as f32
-                            )
-                        );
-                        self.glyphs[glyph_id.0].glyph.advance = horizontal_advance;
-                        return horizontal_advance * font.font.font_size as f32;
-                    }
-                };
+    /// 两个相邻字形之间的字距调整（像素）
+    ///
+    /// 读取字体GPOS的成对调整或kern表， 两个字形不在同一字体中时为0
+    pub fn kern(&mut self, font: &FontInfo, left: GlyphId, right: GlyphId) -> f32 {
+        let (l, r) = match (self.glyphs.get(left.0), self.glyphs.get(right.0)) {
+            (Some(l), Some(r)) if !l.font_face_index.is_null() && l.font_face_index == r.font_face_index => (l, r),
+            _ => return 0.0,
+        };
+        let face_id = match font.font_ids.get(l.font_face_index) {
+            Some(r) => *r,
+            None => return 0.0,
+        };
+        let key = (face_id, l.glyph_index, r.glyph_index);
+        let kern = match self.kerns.get(&key) {
+            Some(r) => *r,
+            None => {
+                let kern = self.datas.get(face_id.0).map_or(0.0, |(data, index)| pair_kerning(data, *index, key.1, key.2));
+                self.kerns.insert(key, kern);
+                kern
             }
+        };
+        kern * font.font.font_size as f32
+    }
+
+    // 文字宽度
+    // 步进宽度在分配GlyphId时已按字形所在的字体取得
+    pub fn width_of_glyph_id(&mut self, _font_id: FontId, font: &mut FontInfo, glyph_id: GlyphId) -> f32 {
+        // 空的或已失效（纹理清空后被回收）的GlyphId， 按半个字号处理
+        match self.glyphs.get(glyph_id.0) {
+            Some(r) => r.glyph.advance * font.font.font_size as f32,
+            None => 0.5 * font.font.font_size as f32,
         }
-        return  self.glyphs[glyph_id.0].glyph.advance * font.font.font_size as f32;
     }
 
-    pub fn glyph_id_desc(&self, glyph_id: GlyphId) -> &GlyphIdDesc {
-        &self.glyphs[glyph_id.0]
+    /// 字形描述， GlyphId为空或已失效时返回None
+    pub fn glyph_id_desc(&self, glyph_id: GlyphId) -> Option<&GlyphIdDesc> {
+        self.glyphs.get(glyph_id.0)
     }
 
     // 字形id
+    //
+    // 按该字符所属文字的后备顺序（见`FaceFallback`）查找含有该字符的字体， 都不存在时， 依次使用'□'、' '代替
+    // 字体都未加载时返回None； 字体中不存在替代字符时， 返回空的GlyphId
     pub fn glyph_id(
         &mut self,
         font_id: FontId,
@@ -407,99 +500,132 @@ impl Sdf2Table {
         char: char,
     ) -> Option<GlyphId> {
         // log::error!("glyph_id: {:?}",(&font_id, char));
-        for (_index, font_face_id) in font_info.font_ids.iter().enumerate() {
-            if let Some(font_face) = self.fonts.get_mut(font_face_id.0) {
-                let mut glyph_index = font_face.glyph_index(char);
-                let mut char = char;
-                if glyph_index == 0 {
-                    log::warn!("{:?} is not have {}", font_info.font.font_family_string.as_str(), char);
-                }
-                if glyph_index == 0 {
-                    char = '□';
-                    glyph_index = font_face.glyph_index('□');
-                }
-                if glyph_index == 0 {
-                    char = ' ';
-                    glyph_index = font_face.glyph_index(' ');
-                }
+        let order = font_info.fallback.order(char, font_info.font_ids.len());
+        let mut has_face = false;
+        for c in [char, '□', ' '] {
+            for index in order.iter().copied() {
+                let font_face_id = font_info.font_ids[index];
+                let font_face = match self.fonts.get_mut(font_face_id.0) {
+                    Some(r) => r,
+                    None => continue,
+                };
+                has_face = true;
+                let glyph_index = font_face.glyph_index(c);
 
-                // 字体中存在字符
+                // 字体中存在字符， 字形记录的是在后备顺序中选中的字体（index）
                 if glyph_index > 0 {
-                    let pxrange = compute_px_range(font_info);
-                    log::error!("compute_px_range: char: {}, font_size: {}, stroke_width: {}", char, font_info.font.font_size, font_info.font.stroke);
-                    match self.glyph_id_map.entry((*font_face_id, glyph_index, pxrange)) {
-                        Entry::Occupied(r) => {
-                            let id = r.get().clone();
-                            return Some(id);
-                        }
-                        Entry::Vacant(r) => {
-                            // 分配GlyphId
-                            let id = GlyphId(self.glyphs.insert(GlyphIdDesc {
-                                font_id,
-                                char,
-                                glyph_index,
-                                font_face_index: pi_null::Null::null(),
-                                glyph: Glyph::default(),
-                            }));
-
-                            // log::error!("glyph_id===============: {:p}", (font_id, char, id));
-
-                            // if self.glyphs[id.0].font_face_index.is_null() {
+                    return self.glyph_id_of_index(font_id, font_info, index, c, glyph_index);
+                }
+            }
+            if !has_face {
+                return None;
+            }
+            if c == char {
+                log::warn!("{:?} is not have {}", font_info.font.font_family_string.as_str(), char);
+            }
+        }
+        log::warn!("{:?} is not have ' ' or '□'", font_info.font.font_family_string.as_str());
+        Some(GlyphId(DefaultKey::null()))
+    }
 
-                            let outline_info = font_face.to_outline(char);
+    /// 取到字体列表中第`font_face_index`个字体的`glyph_index`字形对应的GlyphId
+    ///
+    /// 首次取时， 在纹理中为字形分配位置， 并放入等待队列
+    /// 纹理空间不足时返回None
+    pub fn glyph_id_of_index(
+        &mut self,
+        font_id: FontId,
+        font_info: &mut FontInfo,
+        font_face_index: usize,
+        char: char,
+        glyph_index: u16,
+    ) -> Option<GlyphId> {
+        let font_face_id = *font_info.font_ids.get(font_face_index)?;
+        if let Some(id) = self.color_glyph_map.get(&(font_face_id, glyph_index)) {
+            return Some(*id);
+        }
+        let key = glyph_key(font_face_id, glyph_index, font_info);
+        if let Some(id) = self.glyph_id_map.get(&key).copied() {
+            self.mark_used(id);
+            return Some(id);
+        }
+        if self.is_color_glyph(font_face_id, glyph_index) {
+            return self.insert_color_glyph(font_id, font_face_index, font_face_id, char, glyph_index);
+        }
+        let (outline, advance) = match self.styled_outline(font_face_id, &font_info.font, glyph_index, key.2) {
+            Some((Some(r), advance)) => (GlyphOutline::Styled(r), advance),
+            r => {
+                let font_face = self.fonts.get_mut(font_face_id.0)?;
+                // 空字形（如空格）没有轮廓， 只需要样式下的步进宽度
+                let advance = match r {
+                    Some((_, advance)) => advance,
+                    None => font_face.horizontal_advance_of_glyph_index(glyph_index).max(0.0),
+                };
+                (GlyphOutline::Face(font_face.to_outline_of_glyph_index(glyph_index)), advance)
+            }
+        };
+        self.insert_glyph(font_id, font_info, font_face_index, key, char, outline, advance)
+    }
 
-                            let LayoutInfo {
-                                atlas_bounds,
-                                tex_size,
-                                ..
-                            } = outline_info.compute_layout(FONT_SIZE, pxrange, pxrange);
-                            let offset = self
-                                .index_packer
-                                .alloc(tex_size as usize, tex_size as usize)?;
-                            let bbox = Aabb::new(
-                                Point::new(outline_info.bbox[0], outline_info.bbox[1]),
-                                Point::new(outline_info.bbox[2], outline_info.bbox[3]),
-                            );
-                            let plane_bounds = bbox.scaled(&Vector::new(
-                                1.0 / outline_info.units_per_em as f32,
-                                1.0 / outline_info.units_per_em as f32,
-                            ));
+    // 字体在字体外观上设置了可变轴坐标或需要合成粗体、斜体时， 按样式取得字形的轮廓（空字形为None）及步进宽度（font_size的百分比）
+    // 字体为字体外观的默认实例时返回None， 由pi_sdf取得轮廓
+    fn styled_outline(&self, font_face_id: FontFaceId, font: &Font, glyph_index: u16, sdf_size: usize) -> Option<(Option<StyledOutline>, f32)> {
+        let (data, index) = self.datas.get(font_face_id.0)?;
+        let mut face = ttf_parser::Face::parse(data, *index).ok()?;
+        let style = FaceStyle::new(&face, font);
+        if style.variations.is_empty() && !style.is_synthetic() {
+            return None;
+        }
+        style.apply(&mut face);
+
+        let units_per_em = face.units_per_em() as f32;
+        let id = ttf_parser::GlyphId(glyph_index);
+        let advance = face.glyph_hor_advance(id).unwrap_or(0) as f32;
+        // 合成粗体只加宽有步进宽度的字形， 与塑形一致
+        let advance = if advance > 0.0 { advance + style.embolden * units_per_em } else { advance };
+
+        let mut outline = SynthOutline::new();
+        let bbox = match face.outline_glyph(id, &mut outline).and_then(|_| outline.bounds(&style, units_per_em)) {
+            Some(r) => r,
+            None => return Some((None, advance / units_per_em)),
+        };
+        let scale = sdf_size as f32 / units_per_em;
+        let mut path = PathSink { scale, verbs: Vec::new(), points: Vec::new() };
+        outline.emit(&style, units_per_em, &mut path);
+        let size = ((bbox[2] - bbox[0]).max(bbox[3] - bbox[1]) * scale).ceil().max(1.0) as usize;
+        let outline = StyledOutline {
+            info: Path::new1(path.verbs, path.points).get_svg_info(),
+            size,
+            bbox: bbox.map(|r| r / units_per_em),
+            advance,
+        };
+        Some((Some(outline), advance / units_per_em))
+    }
 
-                            let glyph = Glyph {
-                                plane_min_x: plane_bounds.mins.x,
-                                plane_min_y: plane_bounds.mins.y,
-                                plane_max_x: plane_bounds.maxs.x,
-                                plane_max_y: plane_bounds.maxs.y,
-                                x: offset.x as f32 + atlas_bounds[0],
-                                y: offset.y as f32 + atlas_bounds[1],
-                                width: atlas_bounds[2] - atlas_bounds[0],
-                                height: atlas_bounds[3] - atlas_bounds[1],
-                                advance: outline_info.advance as f32,
-                            };
-                            self.glyphs[id.0].glyph = glyph;
-
-                            self.outline_info
-                                .insert((font_face_id.0, glyph_index, pxrange), outline_info);
-
-                            if !char.is_whitespace() {
-                                // 不是空白符， 才需要放入等待队列
-                                // log::error!("================ glyph_id: {:?}",( font_info.await_info.wait_list.len(), self.glyphs[id.0].char, char, id));
-                                font_info.await_info.wait_list.push(id);
-                            }
-                            let _ = r.insert(id).clone();
-                            return Some(id);
-                        }
-                    };
-                } else {
-                    log::warn!("{:?} is not have ' ' or '□'", font_info.font.font_family_string.as_str());
-                    return Some(GlyphId(DefaultKey::null()));
-                }
+    /// 塑形
+    ///
+    /// 使用字体的GSUB/GPOS表将文本转换为字形序列， 并为每个字形分配GlyphId
+    /// 所有字体中都不存在的字形， 使用'□'代替
+    pub fn shape(&mut self, font_id: FontId, font_info: &mut FontInfo, text: &str, is_reverse: bool) -> ShapedText {
+        let bidi = BidiText::new(text, Some(is_reverse));
+        let raw = {
+            let faces = font_info.font_ids.iter().map(|r| self.datas.get(r.0).map(|(data, index)| (data.as_slice(), *index))).collect::<Vec<_>>();
+            shape_text(&faces, text, &bidi, &font_info.fallback, &font_info.font, &[])
+        };
+        ShapedText::new(text, is_reverse, bidi, raw, |r, char| {
+            if r.glyph_index == 0 {
+                let id = self.glyph_id(font_id, font_info, '□');
+                r.x_advance = match id {
+                    Some(id) if !id.0.is_null() => self.glyphs[id.0].glyph.advance,
+                    _ => 0.5,
+                };
+                return id;
             }
-        }
-        None
+            self.glyph_id_of_index(font_id, font_info, r.font_face_index, char, r.glyph_index)
+        })
     }
 
-    // 字形id
+    // 字形id， 每个字形簇一个字符及其第一个字形的id， 按视觉顺序排列（每个段落一行）
     pub fn glyph_indexs(
         &mut self,
         font_id: FontId,
@@ -508,121 +634,256 @@ impl Sdf2Table {
         is_reverse: bool
     ) -> (String,Vec<Option<GlyphId>> ){
         log::debug!("glyph_indexs: {:?}",(&font_id, text));
-        let mut glyph_ids = vec![None; text.len()];
-        let mut str = text.to_string();
-        // if 
-        let mut is_loop = true;
-        for (_index, font_face_id) in font_info.font_ids.iter().enumerate() {
-            if !is_loop {
-                break;
-            }
-            if let Some(font_face) = self.fonts.get_mut(font_face_id.0) {
-                log::debug!("========= text_split: {}, is_reverse: {}", text, is_reverse);
-                str = text_split(text, is_reverse);
-                
-                let glyph_indexs = font_face.glyph_indexs(&str, 0);
-                log::debug!("========= glyph_indexs2: {:?}, is_reverse: {}", glyph_indexs, str);
-                assert_eq!(glyph_indexs.len(), text.chars().count());
-                let mut index = 0;
-                for (mut glyph_index, mut char) in glyph_indexs.into_iter().zip(text.chars()){
-                    log::debug!("========= glyph_index: {}, char: {}", glyph_index, char);
-                    if glyph_index == 0 {
-                        char = '□';
-                        glyph_index = font_face.glyph_index('□');
-                    }
-                    if glyph_index == 0 {
-                        char = ' ';
-                        glyph_index = font_face.glyph_index(' ');
-                    }
-                    // 字体中存在字符
-                    if glyph_index > 0 {
-                        let pxrange = compute_px_range(font_info);
-                        log::error!("compute_px_range: char: {}, font_size: {}, stroke_width: {}", char, font_info.font.font_size, font_info.font.stroke);
-                        match self.glyph_id_map.entry((*font_face_id, glyph_index, pxrange)) {
-                            Entry::Occupied(r) => {
-                                let id = r.get().clone();
-                                glyph_ids[index] = Some(id);
-                                if is_loop {
-                                    is_loop = false;
-                                }
-                            }
-                            Entry::Vacant(r) => {
-                                // 分配GlyphId
-                                let id = GlyphId(self.glyphs.insert(GlyphIdDesc {
-                                    font_id,
-                                    char,
-                                    glyph_index,
-                                    font_face_index: pi_null::Null::null(),
-                                    glyph: Glyph::default(),
-                                }));
+        self.shape(font_id, font_info, text, is_reverse).visual_glyph_ids()
+    }
 
-                                // log::error!("glyph_id===============: {:p}", (font_id, char, id));
+    /// 为字形在纹理中分配位置，创建GlyphId，并放入等待队列
+    /// font_face_index为字形所在字体在font_info字体列表中的索引，绘制、度量及字距调整都按它找到字体
+    /// advance为字形在字体样式下的步进宽度（font_size的百分比）
+    /// 纹理空间不足（淘汰后仍不足）时返回None
+    fn insert_glyph(
+        &mut self,
+        font_id: FontId,
+        font_info: &mut FontInfo,
+        font_face_index: usize,
+        key: GlyphKey,
+        char: char,
+        outline_info: GlyphOutline,
+        advance: f32,
+    ) -> Option<GlyphId> {
+        let (_, glyph_index, sdf_size, pxrange, ..) = key;
+        let LayoutInfo {
+            atlas_bounds,
+            tex_size,
+            ..
+        } = outline_info.compute_layout(sdf_size, pxrange, pxrange);
+        let offset = self.alloc_index(tex_size as usize, tex_size as usize)?;
+        let plane_bounds = outline_info.plane_bounds();
+
+        let glyph = Glyph {
+            plane_min_x: plane_bounds.mins.x,
+            plane_min_y: plane_bounds.mins.y,
+            plane_max_x: plane_bounds.maxs.x,
+            plane_max_y: plane_bounds.maxs.y,
+            x: offset.x as f32 + atlas_bounds[0],
+            y: offset.y as f32 + atlas_bounds[1],
+            width: atlas_bounds[2] - atlas_bounds[0],
+            height: atlas_bounds[3] - atlas_bounds[1],
+            advance,
+            font_size: sdf_size as f32,
+            page: offset.page,
+            color: false,
+        };
+        // 分配GlyphId
+        let id = GlyphId(self.glyphs.insert(GlyphIdDesc {
+            font_id,
+            char,
+            glyph_index,
+            font_face_index,
+            glyph,
+        }));
+        self.glyph_usage.insert(id.0, GlyphUsage {
+            key,
+            last_used: self.frame,
+            regions: vec![offset],
+        });
+        self.outline_info.insert(key, outline_info);
+
+        if !char.is_whitespace() {
+            // 不是空白符， 才需要放入等待队列
+            font_info.await_info.wait_list.push(id);
+        }
+        self.glyph_id_map.insert(key, id);
+        Some(id)
+    }
 
-                                // if self.glyphs[id.0].font_face_index.is_null() {
+    // 字形是否为彩色字形（COLR、CBDT、sbix）
+    fn is_color_glyph(&self, font_face_id: FontFaceId, glyph_index: u16) -> bool {
+        self.datas.get(font_face_id.0)
+            .and_then(|(data, index)| ttf_parser::Face::parse(data, *index).ok())
+            .is_some_and(|face| is_color_glyph(&face, glyph_index))
+    }
 
-                                let outline_info = font_face.to_outline_of_glyph_index(glyph_index);
+    /// 为彩色字形在彩色图集中分配位置，创建GlyphId，由`draw_color`光栅化
+    /// 与其他字形一样记录font_face_index， 度量及字距调整按它找到字体
+    /// 彩色图集空间不足时返回None
+    fn insert_color_glyph(
+        &mut self,
+        font_id: FontId,
+        font_face_index: usize,
+        font_face_id: FontFaceId,
+        char: char,
+        glyph_index: u16,
+    ) -> Option<GlyphId> {
+        let (data, index) = self.datas.get(font_face_id.0)?;
+        let face = ttf_parser::Face::parse(data, *index).ok()?;
+        let advance = face.glyph_hor_advance(ttf_parser::GlyphId(glyph_index)).unwrap_or(0) as f32 / face.units_per_em() as f32;
+        let glyph = self.color.alloc(data, *index, glyph_index, COLOR_FONT_SIZE, advance)?;
+        let id = GlyphId(self.glyphs.insert(GlyphIdDesc {
+            font_id,
+            char,
+            glyph_index,
+            font_face_index,
+            glyph,
+        }));
+        self.color_glyph_map.insert((font_face_id, glyph_index), id);
+        Some(id)
+    }
 
-                                let LayoutInfo {
-                                    atlas_bounds,
-                                    tex_size,
-                                    ..
-                                } = outline_info.compute_layout(FONT_SIZE, pxrange, pxrange);
-                                let offset = if let Some(r) = self
-                                    .index_packer
-                                    .alloc(tex_size as usize, tex_size as usize){
-                                        r
-                                    }else{
-                                        continue;
-                                    };
-                                let bbox = Aabb::new(
-                                    Point::new(outline_info.bbox[0], outline_info.bbox[1]),
-                                    Point::new(outline_info.bbox[2], outline_info.bbox[3]),
-                                );
-                                let plane_bounds = bbox.scaled(&Vector::new(
-                                    1.0 / outline_info.units_per_em as f32,
-                                    1.0 / outline_info.units_per_em as f32,
-                                ));
-
-                                let glyph = Glyph {
-                                    plane_min_x: plane_bounds.mins.x,
-                                    plane_min_y: plane_bounds.mins.y,
-                                    plane_max_x: plane_bounds.maxs.x,
-                                    plane_max_y: plane_bounds.maxs.y,
-                                    x: offset.x as f32 + atlas_bounds[0],
-                                    y: offset.y as f32 + atlas_bounds[1],
-                                    width: atlas_bounds[2] - atlas_bounds[0],
-                                    height: atlas_bounds[3] - atlas_bounds[1],
-                                    advance: outline_info.advance as f32,
-                                };
-                                self.glyphs[id.0].glyph = glyph;
-
-                                self.outline_info
-                                    .insert((font_face_id.0, glyph_index, pxrange), outline_info);
-
-                                if !char.is_whitespace() {
-                                    // 不是空白符， 才需要放入等待队列
-                                    // log::error!("================ glyph_id: {:?}",( font_info.await_info.wait_list.len(), self.glyphs[id.0].char, char, id));
-                                    font_info.await_info.wait_list.push(id);
-                                }
-                                let _ = r.insert(id).clone();
-                                glyph_ids[index] = Some(id);
-                                if is_loop {
-                                    is_loop = false;
-                                }
-                            }
-                        };
-                    } else {
-                        glyph_ids[index] = Some(GlyphId(DefaultKey::null()));
-                    }
-                    index += 1;
+    /// 绘制彩色字形
+    ///
+    /// 光栅化所有等待中的彩色字形，每个字形调用一次update，
+    /// FontImage为RGBA8数据（非预乘），Block为其在彩色图集中的位置
+    pub fn draw_color<F: FnMut(Block, FontImage)>(&mut self, update: F) {
+        self.color.draw(update);
+    }
+
+    /// 进入下一帧
+    /// 
+    /// 纹理空间不足时，只淘汰当前帧未使用过的字形；
+    /// 不调用此方法时，所有字形都视为在当前帧使用过，不会被淘汰
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// 标记字形在当前帧被使用
+    /// 
+    /// 外部缓存GlyphId（不经过glyph_id重新获取）时，每帧使用前需调用此方法，避免字形被淘汰
+    pub fn mark_used(&mut self, id: GlyphId) {
+        if let Some(r) = self.glyph_usage.get_mut(id.0) {
+            r.last_used = self.frame;
+        }
+    }
+
+    /// 取走自上次调用以来被淘汰的字形
+    /// 
+    /// 被淘汰的GlyphId已失效，外部需要重新通过glyph_id获取
+    pub fn take_evicted(&mut self) -> Vec<GlyphId> {
+        std::mem::take(&mut self.evicted)
+    }
+
+    /// 在index_packer中分配空间
+    /// 空间不足时，按最久未使用的顺序淘汰字形，直到分配成功或没有可淘汰的字形
+    /// 宽高为0时直接返回None，不淘汰字形
+    fn alloc_index(&mut self, width: usize, height: usize) -> Option<AtlasPos> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        loop {
+            if let Some(r) = self.index_packer.alloc(width, height) {
+                return Some(r);
+            }
+
+            let frame = self.frame;
+            let cold = self.glyph_usage.iter()
+                .filter(|(_, r)| r.last_used < frame)
+                .min_by_key(|(_, r)| r.last_used)
+                .map(|(k, _)| k);
+            match cold {
+                Some(k) => self.evict(GlyphId(k)),
+                None => return None,
+            }
+        }
+    }
+
+    /// index纹理的占用统计
+    pub fn index_packer_stats(&self) -> PackerStats {
+        self.index_packer.stats()
+    }
+
+    /// 整理index纹理的碎片
+    ///
+    /// 字形、阴影、外发光、svg的纹理坐标会更新到整理后的位置；
+    /// 整理后无法放入纹理的字形被淘汰（通过take_evicted取到），svg及box_shadow被移除，需要重新添加
+    ///
+    /// 调用方需要根据返回的moves，将纹理内容从原位置拷贝到新位置（区域可能重叠，建议拷贝到新纹理）
+    pub fn defrag(&mut self) -> DefragResult {
+        let result = self.index_packer.defrag();
+        let moves = result.moves.iter().map(|r| (r.from, r)).collect::<XHashMap<_, _>>();
+
+        // 字形及其阴影、外发光
+        let mut glyph_moves: XHashMap<GlyphId, Vec<&PackerMove>> = XHashMap::default();
+        let mut lost = Vec::new();
+        for (k, usage) in self.glyph_usage.iter_mut() {
+            let mut r = Vec::with_capacity(usage.regions.len());
+            for position in std::mem::take(&mut usage.regions) {
+                match moves.get(&position) {
+                    Some(m) => {
+                        usage.regions.push(m.to);
+                        r.push(*m);
+                    },
+                    None => lost.push(GlyphId(k)),
                 }
             }
+            if let Some(g) = self.glyphs.get_mut(k) {
+                move_glyph(&mut g.glyph, &r);
+            }
+            glyph_moves.insert(GlyphId(k), r);
         }
-        (str, glyph_ids)
+        for ((id, _, _), glyph) in self.font_shadow_info.iter_mut() {
+            if let Some(r) = glyph_moves.get(id) {
+                move_glyph(glyph, r);
+            }
+        }
+        for ((id, _), glyph) in self.font_outer_glow_info.iter_mut() {
+            if let Some(r) = glyph_moves.get(id) {
+                move_glyph(glyph, r);
+            }
+        }
+        for id in lost {
+            if self.glyphs.contains_key(id.0) {
+                self.evict(id);
+            }
+        }
+
+        // svg及box_shadow
+        let mut lost = Vec::new();
+        for (hash, info) in self.shapes_tex_info.iter_mut() {
+            if !move_svg(info, &result.moves) {
+                lost.push(*hash);
+            }
+        }
+        for ((hash, _), info) in self.shapes_shadow_tex_info.iter_mut().chain(self.shapes_outer_glow_tex_info.iter_mut()) {
+            if !move_svg(info, &result.moves) {
+                lost.push(*hash);
+            }
+        }
+        for hash in lost {
+            self.shapes.remove(&hash);
+            self.bboxs.remove(&hash);
+            self.shapes_shadow.remove(&hash);
+            self.shapes_outer_glow.remove(&hash);
+            self.shapes_tex_info.remove(&hash);
+            self.shapes_shadow_tex_info.retain(|k, _| k.0 != hash);
+            self.shapes_outer_glow_tex_info.retain(|k, _| k.0 != hash);
+        }
+
+        result
+    }
+
+    /// 淘汰字形，释放其（包括阴影、外发光）在纹理中的空间
+    fn evict(&mut self, id: GlyphId) {
+        if let Some(usage) = self.glyph_usage.remove(id.0) {
+            for position in usage.regions {
+                self.index_packer.dealloc(position);
+            }
+            self.glyph_id_map.remove(&usage.key);
+            self.outline_info.remove(&usage.key);
+        }
+        self.glyphs.remove(id.0);
+        self.font_shadow.remove(&id);
+        self.font_outer_glow.remove(&id);
+        self.font_shadow_info.retain(|k, _| k.0 != id);
+        self.font_outer_glow_info.retain(|k, _| k.0 != id);
+        log::debug!("evict glyph: {:?}", id);
+        self.evicted.push(id);
     }
 
+    /// 劈分文字， 按塑形后的字形簇劈分， 劈分结果中的索引为字形簇的索引（逻辑顺序）
+    /// 换行后通过`SplitChar2::line_map`取得每行的视觉顺序
     pub fn split<'a>(&mut self, font_id: FontId, font_info: &mut FontInfo, text: &'a str, word_split: bool, merge_whitespace: bool, is_reverse: bool) -> SplitChar2<'a>{
-        let (text2, glyph_ids) = self.glyph_indexs(font_id, font_info, text, is_reverse);
+        let shaped = self.shape(font_id, font_info, text, is_reverse);
+        let (text2, glyph_ids) = shaped.cluster_glyph_ids();
         let mut i = text.chars();
         let last = i.next();
         SplitChar2 {
@@ -634,6 +895,8 @@ impl Sdf2Table {
             merge_whitespace: merge_whitespace,
             last: last,
             type_id: 0,
+            shaped: Some(shaped),
+            line_breaks: None,
         }
     }
 
@@ -646,34 +909,48 @@ impl Sdf2Table {
         weight: NotNan<f32>,
     ) {
         // let id =  self.glyph_id_map.get((font_info.font_family_id, char));
-        if let Entry::Vacant(vacant_entry) = self.font_shadow_info.entry((id, radius, weight)) {
-            let c = &self.glyphs[id.0];
-            let font_face_id = font_info.font_ids[c.font_face_index];
+        if !self.font_shadow_info.contains_key(&(id, radius, weight)) {
+            // 使用阴影即使用字形，避免分配阴影空间时淘汰字形本身
+            self.mark_used(id);
+            // 字形已被淘汰（GlyphId失效）时不生成阴影
+            let c = match self.glyphs.get(id.0) {
+                Some(r) => r,
+                None => return,
+            };
+            // 彩色字形没有距离场， 不生成阴影
+            if c.glyph.color {
+                return;
+            }
+            let font_face_id = match font_info.font_ids.get(c.font_face_index) {
+                Some(r) => *r,
+                None => return,
+            };
             println!("add_font_shadow ============={:?}", (c.font_id.0, c.char));
-            let pxrange = compute_px_range(font_info);
-            let outline_info = self.outline_info.get(&(font_face_id.0, c.glyph_index, pxrange)).unwrap();
+            let key = glyph_key(font_face_id, c.glyph_index, font_info);
+            let (sdf_size, pxrange) = (key.2, key.3);
+            let outline_info = match self.outline_info.get(&key) {
+                Some(r) => r,
+                None => return,
+            };
 
             let LayoutInfo {
                 atlas_bounds,
                 tex_size,
                 ..
             } = outline_info.compute_layout(
-                FONT_SIZE,
+                sdf_size,
                 pxrange,
                 (radius as f32 + f32::from(weight) * 3.0) as u32 + 2,
             );
-            let offset = self
-                .index_packer
-                .alloc(tex_size as usize, tex_size as usize)
-                .unwrap();
-            let bbox = Aabb::new(
-                Point::new(outline_info.bbox[0], outline_info.bbox[1]),
-                Point::new(outline_info.bbox[2], outline_info.bbox[3]),
-            );
-            let plane_bounds = bbox.scaled(&Vector::new(
-                1.0 / outline_info.units_per_em as f32,
-                1.0 / outline_info.units_per_em as f32,
-            ));
+            let plane_bounds = outline_info.plane_bounds();
+            let advance = outline_info.advance();
+            let offset = match self.alloc_index(tex_size as usize, tex_size as usize) {
+                Some(r) => r,
+                None => {
+                    log::warn!("add_font_shadow fail, texture is full, glyph_id: {:?}", id);
+                    return;
+                }
+            };
             let glyph = Glyph {
                 plane_min_x: plane_bounds.mins.x,
                 plane_min_y: plane_bounds.mins.y,
@@ -683,9 +960,15 @@ impl Sdf2Table {
                 y: offset.y as f32 + atlas_bounds[1],
                 width: atlas_bounds[2] - atlas_bounds[0],
                 height: atlas_bounds[3] - atlas_bounds[1],
-                advance: outline_info.advance as f32,
+                advance,
+                font_size: sdf_size as f32,
+                page: offset.page,
+                color: false,
             };
-            vacant_entry.insert(glyph);
+            self.font_shadow_info.insert((id, radius, weight), glyph);
+            if let Some(r) = self.glyph_usage.get_mut(id.0) {
+                r.regions.push(offset);
+            }
 
             if let Some(v) = self.font_shadow.get_mut(&id) {
                 v.push((radius, weight));
@@ -697,30 +980,44 @@ impl Sdf2Table {
 
     // 字形id
     pub fn add_font_outer_glow(&mut self, id: GlyphId, font_info: &FontInfo, range: u32) {
-        if let Entry::Vacant(vacant_entry) = self.font_outer_glow_info.entry((id, range)) {
+        if !self.font_outer_glow_info.contains_key(&(id, range)) {
             println!("add_font_outer_glow======={:?}", range);
-            let c = &self.glyphs[id.0];
-            let font_face_id = font_info.font_ids[c.font_face_index];
-            let pxrange = compute_px_range(font_info);
-            let outline_info = self.outline_info.get(&(font_face_id.0, c.glyph_index, pxrange)).unwrap();
+            // 使用外发光即使用字形，避免分配外发光空间时淘汰字形本身
+            self.mark_used(id);
+            // 字形已被淘汰（GlyphId失效）时不生成外发光
+            let c = match self.glyphs.get(id.0) {
+                Some(r) => r,
+                None => return,
+            };
+            // 彩色字形没有距离场， 不生成外发光
+            if c.glyph.color {
+                return;
+            }
+            let font_face_id = match font_info.font_ids.get(c.font_face_index) {
+                Some(r) => *r,
+                None => return,
+            };
+            let key = glyph_key(font_face_id, c.glyph_index, font_info);
+            let sdf_size = key.2;
+            let outline_info = match self.outline_info.get(&key) {
+                Some(r) => r,
+                None => return,
+            };
 
             let LayoutInfo {
                 atlas_bounds,
                 tex_size,
                 ..
-            } = outline_info.compute_layout(FONT_SIZE, range, range);
-            let bbox = Aabb::new(
-                Point::new(outline_info.bbox[0], outline_info.bbox[1]),
-                Point::new(outline_info.bbox[2], outline_info.bbox[3]),
-            );
-            let plane_bounds = bbox.scaled(&Vector::new(
-                1.0 / outline_info.units_per_em as f32,
-                1.0 / outline_info.units_per_em as f32,
-            ));
-            let offset = self
-                .index_packer
-                .alloc(tex_size as usize, tex_size as usize)
-                .unwrap();
+            } = outline_info.compute_layout(sdf_size, range, range);
+            let plane_bounds = outline_info.plane_bounds();
+            let advance = outline_info.advance();
+            let offset = match self.alloc_index(tex_size as usize, tex_size as usize) {
+                Some(r) => r,
+                None => {
+                    log::warn!("add_font_outer_glow fail, texture is full, glyph_id: {:?}", id);
+                    return;
+                }
+            };
 
             let glyph = Glyph {
                 plane_min_x: plane_bounds.mins.x,
@@ -731,9 +1028,15 @@ impl Sdf2Table {
                 y: offset.y as f32 + atlas_bounds[1],
                 width: atlas_bounds[2] - atlas_bounds[0],
                 height: atlas_bounds[3] - atlas_bounds[1],
-                advance: outline_info.advance as f32,
+                advance,
+                font_size: sdf_size as f32,
+                page: offset.page,
+                color: false,
             };
-            vacant_entry.insert(glyph);
+            self.font_outer_glow_info.insert((id, range), glyph);
+            if let Some(r) = self.glyph_usage.get_mut(id.0) {
+                r.regions.push(offset);
+            }
 
             if let Some(v) = self.font_outer_glow.get_mut(&id) {
                 v.push(range);
@@ -755,6 +1058,7 @@ impl Sdf2Table {
         self.shapes_shadow_tex_info.insert(
             (hash, radius),
             SvgTexInfo {
+                page: index_position.page,
                 x: index_position.x as f32 + info.atlas_bounds.mins.x,
                 y: index_position.y as f32 + info.atlas_bounds.mins.y,
                 width: (info.atlas_bounds.maxs.x - info.atlas_bounds.mins.x) as usize,
@@ -788,6 +1092,7 @@ impl Sdf2Table {
         self.shapes_tex_info.insert(
             hash,
             SvgTexInfo {
+                page: index_position.page,
                 x: index_position.x as f32 + info2.atlas_bounds[0],
                 y: index_position.y as f32 + info2.atlas_bounds[1],
                 width: (info2.atlas_bounds[2] - info2.atlas_bounds[0]) as usize,
@@ -812,6 +1117,7 @@ impl Sdf2Table {
                 self.shapes_shadow_tex_info.insert(
                     (id, radius),
                     SvgTexInfo {
+                        page: index_position.page,
                         x: index_position.x as f32 + info.atlas_bounds[0],
                         y: index_position.y as f32 + info.atlas_bounds[1],
                         width: (info.atlas_bounds[2] - info.atlas_bounds[0]) as usize,
@@ -843,6 +1149,7 @@ impl Sdf2Table {
                 self.shapes_outer_glow_tex_info.insert(
                     (id, radius),
                     SvgTexInfo {
+                        page: index_position.page,
                         x: index_position.x as f32 + info.atlas_bounds[0],
                         y: index_position.y as f32 + info.atlas_bounds[1],
                         width: (info.atlas_bounds[2] - info.atlas_bounds[0]) as usize,
@@ -860,12 +1167,9 @@ impl Sdf2Table {
         }
     }
 
-    /// 取到字形信息
-    pub fn glyph(&self, id: GlyphId) -> &Glyph {
-        if self.glyphs.get(id.0).is_none() {
-            panic!("glyph is not exist, {:?}", id);
-        }
-        &self.glyphs[id.0].glyph
+    /// 取到字形信息， 字形已被淘汰（GlyphId失效）时返回None
+    pub fn glyph(&self, id: GlyphId) -> Option<&Glyph> {
+        self.glyphs.get(id.0).map(|r| &r.glyph)
     }
 
     /// 更新字形信息（计算圆弧信息）
@@ -878,11 +1182,19 @@ impl Sdf2Table {
         await_count: usize,
     ) -> AsyncValue<()> {
         let async_value = AsyncValue::new();
+        // 已停止接受任务，不再计算sdf，直接结束
+        if runtime::is_stopped() {
+            log::warn!("draw_await after runtime tasks stopped, skip {} tasks", await_count);
+            async_value.set(());
+            return async_value;
+        }
         if await_count == 0 {
             let async_value1 = async_value.clone();
-            let _ = MULTI_MEDIA_RUNTIME.spawn(async move {
+            if runtime::spawn_media("sdf2_draw_empty", async move {
                 async_value1.set(());
-            });
+            }).is_err() {
+                async_value.set(());
+            }
             return async_value;
         }
 
@@ -943,13 +1255,23 @@ impl Sdf2Table {
         if await_count.load(Ordering::Relaxed) != 0 {
             for (_, font_info) in sheet.fonts.iter_mut() {
                 let pxrange = compute_px_range(font_info);
+                let sdf_size = sdf_font_size(font_info.font.font_size);
+                let font = &font_info.font;
+                let (weight, style, stretch) = (font.font_weight, font.font_style, font.font_stretch);
                 let await_info = &mut font_info.await_info;
                 if await_info.wait_list.len() == 0 {
                     continue;
                 }
 
                 for glyph_id in await_info.wait_list.drain(..) {
-                    let g = &self.glyphs[glyph_id.0];
+                    // 字形在绘制前已被淘汰， 不需要计算
+                    let g = match self.glyphs.get(glyph_id.0) {
+                        Some(r) => r,
+                        None => {
+                            await_count.fetch_sub(1, Ordering::Relaxed);
+                            continue;
+                        }
+                    };
                     // font_face_index不存在， 不需要计算
                     if g.font_face_index.is_null() {
                         log::warn!("font_face_index null=============");
@@ -969,15 +1291,24 @@ impl Sdf2Table {
                         let shadow = self.font_shadow.remove(&glyph_id);
 
                         let font_name = &sheet.font_names[font_face_id.0];
+                        let outline_info = match self.outline_info.remove(&(font_face_id, g.glyph_index, sdf_size, pxrange, weight, style, stretch)) {
+                            Some(r) => r,
+                            None => {
+                                log::warn!("outline_info not found, font_face_id: {:?}, char: {}, glyph_id: {:?}", font_face_id.0, g.char, glyph_id);
+                                await_count.fetch_sub(1, Ordering::Relaxed);
+                                continue;
+                            }
+                        };
                         outline_infos.push((
-                            self.outline_info.remove(&(font_face_id.0, g.glyph_index, pxrange)).expect(&format!("font_face_id.0: {:?}, g.char: {}, glyph_id: {:?}, self: {:?}, not in outline_info!!!", font_face_id.0, g.char, glyph_id, 1)),
+                            outline_info,
                             font_face_id.0,
                             glyph_id,
                             is_outer_glow,
                             shadow,
-                            pxrange
+                            pxrange,
+                            sdf_size,
                         )); // 先取到贝塞尔曲线
-                        keys.push(format!("{}{}", g.char, font_name));
+                        keys.push(arcs_key(font_name, glyph_index));
                         chars.push(g.char)
                     }
                 }
@@ -999,60 +1330,20 @@ impl Sdf2Table {
             let char = chars[index];
             index += 1;
             let key = keys[ll].clone();
-            MULTI_MEDIA_RUNTIME
-                .spawn(async move {
-                    let mut crach_info = None;
-                    {
-                        let mut sdf_map = SDF_FONT.lock().unwrap();
-                        if sdf_map.is_some()
-                            && let Some(buffer) = sdf_map.as_mut().unwrap().remove(&key)
-                        {
-                            #[cfg(all(not(target_arch = "wasm32"), not(feature = "empty")))]
-                            {
-                                crach_info =
-                                    Some(bitcode::deserialize::<CellInfo>(&buffer[..]).unwrap());
-                            }
-
-                            #[cfg(all(target_arch = "wasm32", not(feature = "empty")))]
-                            {
-                                crach_info = Some(buffer);
-                            }
-                        }
-                    }
-
-                    let result_arcs = if let Some(info) = crach_info {
-                        info
-                    } else if let Some(buffer) = stroe::get(key.clone()).await {
-                        #[cfg(all(not(target_arch = "wasm32"), not(feature = "empty")))]
-                        {
-                            bitcode::deserialize::<CellInfo>(&buffer[..]).unwrap()
-                        }
-
-                        #[cfg(all(target_arch = "wasm32", not(feature = "empty")))]
-                        buffer
-                    } else {
-                        #[cfg(all(not(target_arch = "wasm32"), not(feature = "empty")))]
-                        {
-                            let arcs = glyph_visitor.0.compute_near_arcs(2.0);
-                            let buffer = bitcode::serialize(&arcs).unwrap();
-                            stroe::write(key, buffer).await;
-                            arcs
-                        }
-
-                        #[cfg(all(target_arch = "wasm32", not(feature = "empty")))]
-                        {
-                            let buffer = glyph_visitor.0.compute_near_arcs(1.0).await;
-                            stroe::write(key, buffer.clone()).await;
-                            buffer
+            let abandon = (await_count.clone(), async_value.clone());
+            let r = runtime::spawn_media("sdf2_font", async move {
+                    let source = match glyph_visitor.0 {
+                        GlyphOutline::Face(outline) => {
+                            let arcs = glyph_arcs(&outline, key).await;
+                            SdfSource::Arcs(outline, arcs)
                         }
+                        GlyphOutline::Styled(outline) => SdfSource::Path(outline),
                     };
                     // log::error!("computer char {}, time: {:?}. glyph_id: {:?}", char, time.elapsed(), glyph_visitor.2);
                     let lock = &mut result.0.lock().unwrap().font_result;
-                    let mut sdf = glyph_visitor.0.compute_sdf_tex(
-                        result_arcs.clone(),
-                        FONT_SIZE,
+                    let mut sdf = source.compute_sdf_tex(
+                        glyph_visitor.6,
                         glyph_visitor.5,
-                        false,
                         glyph_visitor.5,
                     );
                     sdf.tex_info.char = char;
@@ -1061,11 +1352,9 @@ impl Sdf2Table {
 
                     if let Some(outer_ranges) = glyph_visitor.3 {
                         for v in outer_ranges {
-                            let outer_glow_sdf = glyph_visitor.0.compute_sdf_tex(
-                                result_arcs.clone(),
-                                FONT_SIZE,
+                            let outer_glow_sdf = source.compute_sdf_tex(
+                                glyph_visitor.6,
                                 v,
-                                false,
                                 v,
                             );
                             lock.push((glyph_visitor.2 .0, outer_glow_sdf, SdfType::OuterGlow(v)));
@@ -1078,11 +1367,9 @@ impl Sdf2Table {
                                 tex_info,
                                 sdf_tex,
                                 tex_size,
-                            } = glyph_visitor.0.compute_sdf_tex(
-                                result_arcs.clone(),
-                                FONT_SIZE,
+                            } = source.compute_sdf_tex(
+                                glyph_visitor.6,
                                 glyph_visitor.5,
-                                false,
                                 (shadow_range as f32 + f32::from(weight) * 3.0) as u32 + 2,
                             );
                             let sdf_tex = gaussian_blur(
@@ -1111,8 +1398,10 @@ impl Sdf2Table {
                         log::trace!("encode_data_tex1");
                         async_value1.set(());
                     }
-                })
-                .unwrap();
+                });
+            if let Err(e) = r {
+                task_abandoned(&abandon.0, &abandon.1, e);
+            }
             ll += 1;
         }
     }
@@ -1157,15 +1446,18 @@ impl Sdf2Table {
                 buffer: sdf_tex,
             };
             let glyph = match sdf_type {
-                SdfType::Normal => &glyphs[glyph_id].glyph,
+                SdfType::Normal => glyphs.get(glyph_id).map(|r| &r.glyph),
                 SdfType::Shadow(radius, weight) => self
                     .font_shadow_info
-                    .get(&(GlyphId(glyph_id), radius, weight))
-                    .unwrap(),
+                    .get(&(GlyphId(glyph_id), radius, weight)),
                 SdfType::OuterGlow(radius) => self
                     .font_outer_glow_info
-                    .get(&(GlyphId(glyph_id), radius))
-                    .unwrap(),
+                    .get(&(GlyphId(glyph_id), radius)),
+            };
+            // 计算期间字形已被淘汰， 丢弃结果
+            let glyph = match glyph {
+                Some(r) => r,
+                None => continue,
             };
 
             let sdf_block = Block {
@@ -1173,6 +1465,7 @@ impl Sdf2Table {
                 y: glyph.y - tex_info.atlas_min_y as f32,
                 width: tex_size as f32,
                 height: tex_size as f32,
+                page: glyph.page,
             };
             // log::warn!("update index tex========={:?}", (&index_block,index_img.width, index_img.height, index_img.buffer.len(), &text_info) );
             (update.clone())(sdf_block, sdf_img);
@@ -1198,9 +1491,9 @@ impl Sdf2Table {
         for (hash, box_info) in bboxs.drain() {
             let async_value1 = async_value.clone();
             let result1 = result.clone();
+            let abandon = (await_count.clone(), async_value.clone());
             let await_count = await_count.clone();
-            MULTI_MEDIA_RUNTIME
-                .spawn(async move {
+            let r = runtime::spawn_media("sdf2_box_shadow", async move {
                     let sdfinfo = blur_box(box_info.clone());
 
                     // log::debug!("load========={:?}, {:?}", lock.0, len);
@@ -1212,8 +1505,10 @@ impl Sdf2Table {
                         async_value1.set(());
                         log::trace!("encode_data_tex2");
                     }
-                })
-                .unwrap();
+                });
+            if let Err(e) = r {
+                task_abandoned(&abandon.0, &abandon.1, e);
+            }
         }
     }
 
@@ -1244,6 +1539,7 @@ impl Sdf2Table {
                 y: index_position.y - box_info.atlas_bounds.mins.y as f32,
                 width: index_img.width as f32,
                 height: index_img.height as f32,
+                page: index_position.page,
             };
             // log::warn!("update index tex========={:?}", (&index_block,index_img.width, index_img.height, index_img.buffer.len(), &text_info) );
             (update.clone())(index_block, index_img);
@@ -1273,6 +1569,8 @@ impl Sdf2Table {
         let mut shapes_shadow = self.shapes_shadow.clone();
         self.shapes_shadow.clear();
         let shapes_tex_info = self.shapes_tex_info.clone();
+        // 图集增长后外部可能还未重建纹理， 此时传入的纹理比图集小， 不能由gpu直接绘制
+        let texture_fits = texture.width() as usize >= self.index_packer.width && texture.height() as usize >= self.index_packer.height;
 
         // 遍历所有等待处理的字符贝塞尔曲线，将曲线转化为圆弧描述（多线程）
         for (hash, (info, size, pxrange, cur_off)) in shapes.drain() {
@@ -1283,7 +1581,8 @@ impl Sdf2Table {
             let result1 = result.clone();
             let await_count = await_count.clone();
             let gpu = GPU.read().unwrap();
-            if size > 256 && gpu.is_some() {
+            // gpu直接绘制到传入的纹理（第0页， 且纹理尺寸与图集一致）， 其它情况走cpu计算
+            if size > 256 && gpu.is_some() && texture_fits && shapes_tex_info.get(&hash).map_or(false, |r| r.page == 0) {
                 let index_position = shapes_tex_info.get(&hash).unwrap().clone();
                 let tex_offset = (
                     (index_position.x - index_position.layout.atlas_bounds[0]) as u32,
@@ -1310,8 +1609,8 @@ impl Sdf2Table {
                     log::trace!("encode_data_tex2");
                 }
             } else {
-                MULTI_MEDIA_RUNTIME
-                    .spawn(async move {
+                let abandon = (await_count.clone(), async_value.clone());
+                let r = runtime::spawn_media("sdf2_svg", async move {
                         #[cfg(all(not(target_arch = "wasm32"), not(feature = "empty")))]
                         let sdfinfo =
                             Some(info.compute_sdf_tex(size, pxrange, false, cur_off, 1.0));
@@ -1371,8 +1670,10 @@ impl Sdf2Table {
                             async_value1.set(());
                             log::trace!("encode_data_tex2");
                         }
-                    })
-                    .unwrap();
+                    });
+                if let Err(e) = r {
+                    task_abandoned(&abandon.0, &abandon.1, e);
+                }
             }
         }
     }
@@ -1418,7 +1719,11 @@ impl Sdf2Table {
                 tex_size,
             }) = info
             {
-                let index_position = self.shapes_tex_info.get(&hash).unwrap();
+                // 计算期间已被移除（碎片整理时无法放入纹理）， 丢弃结果
+                let index_position = match self.shapes_tex_info.get(&hash) {
+                    Some(r) => r,
+                    None => continue,
+                };
 
                 let index_img = FontImage {
                     width: tex_size as usize,
@@ -1432,6 +1737,7 @@ impl Sdf2Table {
                     y: index_position.y - tex_info.atlas_min_y as f32,
                     width: index_img.width as f32,
                     height: index_img.height as f32,
+                    page: index_position.page,
                 };
                 // log::warn!("update index tex========={:?}", (&index_block,index_img.width, index_img.height, index_img.buffer.len(), &text_info) );
                 (update.clone())(index_block, index_img);
@@ -1445,6 +1751,192 @@ impl Sdf2Table {
 //     SvgInfo::new_from_arc_endpoint(SdfAabb(binding_box), arc_endpoints)
 // }
 
+// 计算距离场的数据： 字体轮廓先转化为圆弧， 按样式变换后的轮廓作为路径直接计算
+enum SdfSource {
+    Arcs(OutlineInfo, Arcs),
+    Path(StyledOutline),
+}
+
+impl SdfSource {
+    fn compute_sdf_tex(&self, sdf_size: usize, pxrange: u32, cur_off: u32) -> SdfInfo2 {
+        match self {
+            SdfSource::Arcs(outline, arcs) => outline.compute_sdf_tex(arcs.clone(), sdf_size, pxrange, false, cur_off),
+            SdfSource::Path(outline) => {
+                #[cfg(all(not(target_arch = "wasm32"), not(feature = "empty")))]
+                let sdf = outline.info.compute_sdf_tex(outline.size, pxrange, false, cur_off, 1.0);
+                #[cfg(all(target_arch = "wasm32", not(feature = "empty")))]
+                let sdf = outline.info.compute_sdf_tex_sync(outline.size, pxrange, false, cur_off, 1.0);
+                sdf
+            }
+        }
+    }
+}
+
+impl GlyphOutline {
+    /// 字形在纹理中的布局
+    pub fn compute_layout(&self, sdf_size: usize, pxrange: u32, cur_off: u32) -> LayoutInfo {
+        match self {
+            GlyphOutline::Face(r) => r.compute_layout(sdf_size, pxrange, cur_off),
+            GlyphOutline::Styled(r) => r.info.compute_layout(r.size, pxrange, cur_off),
+        }
+    }
+
+    /// 字形的包围盒（em的比例）
+    pub fn plane_bounds(&self) -> Aabb {
+        match self {
+            GlyphOutline::Face(r) => Aabb::new(
+                Point::new(r.bbox[0], r.bbox[1]),
+                Point::new(r.bbox[2], r.bbox[3]),
+            ).scaled(&Vector::new(1.0 / r.units_per_em as f32, 1.0 / r.units_per_em as f32)),
+            GlyphOutline::Styled(r) => Aabb::new(Point::new(r.bbox[0], r.bbox[1]), Point::new(r.bbox[2], r.bbox[3])),
+        }
+    }
+
+    /// 步进宽度（字体单位）
+    pub fn advance(&self) -> f32 {
+        match self {
+            GlyphOutline::Face(r) => r.advance as f32,
+            GlyphOutline::Styled(r) => r.advance,
+        }
+    }
+}
+
+// 字形的key
+fn glyph_key(font_face_id: FontFaceId, glyph_index: u16, font_info: &FontInfo) -> GlyphKey {
+    let font = &font_info.font;
+    (font_face_id, glyph_index, sdf_font_size(font.font_size), compute_px_range(font_info), font.font_weight, font.font_style, font.font_stretch)
+}
+
+// 将字形轮廓输出为路径， 按`scale`缩放， y轴翻转为svg的坐标系（向下）
+struct PathSink {
+    scale: f32,
+    verbs: Vec<PathVerb>,
+    points: Vec<f32>,
+}
+
+impl PathSink {
+    fn push(&mut self, verb: PathVerb, points: &[(f32, f32)]) {
+        self.verbs.push(verb);
+        for (x, y) in points {
+            self.points.extend([x * self.scale, -y * self.scale]);
+        }
+    }
+}
+
+impl ttf_parser::OutlineBuilder for PathSink {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.push(PathVerb::MoveTo, &[(x, y)]);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push(PathVerb::LineTo, &[(x, y)]);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.push(PathVerb::QuadTo, &[(x1, y1), (x, y)]);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.push(PathVerb::CubicTo, &[(x1, y1), (x2, y2), (x, y)]);
+    }
+
+    fn close(&mut self) {
+        self.push(PathVerb::Close, &[]);
+    }
+}
+
+// 字体轮廓转化的圆弧数据， 依次从预置的数据、本地存储中取得， 都不存在时计算并写入本地存储
+// 字形圆弧信息在缓存及本地存储中的key： 字体名及字形索引
+// 塑形后同一字形簇中的字形（连字、位置变体、组合标记等）都记录簇的第一个字符， 不能以字符作为key
+// 只有字体外观默认实例的轮廓（GlyphOutline::Face）使用圆弧缓存， 其轮廓与sdf字号、像素范围及字体样式无关
+fn arcs_key(font_name: &str, glyph_index: u16) -> String {
+    format!("{}:{}", font_name, glyph_index)
+}
+
+async fn glyph_arcs(outline: &OutlineInfo, key: String) -> Arcs {
+    let mut crach_info = None;
+    {
+        let mut sdf_map = SDF_FONT.lock().unwrap();
+        if sdf_map.is_some()
+            && let Some(buffer) = sdf_map.as_mut().unwrap().remove(&key)
+        {
+            #[cfg(all(not(target_arch = "wasm32"), not(feature = "empty")))]
+            {
+                crach_info =
+                    Some(bitcode::deserialize::<CellInfo>(&buffer[..]).unwrap());
+            }
+
+            #[cfg(all(target_arch = "wasm32", not(feature = "empty")))]
+            {
+                crach_info = Some(buffer);
+            }
+        }
+    }
+
+    if let Some(info) = crach_info {
+        info
+    } else if let Some(buffer) = stroe::get(key.clone()).await {
+        #[cfg(all(not(target_arch = "wasm32"), not(feature = "empty")))]
+        {
+            bitcode::deserialize::<CellInfo>(&buffer[..]).unwrap()
+        }
+
+        #[cfg(all(target_arch = "wasm32", not(feature = "empty")))]
+        buffer
+    } else {
+        #[cfg(all(not(target_arch = "wasm32"), not(feature = "empty")))]
+        {
+            let arcs = outline.compute_near_arcs(2.0);
+            let buffer = bitcode::serialize(&arcs).unwrap();
+            stroe::write(key, buffer).await;
+            arcs
+        }
+
+        #[cfg(all(target_arch = "wasm32", not(feature = "empty")))]
+        {
+            let buffer = outline.compute_near_arcs(1.0).await;
+            stroe::write(key, buffer.clone()).await;
+            buffer
+        }
+    }
+}
+
+// 碎片整理后，将纹理坐标移动到所在区域的新位置
+fn move_glyph(glyph: &mut Glyph, moves: &[&PackerMove]) {
+    for m in moves {
+        if contains(m, glyph.page, glyph.x, glyph.y) {
+            glyph.x += m.to.x as f32 - m.from.x as f32;
+            glyph.y += m.to.y as f32 - m.from.y as f32;
+            return;
+        }
+    }
+}
+
+// 碎片整理后，将svg纹理坐标移动到所在区域的新位置， 区域已被丢弃时返回false
+fn move_svg(info: &mut SvgTexInfo, moves: &[PackerMove]) -> bool {
+    for m in moves {
+        if contains(m, info.page, info.x, info.y) {
+            info.x += m.to.x as f32 - m.from.x as f32;
+            info.y += m.to.y as f32 - m.from.y as f32;
+            return true;
+        }
+    }
+    false
+}
+
+fn contains(m: &PackerMove, page: usize, x: f32, y: f32) -> bool {
+    let (x, y) = (x as usize, y as usize);
+    page == m.from.page && x >= m.from.x && x < m.from.x + m.width && y >= m.from.y && y < m.from.y + m.height
+}
+
+// 任务派发失败（已停止接受任务）时，直接计为完成，避免draw_await永远等待
+fn task_abandoned(await_count: &AtomicUsize, async_value: &AsyncValue<()>, e: std::io::Error) {
+    log::warn!("sdf task abandoned, {:?}", e);
+    if await_count.fetch_sub(1, Ordering::Relaxed) == 1 {
+        async_value.set(());
+    }
+}
+
 #[derive(Debug)]
 pub struct AwaitDraw {
     pub char: char,
@@ -1503,3 +1995,66 @@ pub fn create_async_value(font: &Atom, chars: &[char]) -> AsyncValue<Vec<Vec<u8>
     }
     r
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::font::AwaitInfo;
+
+    // 占满整个纹理的字形， 不经过字体直接分配
+    fn insert(table: &mut Sdf2Table, glyph_index: u16) -> Option<GlyphId> {
+        let position = table.alloc_index(32, 32)?;
+        let id = GlyphId(table.glyphs.insert(GlyphIdDesc {
+            font_id: FontId::default(),
+            char: 'a',
+            glyph_index,
+            font_face_index: 0,
+            glyph: Glyph::default(),
+        }));
+        table.glyph_usage.insert(id.0, GlyphUsage {
+            key: (FontFaceId(DefaultKey::null()), glyph_index, 32, 5, 500, FontStyle::Normal, 100),
+            last_used: table.frame,
+            regions: vec![position],
+        });
+        Some(id)
+    }
+
+    #[test]
+    fn cluster_glyphs_have_own_arcs() {
+        // "ffi"连字之后的字形、阿拉伯文字的位置变体、组合标记都与簇的第一个字形字符相同
+        let key = arcs_key("Mini", 4);
+        assert_ne!(key, arcs_key("Mini", 5));
+        assert_ne!(key, arcs_key("Other", 4));
+        assert_eq!(key, arcs_key("Mini", 4));
+    }
+
+    #[test]
+    fn access_evicted_glyph() {
+        let mut table = Sdf2Table::with_size(32, 32);
+        table.index_packer.set_limit(32, 1);
+        let first = insert(&mut table, 1).unwrap();
+        // 同一帧中使用的字形不会被淘汰
+        assert!(insert(&mut table, 2).is_none());
+
+        table.next_frame();
+        let second = insert(&mut table, 2).unwrap();
+        assert_eq!(table.take_evicted(), vec![first]);
+        assert!(table.glyph(first).is_none());
+        assert!(table.glyph_id_desc(first).is_none());
+        assert!(table.glyph(second).is_some());
+
+        let font_info = FontInfo {
+            font: Font::new(Atom::from("a"), 32, 500, NotNan::new(0.0).unwrap()),
+            font_ids: Default::default(),
+            height: 0.0,
+            max_height: 0.0,
+            await_info: AwaitInfo { size: Size { width: 0, height: 0 }, wait_list: Vec::new() },
+            font_family_id: FontFamilyId::default(),
+            fallback: Default::default(),
+        };
+        table.add_font_shadow(first, &font_info, 2, NotNan::new(1.0).unwrap());
+        table.add_font_outer_glow(first, &font_info, 2);
+        assert!(table.font_shadow_info.is_empty());
+        assert!(table.font_outer_glow_info.is_empty());
+    }
+}
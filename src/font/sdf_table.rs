@@ -7,10 +7,9 @@ use pi_share::{ThreadSync, ShareMutex, Share};
 use pi_slotmap::{SecondaryMap, DefaultKey, SlotMap};
 use serde::{Serialize, Deserialize};
 
-use super::{font::{FontId, Block, FontImage, DrawBlock, FontInfo, FontFaceId, GlyphId, GlyphIdDesc, Size, Glyph, OFFSET_RANGE, FontFamilyId}, text_pack::TextPacker};
+use super::{font::{FontId, Block, FontImage, DrawBlock, FontInfo, FontFaceId, GlyphId, GlyphIdDesc, Size, Glyph, OFFSET_RANGE, FontFamilyId}, shape::ShapedText, text_pack::TextPacker, text_split::{graphemes, SplitChar2}};
 
-use crate::runtime::MULTI_MEDIA_RUNTIME;
-use pi_async_rt::prelude::AsyncRuntime;
+use crate::runtime;
 // use pi_async_rt::prelude::serial::AsyncRuntime;
 
 
@@ -44,12 +43,17 @@ impl SdfTable {
 			text_packer: TextPacker::new(width, height),
 		}
 	}
-	/// 取到字形信息
-	pub fn glyph(&self, id: GlyphId) -> &Glyph {
-		if self.glyphs.get(*id).is_none() {
-			panic!("glyph is not exist, {:?}", id);
-		}
-		&self.glyphs[*id].glyph
+	/// 清空纹理中的所有文字， 之前分配的GlyphId全部失效（包括默认字符，需要重新添加）
+	pub fn clear(&mut self) {
+		self.text_packer.clear();
+		self.glyph_id_map.clear();
+		self.glyphs.clear();
+		self.default_char = None;
+	}
+
+	/// 取到字形信息， GlyphId为空或已失效时返回None
+	pub fn glyph(&self, id: GlyphId) -> Option<&Glyph> {
+		self.glyphs.get(*id).map(|r| &r.glyph)
 	}
 
 	// 添加sdf配置
@@ -63,6 +67,7 @@ impl SdfTable {
 		if let Some(r) = self.fonts_glyph.get(font_id.0) {
 			if let Some(glyph_info) = r.glyphs.get(&char) {
 				self.default_char = Some((r.metrics.clone(), glyph_info.clone(), name, char, font_id, glyph_id));
+				return;
 			}
 		}
 
@@ -135,7 +140,10 @@ impl SdfTable {
 						plane_max_y: 0.0,
 						width: 0.0, 
 						height: 0.0,
-						advance: 0.0,},
+						advance: 0.0,
+						font_size: 0.0,
+						page: 0,
+						color: false,},
 				}));
 
 				r.insert(id).clone()
@@ -145,7 +153,7 @@ impl SdfTable {
 		// let ff = font.font.font_family_string.clone();
 		let mut max_height = font_info.max_height;
 		let char_texture_size: Size<f32> = {
-			let (glyph_info, index) = match Self::glyph_info(font_info, char, &self.fonts_glyph) {
+			let (glyph_info, font_size, index) = match Self::info(font_info, char, &self.fonts_glyph).map(|r| (r.0, r.1.font_size, r.2)) {
 				Some(r) => {
 					font_info.await_info.wait_list.push(id);
 					r
@@ -176,6 +184,7 @@ impl SdfTable {
 			glyph.glyph.plane_min_x = glyph_info.ox as f32 / OFFSET_RANGE;
 			glyph.glyph.plane_min_y = glyph_info.oy as f32 / OFFSET_RANGE;
 			glyph.glyph.advance = glyph_info.advance as f32;
+			glyph.glyph.font_size = font_size;
 			max_height = glyph_info.height as f32;
 			// sdf的文字纹理， 不需要加上描边宽度， 也不需要间隔, 直接从配置中取到
 			Size {
@@ -197,6 +206,7 @@ impl SdfTable {
 		g.glyph.height = char_texture_size.height;
 		g.glyph.x = tex_position.x as f32;
 		g.glyph.y = tex_position.y as f32;
+		g.glyph.page = tex_position.page;
 
 		// 放入等待队列, 并统计等待队列的总宽度
 		font_info.await_info.size.width += g.glyph.width.ceil() as usize;
@@ -206,6 +216,75 @@ impl SdfTable {
 		Some(id)
 	}
 
+	/// 字形描述， GlyphId为空或已失效（如调用过`clear`）时返回None
+	pub fn glyph_id_desc(&self, glyph_id: GlyphId) -> Option<&GlyphIdDesc> {
+		self.glyphs.get(glyph_id.0)
+	}
+
+	/// 字形所在字体的度量信息
+	/// font_face_index等于字体列表长度时，表示该字形为默认字符
+	pub fn metrics(&self, glyph_id: GlyphId, font: &FontInfo) -> Option<&MetricsInfo> {
+		let glyph = self.glyphs.get(glyph_id.0)?;
+		match font.font_ids.get(glyph.font_face_index) {
+			Some(face_id) => self.fonts_glyph.get(face_id.0).map(|r| &r.metrics),
+			None => self.default_char.as_ref().map(|r| &r.0),
+		}
+	}
+
+	pub fn fontface_metrics(&self, face_id: FontFaceId) -> Option<&MetricsInfo> {
+		match self.fonts_glyph.get(face_id.0) {
+			Some(r) => Some(&r.metrics),
+			None => self.default_char.as_ref().map(|r| &r.0),
+		}
+	}
+
+	// 文字宽度（advance为配置中font_size下的像素宽度）
+	pub fn width_of_glyph_id(&self, font: &FontInfo, glyph_id: GlyphId) -> f32 {
+		match self.metrics(glyph_id, font) {
+			Some(metrics) => self.glyphs[glyph_id.0].glyph.advance * (font.font.font_size as f32 / metrics.font_size),
+			None => font.font.font_size as f32 / 2.0,
+		}
+	}
+
+	/// 塑形
+	///
+	/// sdf1的配置不含GSUB/GPOS信息， 不经过塑形， 每个字素簇作为一个字形簇， 仅进行双向分析
+	pub fn shape(&mut self, font_id: FontId, font_info: &mut FontInfo, text: &str, is_reverse: bool) -> ShapedText {
+		let glyph_ids = graphemes(text).into_iter().map(|g| self.glyph_id(font_id, font_info, g.char)).collect::<Vec<Option<GlyphId>>>();
+		let font_size = font_info.font.font_size.max(1) as f32;
+		let advances = glyph_ids.iter().map(|id| match id {
+			Some(id) => self.width_of_glyph_id(font_info, *id) / font_size,
+			None => 0.5,
+		}).collect();
+		ShapedText::from_graphemes(text, is_reverse, glyph_ids, advances)
+	}
+
+	// 字形id， sdf1没有字形索引， 每个字素簇对应一个GlyphId， 按视觉顺序排列（每个段落一行）
+	pub fn glyph_indexs(&mut self, font_id: FontId, font_info: &mut FontInfo, text: &str, is_reverse: bool) -> (String, Vec<Option<GlyphId>>) {
+		self.shape(font_id, font_info, text, is_reverse).visual_glyph_ids()
+	}
+
+	/// 劈分文字， 劈分结果中的索引为字素簇的索引（逻辑顺序）
+	/// 换行后通过`SplitChar2::line_map`取得每行的视觉顺序
+	pub fn split<'a>(&mut self, font_id: FontId, font_info: &mut FontInfo, text: &'a str, word_split: bool, merge_whitespace: bool, is_reverse: bool) -> SplitChar2<'a> {
+		let shaped = self.shape(font_id, font_info, text, is_reverse);
+		let (text2, glyph_ids) = shaped.cluster_glyph_ids();
+		let mut i = text.chars();
+		let last = i.next();
+		SplitChar2 {
+			text: text2.chars().collect::<Vec<char>>(),
+			glyph_ids,
+			cur_index: 0,
+			iter: i,
+			word_split,
+			merge_whitespace,
+			last,
+			type_id: 0,
+			shaped: Some(shaped),
+			line_breaks: None,
+		}
+	}
+
 	pub fn draw<F: FnMut(Block, FontImage) + Clone + ThreadSync + 'static>(
 		&mut self, 
 		mut draw_list: Vec<DrawBlock>,
@@ -255,7 +334,7 @@ impl SdfTable {
 		for (index, chars) in chars_familys.into_iter().enumerate() {
 			let async_value1 = async_value.clone();
 			let result1 = result.clone();
-			MULTI_MEDIA_RUNTIME.spawn(async move {
+			let r = runtime::spawn_media("sdf_load", async move {
 				let v = create_async_value(&chars.0, &chars.1);
 				let buffers: Vec<Vec<u8>> = v.await;
 				let mut lock = result1.lock().unwrap();
@@ -265,9 +344,14 @@ impl SdfTable {
 				if lock.0 == len {
 					async_value1.set(true);
 				}
-			}).unwrap();
+			});
+			// 已停止接受任务，放弃本次绘制
+			if let Err(e) = r {
+				log::warn!("sdf draw abandoned, {:?}", e);
+				return;
+			}
 		}
-		MULTI_MEDIA_RUNTIME.spawn(async move {
+		let r = runtime::spawn_media("sdf_draw", async move {
 			log::debug!("load1=========");
 			async_value.await;
 			let mut lock = result.lock().unwrap();
@@ -329,7 +413,10 @@ impl SdfTable {
 					update(draw_block.block, img);
 				}
 			}
-		}).unwrap();
+		});
+		if let Err(e) = r {
+			log::warn!("sdf draw abandoned, {:?}", e);
+		}
 
 		// let mut count = AtomicUsize::new(chars_familys.len());
 		
@@ -430,8 +517,8 @@ impl SdfTable {
 	}
 
 	fn info<'a>(font: &FontInfo, char: char,  font_cfg: &'a SecondaryMap<DefaultKey, FontCfg>) -> Option<(&'a GlyphInfo, &'a MetricsInfo, usize)> {
-		for (index, font_id) in font.font_ids.iter().enumerate() {
-			
+		for index in font.fallback.order(char, font.font_ids.len()) {
+			let font_id = font.font_ids[index];
 			if let Some(r) = font_cfg.get(font_id.0) {
 				if let Some(glyph_info) = r.glyphs.get(&char) {
 					return  Some((glyph_info, &r.metrics, index));
@@ -0,0 +1,477 @@
+//! 文字塑形
+//!
+//! 使用字体的GSUB/GPOS表（rustybuzz）将文本转换为字形序列，支持连字、上下文变形、组合标记定位，
+//! 以及印度文、泰文等复杂文字的字形簇
+//! 一个字形簇（cluster）由一个或多个字符组成，对应一个或多个字形，是布局中不可再分的最小单位
+
+use std::ops::Range;
+
+use rustybuzz::{Direction, Face, Feature, UnicodeBuffer, Variation};
+use ttf_parser::{gpos::{PairAdjustment, PositioningSubtable}, GlyphId as TtfGlyphId, Tag};
+
+use super::{bidi::{BidiMap, BidiText}, fallback::{script_runs, FaceFallback}, font::{Font, GlyphId}, synth::FaceStyle, text_split::{first_char, graphemes}};
+
+/// 塑形得到的原始字形（尚未分配GlyphId）
+#[derive(Debug, Clone)]
+pub struct RawGlyph {
+    /// 所用字体在字体列表（FontInfo::font_ids）中的索引
+    pub font_face_index: usize,
+    /// 字体中的字形索引， 0表示所有字体中都不存在
+    pub glyph_index: u16,
+    /// 所属字形簇在原文本中的起始字节偏移
+    pub cluster: usize,
+    /// 步进及偏移（相对于字体高度的百分比）
+    pub x_advance: f32,
+    pub y_advance: f32,
+    pub x_offset: f32,
+    pub y_offset: f32,
+}
+
+/// 塑形后的字形
+#[derive(Debug, Clone)]
+pub struct ShapedGlyph {
+    /// 字形id， None表示纹理空间不足， 未能分配
+    pub glyph_id: Option<GlyphId>,
+    /// 所用字体在字体列表中的索引
+    pub font_face_index: usize,
+    /// 字体中的字形索引
+    pub glyph_index: u16,
+    /// 所属字形簇在原文本中的起始字节偏移
+    pub cluster: usize,
+    /// 水平步进（相对于字体高度的百分比）
+    pub x_advance: f32,
+    /// 垂直步进（相对于字体高度的百分比）
+    pub y_advance: f32,
+    /// 水平偏移（相对于字体高度的百分比）
+    pub x_offset: f32,
+    /// 垂直偏移（相对于字体高度的百分比）
+    pub y_offset: f32,
+}
+
+/// 字形簇
+#[derive(Debug, Clone)]
+pub struct ShapedCluster {
+    /// 字形簇的第一个字符（"\r\n"为'\n'）
+    pub char: char,
+    /// 在原文本中的字节范围
+    pub byte_range: Range<usize>,
+    /// 在原文本中的字符范围
+    pub char_range: Range<usize>,
+    /// 在ShapedText::glyphs中的范围
+    pub glyphs: Range<usize>,
+    /// 字形簇的水平步进之和（相对于字体高度的百分比）
+    pub advance: f32,
+}
+
+/// 塑形结果
+///
+/// 字形簇按逻辑顺序排列， 每个字形簇中的字形按视觉顺序排列
+/// 换行后通过`line_map`取得每行的视觉顺序
+#[derive(Debug, Clone, Default)]
+pub struct ShapedText {
+    /// 段落方向是否从右到左
+    pub rtl: bool,
+    pub glyphs: Vec<ShapedGlyph>,
+    pub clusters: Vec<ShapedCluster>,
+    /// 双向分析结果（按原文本的字符索引）
+    pub bidi: BidiText,
+}
+
+impl ShapedText {
+    /// 由原始字形创建塑形结果
+    ///
+    /// `glyph_id`为每个原始字形分配GlyphId， 参数为原始字形及其字形簇的第一个字符，
+    /// 可修改原始字形的步进（如用替代字符代替不存在的字形时）
+    pub fn new<F: FnMut(&mut RawGlyph, char) -> Option<GlyphId>>(text: &str, rtl: bool, bidi: BidiText, raw: Vec<RawGlyph>, mut glyph_id: F) -> Self {
+        // 字形簇的结束位置为下一个字形簇的起始位置
+        let mut starts = raw.iter().map(|r| r.cluster).collect::<Vec<usize>>();
+        starts.sort_unstable();
+        starts.dedup();
+        let end_of = |start: usize| match starts.binary_search(&start) {
+            Ok(i) | Err(i) => starts.get(i + 1).copied().unwrap_or(text.len()),
+        };
+
+        let mut glyphs: Vec<ShapedGlyph> = Vec::with_capacity(raw.len());
+        let mut clusters: Vec<ShapedCluster> = Vec::new();
+        for mut r in raw {
+            let char = first_char(&text[r.cluster..]);
+            let id = glyph_id(&mut r, char);
+
+            match clusters.last_mut() {
+                Some(c) if c.byte_range.start == r.cluster => {
+                    c.glyphs.end += 1;
+                    c.advance += r.x_advance;
+                }
+                _ => {
+                    let end = end_of(r.cluster);
+                    let char_start = text[..r.cluster].chars().count();
+                    clusters.push(ShapedCluster {
+                        char,
+                        byte_range: r.cluster..end,
+                        char_range: char_start..char_start + text[r.cluster..end].chars().count(),
+                        glyphs: glyphs.len()..glyphs.len() + 1,
+                        advance: r.x_advance,
+                    });
+                }
+            }
+            glyphs.push(ShapedGlyph {
+                glyph_id: id,
+                font_face_index: r.font_face_index,
+                glyph_index: r.glyph_index,
+                cluster: r.cluster,
+                x_advance: r.x_advance,
+                y_advance: r.y_advance,
+                x_offset: r.x_offset,
+                y_offset: r.y_offset,
+            });
+        }
+        Self { rtl, glyphs, clusters, bidi }
+    }
+
+    /// 不经过塑形， 每个字素簇作为一个字形簇（字体不支持塑形时使用）
+    ///
+    /// # 参数
+    /// - `text`: 文本（逻辑顺序）
+    /// - `rtl`: 段落方向是否从右到左
+    /// - `glyph_ids`: 每个字素簇（第一个字符）的字形id
+    /// - `advances`: 每个字素簇的步进（相对于字体高度的百分比）
+    pub fn from_graphemes(text: &str, rtl: bool, glyph_ids: Vec<Option<GlyphId>>, advances: Vec<f32>) -> Self {
+        let mut r = Self { rtl, bidi: BidiText::new(text, Some(rtl)), ..Default::default() };
+        for (index, (g, (glyph_id, advance))) in graphemes(text).into_iter().zip(glyph_ids.into_iter().zip(advances)).enumerate() {
+            r.glyphs.push(ShapedGlyph {
+                glyph_id,
+                font_face_index: 0,
+                glyph_index: 0,
+                cluster: g.byte_range.start,
+                x_advance: advance,
+                y_advance: 0.0,
+                x_offset: 0.0,
+                y_offset: 0.0,
+            });
+            r.clusters.push(ShapedCluster {
+                char: g.char,
+                byte_range: g.byte_range,
+                char_range: g.char_range,
+                glyphs: index..index + 1,
+                advance,
+            });
+        }
+        r
+    }
+
+    /// 字形簇中的字形
+    pub fn cluster_glyphs(&self, cluster: &ShapedCluster) -> &[ShapedGlyph] {
+        &self.glyphs[cluster.glyphs.clone()]
+    }
+
+    /// 总步进（相对于字体高度的百分比）
+    pub fn advance(&self) -> f32 {
+        self.clusters.iter().map(|r| r.advance).sum()
+    }
+
+    /// 每个字形簇一个字符及其第一个字形的id（逻辑顺序）， 用于兼容逐字符的接口
+    pub fn cluster_glyph_ids(&self) -> (String, Vec<Option<GlyphId>>) {
+        let text = self.clusters.iter().map(|r| r.char).collect::<String>();
+        let glyph_ids = self.clusters.iter().map(|r| self.glyphs[r.glyphs.start].glyph_id).collect();
+        (text, glyph_ids)
+    }
+
+    /// 与`cluster_glyph_ids`相同， 但不换行， 每个段落作为一行按视觉顺序排列
+    pub fn visual_glyph_ids(&self) -> (String, Vec<Option<GlyphId>>) {
+        let mut text = String::with_capacity(self.clusters.len());
+        let mut glyph_ids = Vec::with_capacity(self.clusters.len());
+        let mut start = 0;
+        while start < self.clusters.len() {
+            // 换行符作为行的最后一个字形簇
+            let end = self.clusters[start..].iter().position(|r| r.char == '\n').map_or(self.clusters.len(), |i| start + i + 1);
+            let map = self.line_map(start..end);
+            for logical in map.visual_to_logical.iter() {
+                let c = &self.clusters[start + logical];
+                text.push(c.char);
+                glyph_ids.push(self.glyphs[c.glyphs.start].glyph_id);
+            }
+            start = end;
+        }
+        (text, glyph_ids)
+    }
+
+    /// 一行的逻辑与视觉索引映射
+    ///
+    /// # 参数
+    /// - `line`: 行中字形簇的范围（换行后得到）， 映射中的索引相对于`line.start`
+    pub fn line_map(&self, line: Range<usize>) -> BidiMap {
+        if line.is_empty() {
+            return BidiMap::default();
+        }
+        let clusters = &self.clusters[line];
+        let chars = clusters[0].char_range.start..clusters[clusters.len() - 1].char_range.end;
+        let levels = self.bidi.line_levels(chars.clone());
+        BidiMap::new(clusters.iter().map(|r| levels[r.char_range.start - chars.start]).collect())
+    }
+}
+
+/// 塑形
+///
+/// 按双向分析得到的层级将文本分段， 再按文字（Script）细分， 每段按其方向塑形
+/// 每段按其文字的后备顺序， 先使用第一个可用的字体塑形， 其中不存在的字形簇， 依次使用后续字体重新塑形
+/// 所有字体中都不存在的字形， glyph_index为0
+///
+/// # 参数
+/// - `faces`: 字体数据及其在字体集合中的索引， 与FontInfo::font_ids一一对应， None表示该字体未加载
+/// - `text`: 文本
+/// - `bidi`: 文本的双向分析结果
+/// - `fallback`: 每种文字尝试字体的顺序
+/// - `font`: 字体， 按其字重、样式、宽度设置可变轴坐标， 合成粗体时增加步进宽度
+/// - `features`: OpenType特性（如关闭连字"-liga"）， 为空时使用默认特性
+///
+/// # 返回值
+/// 字形簇按逻辑顺序排列， 同一字形簇中的字形按视觉顺序排列
+/// 字形簇不会拆分字素簇（表情序列、国旗、基字符加组合标记）， 字素簇中的字形都不存在时只保留一个字形
+pub fn shape_text(faces: &[Option<(&[u8], u32)>], text: &str, bidi: &BidiText, fallback: &FaceFallback, font: &Font, features: &[Feature]) -> Vec<RawGlyph> {
+    let starts = graphemes(text).into_iter().map(|r| r.byte_range.start).collect::<Vec<usize>>();
+    let mut out: Vec<RawGlyph> = Vec::new();
+    let mut run = Vec::new();
+    for (range, level) in bidi.level_runs() {
+        let rtl = level & 1 == 1;
+        for (range, script) in script_runs(text, range) {
+            let order = fallback.order_of_script(script, faces.len());
+            shape_range(faces, &order, text, range, rtl, font, features, &mut run);
+            // 字形簇扩展到所在字素簇的起始位置
+            for r in run.iter_mut() {
+                if let Err(i) = starts.binary_search(&r.cluster) {
+                    r.cluster = starts[i.saturating_sub(1)];
+                }
+            }
+            if rtl {
+                // 从右到左的段， 按字形簇反转回逻辑顺序， 字形簇内保持视觉顺序
+                let mut end = run.len();
+                while end > 0 {
+                    let cluster = run[end - 1].cluster;
+                    let mut start = end - 1;
+                    while start > 0 && run[start - 1].cluster == cluster {
+                        start -= 1;
+                    }
+                    out.extend_from_slice(&run[start..end]);
+                    end = start;
+                }
+                run.clear();
+            } else {
+                out.append(&mut run);
+            }
+        }
+    }
+
+    // 字素簇中的字形都不存在时（如字体不支持的表情序列）， 只保留一个字形， 用一个替代字符显示
+    let mut i = 0;
+    while i < out.len() {
+        let e = cluster_end(&out, i);
+        if e - i > 1 && out[i..e].iter().all(|r| r.glyph_index == 0) {
+            out.drain(i + 1..e);
+            i += 1;
+        } else {
+            // 跳过整个字形簇， 避免在簇内的后续字形上重复检查
+            i = e;
+        }
+    }
+    out
+}
+
+// 按order中的顺序（从头开始）选择字体塑形， 不存在的字形簇使用order中的后续字体
+#[allow(clippy::too_many_arguments)]
+fn shape_range(faces: &[Option<(&[u8], u32)>], order: &[usize], text: &str, range: Range<usize>, rtl: bool, font: &Font, features: &[Feature], out: &mut Vec<RawGlyph>) {
+    // 找到下一个可用的字体
+    let mut face = None;
+    for (pos, index) in order.iter().copied().enumerate() {
+        if let Some(r) = faces.get(index).copied().flatten().and_then(|(data, index)| Face::from_slice(data, index)) {
+            face = Some((pos, index, r));
+            break;
+        }
+    }
+    let (pos, font_face_index, mut face) = match face {
+        Some(r) => r,
+        None => {
+            // 没有可用字体， 每个字符一个空字形
+            let mut glyphs = text[range.clone()].char_indices().map(|(i, _)| RawGlyph {
+                font_face_index: 0,
+                glyph_index: 0,
+                cluster: range.start + i,
+                x_advance: 0.0,
+                y_advance: 0.0,
+                x_offset: 0.0,
+                y_offset: 0.0,
+            }).collect::<Vec<RawGlyph>>();
+            if rtl {
+                glyphs.reverse();
+            }
+            out.extend(glyphs);
+            return;
+        }
+    };
+
+    let style = FaceStyle::new(&face, font);
+    if !style.variations.is_empty() {
+        let variations = style.variations.iter().map(|(tag, value)| Variation { tag: *tag, value: *value }).collect::<Vec<Variation>>();
+        face.set_variations(&variations);
+    }
+
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(&text[range.clone()]);
+    buffer.guess_segment_properties();
+    buffer.set_direction(if rtl { Direction::RightToLeft } else { Direction::LeftToRight });
+    let glyph_buffer = rustybuzz::shape(&face, features, buffer);
+    let scale = 1.0 / face.units_per_em() as f32;
+    let glyphs = glyph_buffer.glyph_infos().iter().zip(glyph_buffer.glyph_positions()).map(|(info, pos)| RawGlyph {
+        font_face_index,
+        glyph_index: info.glyph_id as u16,
+        cluster: range.start + info.cluster as usize,
+        // 合成粗体只加宽有步进宽度的字形（组合符号等零宽字形保持零宽）
+        x_advance: pos.x_advance as f32 * scale + if pos.x_advance != 0 { style.embolden } else { 0.0 },
+        y_advance: pos.y_advance as f32 * scale,
+        x_offset: pos.x_offset as f32 * scale,
+        y_offset: pos.y_offset as f32 * scale,
+    }).collect::<Vec<RawGlyph>>();
+
+    // 字形簇的结束位置为下一个字形簇的起始位置
+    let mut starts = glyphs.iter().map(|r| r.cluster).collect::<Vec<usize>>();
+    starts.sort_unstable();
+    starts.dedup();
+    let end_of = |start: usize| match starts.binary_search(&start) {
+        Ok(i) | Err(i) => starts.get(i + 1).copied().unwrap_or(range.end),
+    };
+
+    // 含有不存在字形的字形簇， 连续的一段用后续字体重新塑形
+    let mut i = 0;
+    while i < glyphs.len() {
+        let e = cluster_end(&glyphs, i);
+        let missing = glyphs[i..e].iter().any(|r| r.glyph_index == 0);
+        if !missing || pos + 1 >= order.len() {
+            out.extend_from_slice(&glyphs[i..e]);
+            i = e;
+            continue;
+        }
+
+        let mut j = e;
+        while j < glyphs.len() {
+            let e = cluster_end(&glyphs, j);
+            if !glyphs[j..e].iter().any(|r| r.glyph_index == 0) {
+                break;
+            }
+            j = e;
+        }
+        let start = glyphs[i..j].iter().map(|r| r.cluster).min().unwrap();
+        let end = glyphs[i..j].iter().map(|r| end_of(r.cluster)).max().unwrap();
+        shape_range(faces, &order[pos + 1..], text, start..end, rtl, font, features, out);
+        i = j;
+    }
+}
+
+// 从i开始的字形簇的结束位置（字形索引）
+fn cluster_end(glyphs: &[RawGlyph], i: usize) -> usize {
+    let cluster = glyphs[i].cluster;
+    let mut e = i + 1;
+    while e < glyphs.len() && glyphs[e].cluster == cluster {
+        e += 1;
+    }
+    e
+}
+
+/// 两个相邻字形之间的字距调整（相对于字体高度的百分比， 负数表示靠近）
+///
+/// 优先读取GPOS中kern特性的成对调整（Pair Adjustment）， 不存在时读取kern表
+///
+/// # 参数
+/// - `data`: 字体文件数据
+/// - `index`: 字体在字体集合中的索引
+/// - `left`: 左侧字形在字体中的索引
+/// - `right`: 右侧字形在字体中的索引
+pub fn pair_kerning(data: &[u8], index: u32, left: u16, right: u16) -> f32 {
+    let face = match ttf_parser::Face::parse(data, index) {
+        Ok(r) => r,
+        Err(_) => return 0.0,
+    };
+    let (left, right) = (TtfGlyphId(left), TtfGlyphId(right));
+    let scale = 1.0 / face.units_per_em() as f32;
+
+    if let Some(gpos) = face.tables().gpos {
+        let kern = Tag::from_bytes(b"kern");
+        let mut lookups = Vec::new();
+        for feature in (0..gpos.features.len()).filter_map(|i| gpos.features.get(i)) {
+            if feature.tag == kern {
+                lookups.extend(feature.lookup_indices);
+            }
+        }
+        lookups.sort_unstable();
+        lookups.dedup();
+
+        let mut value = None;
+        for lookup in lookups.into_iter().filter_map(|i| gpos.lookups.get(i)) {
+            // 每个查找表中， 第一个含有该字形对的子表生效
+            for subtable in lookup.subtables.into_iter::<PositioningSubtable>() {
+                if let PositioningSubtable::Pair(pair) = subtable {
+                    // 覆盖左侧字形但没有该字形对时（格式1子表中不含右侧字形）， 继续查找后续子表
+                    if pair.coverage().contains(left) {
+                        if let Some(r) = pair_value(&pair, left, right) {
+                            value = Some(value.unwrap_or(0) + r);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(r) = value {
+            return r as f32 * scale;
+        }
+    }
+
+    if let Some(kern) = face.tables().kern {
+        for subtable in kern.subtables {
+            if subtable.horizontal && !subtable.variable && !subtable.has_cross_stream {
+                if let Some(r) = subtable.glyphs_kerning(left, right) {
+                    return r as f32 * scale;
+                }
+            }
+        }
+    }
+    0.0
+}
+
+// 成对调整中， 左侧字形步进的调整与右侧字形位置的调整之和（字体单位）
+fn pair_value(pair: &PairAdjustment, left: TtfGlyphId, right: TtfGlyphId) -> Option<i32> {
+    let (first, second) = match pair {
+        PairAdjustment::Format1 { coverage, sets } => sets.get(coverage.get(left)?)?.get(right)?,
+        PairAdjustment::Format2 { classes, matrix, .. } => matrix.get((classes.0.get(left), classes.1.get(right)))?,
+    };
+    Some(first.x_advance as i32 + second.x_placement as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // kern.ttf为mini.ttf（字形： 1 'A'， 2 ' '， 3 '.'， 4 ':'）加上GPOS的kern特性及kern表：
+    // GPOS格式1子表 A . -80， 格式2子表 A : -40； kern表 A . -50， . A -60（每em 1000单位）
+    const KERN: &[u8] = include_bytes!("../../tests/fonts/kern.ttf");
+    const MINI: &[u8] = include_bytes!("../../tests/fonts/mini.ttf");
+
+    fn assert_kern(data: &[u8], left: u16, right: u16, expected: f32) {
+        let kern = pair_kerning(data, 0, left, right);
+        assert!((kern - expected).abs() < 1e-6, "{} {}: {} != {}", left, right, kern, expected);
+    }
+
+    #[test]
+    fn gpos_pair_adjustment() {
+        // GPOS优先于kern表
+        assert_kern(KERN, 1, 3, -0.08);
+        // 格式1子表覆盖左侧字形但没有该字形对时， 继续查找格式2子表
+        assert_kern(KERN, 1, 4, -0.04);
+    }
+
+    #[test]
+    fn kern_table_fallback() {
+        // GPOS中没有该字形对时读取kern表
+        assert_kern(KERN, 3, 1, -0.06);
+        assert_kern(KERN, 4, 1, 0.0);
+        assert_kern(MINI, 1, 3, 0.0);
+        assert_kern(&KERN[..64], 1, 3, 0.0);
+    }
+}
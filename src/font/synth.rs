@@ -0,0 +1,349 @@
+//! 字重、样式、宽度
+//!
+//! 可变字体按`Font`的字重、样式、宽度设置wght、wdth、ital、slnt轴；
+//! 字体外观中没有匹配的粗体或斜体时， 合成粗体（轮廓外扩）及斜体（错切）
+
+use smallvec::SmallVec;
+use ttf_parser::{Face, OutlineBuilder, Tag};
+
+use super::font::{Font, FontStyle};
+
+/// 合成斜体的倾斜角度（度）， 与CSS中oblique的默认角度一致
+pub const OBLIQUE_ANGLE: f32 = 14.0;
+
+/// 合成粗体的轮廓外扩量（em的比例， 两侧之和）
+pub const EMBOLDEN_STRENGTH: f32 = 1.0 / 24.0;
+
+/// 合成粗体的最小字重
+pub const SYNTHETIC_BOLD_WEIGHT: usize = 600;
+
+/// 字体在一个字体外观上的样式： 可变轴的坐标及需要合成的效果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FaceStyle {
+    /// 可变轴的坐标
+    pub variations: SmallVec<[(Tag, f32); 4]>,
+    /// 合成粗体的外扩量（em的比例， 两侧之和）， 0表示不合成
+    pub embolden: f32,
+    /// 合成斜体的错切系数（tan(倾斜角度)）， 0表示不合成
+    pub skew: f32,
+}
+
+impl FaceStyle {
+    /// 按字体的字重、样式、宽度确定字体外观的样式
+    pub fn new(face: &Face, font: &Font) -> Self {
+        let mut r = Self::default();
+        let axis = |tag: &[u8; 4]| face.variation_axes().into_iter().find(|a| a.tag == Tag::from_bytes(tag));
+
+        // 字重
+        let weight = font.font_weight as f32;
+        let mut bold = font.font_weight < SYNTHETIC_BOLD_WEIGHT || face.weight().to_number() as usize >= SYNTHETIC_BOLD_WEIGHT;
+        if let Some(a) = axis(b"wght") {
+            r.variations.push((a.tag, weight.clamp(a.min_value, a.max_value)));
+            bold |= a.max_value >= SYNTHETIC_BOLD_WEIGHT as f32;
+        }
+        if !bold {
+            r.embolden = EMBOLDEN_STRENGTH;
+        }
+
+        // 宽度（百分比）
+        if let Some(a) = axis(b"wdth") {
+            r.variations.push((a.tag, (font.font_stretch as f32).clamp(a.min_value, a.max_value)));
+        }
+
+        // 样式
+        if font.font_style != FontStyle::Normal {
+            let mut slanted = face.is_italic() || face.is_oblique();
+            if let Some(a) = axis(b"ital").filter(|a| a.max_value >= 1.0 && font.font_style == FontStyle::Italic) {
+                r.variations.push((a.tag, 1.0));
+                slanted = true;
+            } else if let Some(a) = axis(b"slnt").filter(|a| a.min_value < 0.0) {
+                // slnt轴的角度为逆时针方向， 向右倾斜为负数
+                r.variations.push((a.tag, (-OBLIQUE_ANGLE).max(a.min_value)));
+                slanted = true;
+            }
+            if !slanted {
+                r.skew = OBLIQUE_ANGLE.to_radians().tan();
+            }
+        }
+        r
+    }
+
+    /// 设置字体外观的可变轴坐标
+    pub fn apply(&self, face: &mut Face) {
+        for (tag, value) in self.variations.iter() {
+            face.set_variation(*tag, *value);
+        }
+    }
+
+    /// 是否需要合成粗体或斜体
+    pub fn is_synthetic(&self) -> bool {
+        self.embolden > 0.0 || self.skew != 0.0
+    }
+}
+
+/// 合成粗体、斜体的轮廓
+///
+/// 先记录字形的完整轮廓（需要由整个轮廓的方向决定外扩方向）， 再变换后输出到目标
+#[derive(Debug, Default)]
+pub struct SynthOutline {
+    cmds: Vec<Cmd>,
+    points: Vec<(f32, f32)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Cmd {
+    Move,
+    Line,
+    Quad,
+    Cubic,
+    Close,
+}
+
+impl SynthOutline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 变换后的轮廓输出到`out`
+    ///
+    /// # 参数
+    /// - `style`: 需要合成的效果
+    /// - `units_per_em`: 字体的units_per_em， 外扩量按此换算为字体单位
+    pub fn emit<B: OutlineBuilder>(&self, style: &FaceStyle, units_per_em: f32, out: &mut B) {
+        let points = self.transform(style, units_per_em);
+        let mut i = 0;
+        for cmd in self.cmds.iter() {
+            match cmd {
+                Cmd::Move => out.move_to(points[i].0, points[i].1),
+                Cmd::Line => out.line_to(points[i].0, points[i].1),
+                Cmd::Quad => out.quad_to(points[i].0, points[i].1, points[i + 1].0, points[i + 1].1),
+                Cmd::Cubic => out.curve_to(points[i].0, points[i].1, points[i + 1].0, points[i + 1].1, points[i + 2].0, points[i + 2].1),
+                Cmd::Close => out.close(),
+            }
+            i += cmd.len();
+        }
+    }
+
+    /// 变换后的包围盒（含控制点）， [x_min, y_min, x_max, y_max]， 字体单位
+    pub fn bounds(&self, style: &FaceStyle, units_per_em: f32) -> Option<[f32; 4]> {
+        let points = self.transform(style, units_per_em);
+        let first = points.first()?;
+        Some(points.iter().fold([first.0, first.1, first.0, first.1], |r, p| {
+            [r[0].min(p.0), r[1].min(p.1), r[2].max(p.0), r[3].max(p.1)]
+        }))
+    }
+
+    fn transform(&self, style: &FaceStyle, units_per_em: f32) -> Vec<(f32, f32)> {
+        let mut points = self.points.clone();
+        if style.embolden > 0.0 {
+            let strength = style.embolden * units_per_em;
+            self.embolden(&mut points, strength / 2.0);
+            // 保持左侧留白不变， 步进宽度由调用者增加strength
+            for p in points.iter_mut() {
+                p.0 += strength / 2.0;
+            }
+        }
+        if style.skew != 0.0 {
+            for p in points.iter_mut() {
+                p.0 += p.1 * style.skew;
+            }
+        }
+        points
+    }
+
+    // 每个轮廓在points中的范围
+    fn contours(&self) -> Vec<std::ops::Range<usize>> {
+        let mut r = Vec::new();
+        let (mut start, mut i) = (0, 0);
+        for cmd in self.cmds.iter() {
+            if let Cmd::Move = cmd {
+                if i > start {
+                    r.push(start..i);
+                }
+                start = i;
+            }
+            i += cmd.len();
+        }
+        if i > start {
+            r.push(start..i);
+        }
+        r
+    }
+
+    // 沿法线方向外扩轮廓上的每个点（包括控制点）， 与FreeType的FT_Outline_EmboldenXY相同
+    fn embolden(&self, points: &mut [(f32, f32)], strength: f32) {
+        let contours = self.contours();
+        // 整个轮廓的方向， 面积为负时为顺时针（TrueType）
+        let area: f32 = contours.iter().map(|c| {
+            let c = &points[c.clone()];
+            (0..c.len()).map(|i| {
+                let (a, b) = (c[i], c[(i + 1) % c.len()]);
+                a.0 * b.1 - b.0 * a.1
+            }).sum::<f32>()
+        }).sum();
+        let clockwise = area < 0.0;
+
+        for range in contours {
+            let src = points[range.clone()].to_vec();
+            let n = src.len();
+            if n < 2 {
+                continue;
+            }
+            for i in 0..n {
+                let p = src[i];
+                let prev = (1..n).map(|k| src[(i + n - k) % n]).find(|r| *r != p);
+                let next = (1..n).map(|k| src[(i + k) % n]).find(|r| *r != p);
+                let (prev, next) = match (prev, next) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => continue,
+                };
+                let inv = normalize((p.0 - prev.0, p.1 - prev.1));
+                let out = normalize((next.0 - p.0, next.1 - p.1));
+                let d = inv.0 * out.0 + inv.1 * out.1;
+                // 转角过大（接近180度）时不移动
+                if d <= -0.9396 {
+                    continue;
+                }
+                let d = d + 1.0;
+                let mut shift = (inv.1 + out.1, inv.0 + out.0);
+                let mut q = out.0 * inv.1 - out.1 * inv.0;
+                if clockwise {
+                    shift.0 = -shift.0;
+                    q = -q;
+                } else {
+                    shift.1 = -shift.1;
+                }
+                let scale = if q <= d { strength / d } else { strength / q };
+                points[range.start + i].0 += shift.0 * scale;
+                points[range.start + i].1 += shift.1 * scale;
+            }
+        }
+    }
+}
+
+impl Cmd {
+    // 命令使用的点数
+    fn len(&self) -> usize {
+        match self {
+            Cmd::Move | Cmd::Line => 1,
+            Cmd::Quad => 2,
+            Cmd::Cubic => 3,
+            Cmd::Close => 0,
+        }
+    }
+}
+
+fn normalize(v: (f32, f32)) -> (f32, f32) {
+    let l = (v.0 * v.0 + v.1 * v.1).sqrt();
+    if l > 0.0 { (v.0 / l, v.1 / l) } else { (0.0, 0.0) }
+}
+
+impl OutlineBuilder for SynthOutline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.cmds.push(Cmd::Move);
+        self.points.push((x, y));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.cmds.push(Cmd::Line);
+        self.points.push((x, y));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.cmds.push(Cmd::Quad);
+        self.points.extend([(x1, y1), (x, y)]);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.cmds.push(Cmd::Cubic);
+        self.points.extend([(x1, y1), (x2, y2), (x, y)]);
+    }
+
+    fn close(&mut self) {
+        self.cmds.push(Cmd::Close);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ordered_float::NotNan;
+    use ttf_parser::GlyphId;
+
+    use super::*;
+
+    const MINI: &[u8] = include_bytes!("../../tests/fonts/mini.ttf");
+
+    fn font(weight: usize, style: FontStyle) -> Font {
+        let mut font = Font::new("mini".into(), 32, weight, NotNan::new(0.0).unwrap());
+        font.font_style = style;
+        font
+    }
+
+    // 顺时针（TrueType方向）的正方形
+    fn square(size: f32) -> SynthOutline {
+        let mut r = SynthOutline::new();
+        r.move_to(0.0, 0.0);
+        r.line_to(0.0, size);
+        r.line_to(size, size);
+        r.line_to(size, 0.0);
+        r.close();
+        r
+    }
+
+    fn assert_bounds(r: Option<[f32; 4]>, expect: [f32; 4]) {
+        let r = r.unwrap();
+        for i in 0..4 {
+            assert!((r[i] - expect[i]).abs() < 1e-3, "{:?} != {:?}", r, expect);
+        }
+    }
+
+    #[test]
+    fn synthetic_style() {
+        let face = Face::parse(MINI, 0).unwrap();
+        assert!(!FaceStyle::new(&face, &font(400, FontStyle::Normal)).is_synthetic());
+
+        let bold = FaceStyle::new(&face, &font(700, FontStyle::Normal));
+        assert_eq!(bold.embolden, EMBOLDEN_STRENGTH);
+        assert_eq!(bold.skew, 0.0);
+        assert!(bold.variations.is_empty());
+
+        let italic = FaceStyle::new(&face, &font(400, FontStyle::Italic));
+        assert_eq!(italic.embolden, 0.0);
+        assert!((italic.skew - OBLIQUE_ANGLE.to_radians().tan()).abs() < 1e-6);
+        assert!(italic.is_synthetic());
+    }
+
+    #[test]
+    fn square_bounds() {
+        let outline = square(100.0);
+        let strength = EMBOLDEN_STRENGTH * 1000.0;
+        let bold = FaceStyle { embolden: EMBOLDEN_STRENGTH, ..Default::default() };
+        let oblique = FaceStyle { skew: OBLIQUE_ANGLE.to_radians().tan(), ..Default::default() };
+
+        assert_bounds(outline.bounds(&FaceStyle::default(), 1000.0), [0.0, 0.0, 100.0, 100.0]);
+        // 每边外扩strength / 2， 再右移strength / 2保持左侧留白
+        assert_bounds(outline.bounds(&bold, 1000.0), [0.0, -strength / 2.0, 100.0 + strength, 100.0 + strength / 2.0]);
+        assert_bounds(outline.bounds(&oblique, 1000.0), [0.0, 0.0, 100.0 + 100.0 * oblique.skew, 100.0]);
+        assert!(SynthOutline::new().bounds(&bold, 1000.0).is_none());
+    }
+
+    #[test]
+    fn glyph_bounds() {
+        let face = Face::parse(MINI, 0).unwrap();
+        let mut outline = SynthOutline::new();
+        let bbox = face.outline_glyph(GlyphId(1), &mut outline).unwrap();
+        let plain = outline.bounds(&FaceStyle::default(), 1000.0).unwrap();
+        assert_eq!(plain, [bbox.x_min as f32, bbox.y_min as f32, bbox.x_max as f32, bbox.y_max as f32]);
+
+        let bold = FaceStyle { embolden: EMBOLDEN_STRENGTH, ..Default::default() };
+        let r = outline.bounds(&bold, 1000.0).unwrap();
+        let strength = EMBOLDEN_STRENGTH * 1000.0;
+        assert!(r[2] - r[0] >= plain[2] - plain[0] + strength - 1e-3);
+        assert!(r[1] < plain[1] && r[3] > plain[3]);
+
+        // 输出的轮廓与包围盒一致
+        let mut emitted = SynthOutline::new();
+        outline.emit(&bold, 1000.0, &mut emitted);
+        assert_eq!(emitted.bounds(&FaceStyle::default(), 1000.0), Some(r));
+    }
+}
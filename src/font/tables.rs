@@ -2,20 +2,26 @@
 
 use pi_share::Share;
 use pi_wgpu as wgpu;
-use super::{/* bitmap_table::BitmapTable, */ font::{FontFaceId, FontId, FontInfo, FontType, GlyphId, GlyphIdDesc, Size, BASE_FONT_SIZE}, sdf2_table::Sdf2Table, sdf_table::{MetricsInfo, SdfTable}, text_split::{SplitChar, SplitChar2}};
+use super::{bitmap_table::BitmapTable, color_glyph::ColorAtlas, font::{Block, Font, FontFaceId, FontId, FontImage, FontInfo, FontType, GlyphId, GlyphIdDesc, Size, BASE_FONT_SIZE}, sdf2_table::Sdf2Table, sdf_table::{MetricsInfo, SdfTable}, shape::ShapedText, synth::FaceStyle, text_pack::PackerStats, text_split::{SplitChar, SplitChar2}, woff::to_sfnt};
+
+/// 纹理图集默认的页数上限
+pub const DEFAULT_MAX_PAGES: usize = 4;
 
 /// 字体表管理器，负责管理不同字体渲染方式的存储和查询
 pub struct FontTable {
 	/// 位图字体表，用于处理基于位图的字体渲染
-	/// - Web平台依赖Canvas文字功能
-	/// - 本地平台(app/exe)通常使用freetype实现
-	// pub bitmap_table: BitmapTable,
+	/// 在cpu上按实际字号光栅化字体轮廓，适用于小字号文字
+	pub bitmap_table: BitmapTable,
 	
 	/// SDF1字体表，用于基于有符号距离场的一代字体渲染
 	pub sdf_table: SdfTable,
 	
 	/// SDF2字体表，用于改进版的有符号距离场字体渲染
 	pub sdf2_table: Sdf2Table,
+
+	/// 纹理图集代数，每次clear后加1
+	/// 外部缓存的GlyphId及纹理坐标，在代数变化后都已失效，需要重新请求
+	generation: usize,
 }
 
 impl FontTable {
@@ -27,11 +33,65 @@ impl FontTable {
 	/// - `device`: wgpu图形设备共享实例
 	/// - `queue`: wgpu命令队列共享实例
 	pub fn new(width: usize, height: usize, device: Share<wgpu::Device>, queue: Share<wgpu::Queue>) -> Self {
-		Self {
-			// bitmap_table: BitmapTable::new(width, height),
+		let max_size = device.limits().max_texture_dimension_2d as usize;
+		let mut r = Self {
+			bitmap_table: BitmapTable::new(width, height),
 			sdf_table: SdfTable::new(width, height),
 			sdf2_table: Sdf2Table::new(width, height, device, queue),
+			generation: 0,
+		};
+		r.set_atlas_limit(max_size, DEFAULT_MAX_PAGES);
+		r
+	}
+
+	/// 设置纹理图集的增长上限
+	/// 
+	/// 纹理空间不足时，图集尺寸成倍增长直到`max_size`，之后增加新页直到`max_pages`
+	/// 
+	/// # 参数
+	/// - `max_size`: 单页纹理宽高上限
+	/// - `max_pages`: 页数上限
+	pub fn set_atlas_limit(&mut self, max_size: usize, max_pages: usize) {
+		self.bitmap_table.text_packer.set_limit(max_size, max_pages);
+		self.sdf_table.text_packer.set_limit(max_size, max_pages);
+		self.sdf2_table.index_packer.set_limit(max_size, max_pages);
+		self.bitmap_table.color.text_packer.set_limit(max_size, max_pages);
+		self.sdf2_table.color.text_packer.set_limit(max_size, max_pages);
+	}
+
+	/// 添加字体数据
+	/// 
+	/// # 参数
+	/// - `face_id`: 字体face ID
+	/// - `buffer`: 字体文件数据（ttf/otf/ttc/otc， 或woff/woff2， 先解码为sfnt）
+	/// - `index`: 字体在字体集合中的索引， 不是字体集合时为0
+	///
+	/// # 返回值
+	/// 数据无法解析为字体（或集合中不存在该索引的字体）时返回false， 不添加到任何字体表
+	pub fn add_font(&mut self, face_id: FontFaceId, buffer: Share<Vec<u8>>, index: u32) -> bool {
+		let buffer = to_sfnt(buffer);
+		if let Err(e) = ttf_parser::Face::parse(&buffer, index) {
+			log::warn!("font parse fail, face_id: {:?}, index: {}, err: {:?}", face_id, index, e);
+			return false;
 		}
+		if !self.sdf2_table.add_font(face_id, buffer.clone(), index) {
+			log::warn!("font collection has no face, face_id: {:?}, index: {}", face_id, index);
+			return false;
+		}
+		self.bitmap_table.add_font(face_id, buffer, index);
+		true
+	}
+
+	/// 是否已通过`add_font`添加字体数据
+	pub fn has_font(&self, face_id: FontFaceId) -> bool {
+		self.bitmap_table.has_font(face_id)
+	}
+
+	/// 字体在字体外观上的样式（可变轴坐标及需要合成的效果）
+	///
+	/// 位图字体在光栅化时、sdf2在生成距离场前已应用到字形轮廓
+	pub fn face_style(&self, face_id: FontFaceId, font: &Font) -> Option<FaceStyle> {
+		self.bitmap_table.face_style(face_id, font)
 	}
 
 	/// 获取指定字体类型的纹理图集尺寸
@@ -43,12 +103,68 @@ impl FontTable {
 	/// 返回包含宽度和高度的Size结构体
 	pub fn size(&self, font_type: FontType) -> Size<usize> {
 		match font_type {
-			FontType::Bitmap => panic!("not surpport bitmap_table!!!"), //Size { width: self.bitmap_table.text_packer.width, height: self.bitmap_table.text_packer.height },
+			FontType::Bitmap => Size { width: self.bitmap_table.text_packer.width, height: self.bitmap_table.text_packer.height },
 			FontType::Sdf1 => Size { width: self.sdf_table.text_packer.width, height: self.sdf_table.text_packer.height },
 			FontType::Sdf2 =>  Size { width: self.sdf2_table.index_packer.width, height: self.sdf2_table.index_packer.height },
 		}
 	}
 
+	/// 获取指定字体类型的纹理图集页数
+	/// 
+	/// # 参数
+	/// - `font_type`: 字体渲染类型枚举
+	pub fn page_count(&self, font_type: FontType) -> usize {
+		match font_type {
+			FontType::Bitmap => self.bitmap_table.text_packer.page_count(),
+			FontType::Sdf1 => self.sdf_table.text_packer.page_count(),
+			FontType::Sdf2 => self.sdf2_table.index_packer.page_count(),
+		}
+	}
+
+	/// 取出并重置指定字体类型的纹理图集变化通知， 见`TextPacker::take_resized`
+	pub fn take_atlas_resized(&mut self, font_type: FontType) -> bool {
+		match font_type {
+			FontType::Bitmap => self.bitmap_table.text_packer.take_resized(),
+			FontType::Sdf1 => self.sdf_table.text_packer.take_resized(),
+			FontType::Sdf2 => self.sdf2_table.index_packer.take_resized(),
+		}
+	}
+
+	/// 获取指定字体类型的纹理图集占用统计
+	/// 
+	/// # 参数
+	/// - `font_type`: 字体渲染类型枚举
+	pub fn stats(&self, font_type: FontType) -> PackerStats {
+		match font_type {
+			FontType::Bitmap => self.bitmap_table.text_packer.stats(),
+			FontType::Sdf1 => self.sdf_table.text_packer.stats(),
+			FontType::Sdf2 => self.sdf2_table.index_packer_stats(),
+		}
+	}
+
+	/// 获取指定字体类型的彩色字形图集
+	///
+	/// Sdf1不读取字体轮廓， 没有彩色字形， 其图集始终为空
+	pub fn color_atlas(&self, font_type: FontType) -> &ColorAtlas {
+		match font_type {
+			FontType::Bitmap | FontType::Sdf1 => &self.bitmap_table.color,
+			FontType::Sdf2 => &self.sdf2_table.color,
+		}
+	}
+
+	/// 光栅化指定字体类型中所有等待中的彩色字形
+	///
+	/// # 参数
+	/// - `font_type`: 字体渲染类型枚举
+	/// - `update`: 每个字形回调一次， FontImage为RGBA8数据（非预乘）
+	pub fn draw_color<F: FnMut(Block, FontImage)>(&mut self, font_type: FontType, update: F) {
+		match font_type {
+			FontType::Bitmap => self.bitmap_table.draw_color(update),
+			FontType::Sdf1 => (),
+			FontType::Sdf2 => self.sdf2_table.draw_color(update),
+		}
+	}
+
 	/// 检查并创建对应的字体face对象
 	/// 
 	/// # 参数
@@ -56,12 +172,8 @@ impl FontTable {
 	/// - `font_type`: 字体渲染类型枚举
 	/// 
 	/// # 注意
-	/// 当前仅对位图字体类型有效
-	pub fn check_or_create_face(& mut self, font: &FontInfo, font_type: FontType) {
-		if font_type == FontType::Bitmap {
-			panic!("not surpport bitmap_table!!!");
-			// self.bitmap_table.brush.check_or_create_face(font);
-		}
+	/// 字体数据统一通过`add_font`添加，各字体表均无需在此创建face
+	pub fn check_or_create_face(& mut self, _font: &FontInfo, _font_type: FontType) {
 	}
 
 	/// 计算字体的垂直度量信息
@@ -79,12 +191,7 @@ impl FontTable {
 		} else if font_type == FontType::Sdf2 {
 			self.sdf2_table.height(font)
 		} else {
-			panic!("not surpport bitmap_table!!!")
-			// let mut r = self.bitmap_table.brush.base_height(font);
-			// log::warn!("height======={:?}, {:?}", r, font);
-			// // max_height, todo
-			// r = font.font.font_size as f32 / BASE_FONT_SIZE as f32 * r;
-			// (r, r)
+			self.bitmap_table.height(font)
 		}
 	}
 	
@@ -98,39 +205,41 @@ impl FontTable {
 	/// 
 	/// # 返回值
 	/// 返回字符宽度（像素）
-	/// 
-	/// # 注意
-	/// 位图字体类型的实现待完善
 	pub fn measure_width(&mut self, f: FontId, font: &mut FontInfo,  char: char, font_type: FontType) -> f32 {
 		if font_type == FontType::Sdf1 {
 			self.sdf_table.width(font, char).0
 		} else if font_type == FontType::Sdf2 {
 			self.sdf2_table.width(f, font, char).0
 		} else {
-			// let base_w = self.bitmap_table.brush.base_width( font, char);
-			// let ratio = font.font.font_size as f32 / BASE_FONT_SIZE as f32;
-			// let r = ratio * base_w.0 + *font.font.stroke;
-
-			// log::warn!("width======={:?}, {:?}, {:?}, {:?}", base_w, char, r, font);
-			// r
-			todo!()
+			self.bitmap_table.width(f, font, char).0
 		}
 	}
 
 	pub fn measure_width_of_glyph_id(&mut self, f: FontId, font: &mut FontInfo,  glyph_id: GlyphId, font_type: FontType) -> f32 {
 		if font_type == FontType::Sdf1 {
-			// self.sdf_table.width(font, char).0
-			panic!("not surpport sdf1");
+			self.sdf_table.width_of_glyph_id(font, glyph_id)
 		} else if font_type == FontType::Sdf2 {
 			self.sdf2_table.width_of_glyph_id(f, font, glyph_id)
 		} else {
-			// let base_w = self.bitmap_table.brush.base_width( font, char);
-			// let ratio = font.font.font_size as f32 / BASE_FONT_SIZE as f32;
-			// let r = ratio * base_w.0 + *font.font.stroke;
+			self.bitmap_table.width_of_glyph_id(f, font, glyph_id)
+		}
+	}
 
-			// log::warn!("width======={:?}, {:?}, {:?}, {:?}", base_w, char, r, font);
-			// r
-			todo!()
+	/// 两个相邻字形之间的字距调整（像素）
+	/// 
+	/// # 参数
+	/// - `font`: 字体信息引用
+	/// - `left`: 左侧字形ID
+	/// - `right`: 右侧字形ID
+	/// - `font_type`: 字体渲染类型枚举
+	/// 
+	/// # 注意
+	/// Sdf1的配置中没有字距信息， 总是返回0
+	pub fn kern(&mut self, font: &FontInfo, left: GlyphId, right: GlyphId, font_type: FontType) -> f32 {
+		match font_type {
+			FontType::Bitmap => self.bitmap_table.kern(font, left, right),
+			FontType::Sdf1 => 0.0,
+			FontType::Sdf2 => self.sdf2_table.kern(font, left, right),
 		}
 	}
 
@@ -145,11 +254,11 @@ impl FontTable {
 	/// 返回Option包装的MetricsInfo引用
 	pub fn metrics(&self, id: GlyphId, font: &FontInfo, font_type: FontType) -> Option<&MetricsInfo> {
 		if font_type == FontType::Sdf1 {
-			todo!()
+			self.sdf_table.metrics(id, font)
 		} else if font_type == FontType::Sdf2 {
 			self.sdf2_table.metrics(id, font)
 		} else {
-			todo!()
+			self.bitmap_table.metrics(id, font)
 		}
 	}
 
@@ -163,11 +272,11 @@ impl FontTable {
 	/// 返回Option包装的MetricsInfo引用
 	pub fn fontface_metrics(&self, face_id: FontFaceId, font_type: FontType) -> Option<&MetricsInfo> {
         if font_type == FontType::Sdf1 {
-			todo!()
+			self.sdf_table.fontface_metrics(face_id)
 		} else if font_type == FontType::Sdf2 {
 			self.sdf2_table.fontface_metrics(face_id)
 		} else {
-			todo!()
+			self.bitmap_table.fontface_metrics(face_id)
 		}
 	}
 
@@ -178,14 +287,14 @@ impl FontTable {
 	/// - `font_type`: 字体渲染类型枚举
 	/// 
 	/// # 返回值
-	/// 返回GlyphIdDesc的引用
-	pub fn glyph_id_desc(&self, glyph_id: GlyphId, font_type: FontType) -> &GlyphIdDesc {
+	/// 返回GlyphIdDesc的引用， GlyphId为空或已失效时返回None
+	pub fn glyph_id_desc(&self, glyph_id: GlyphId, font_type: FontType) -> Option<&GlyphIdDesc> {
 		if font_type == FontType::Sdf1 {
-			todo!()
+			self.sdf_table.glyph_id_desc(glyph_id)
 		} else if font_type == FontType::Sdf2 {
 			self.sdf2_table.glyph_id_desc(glyph_id)
 		} else {
-			todo!()
+			self.bitmap_table.glyph_id_desc(glyph_id)
 		}
     }
 
@@ -201,10 +310,7 @@ impl FontTable {
 	/// 返回Option包装的GlyphId
 	pub fn glyph_id(&mut self, f: FontId, char: char, font_info: &mut FontInfo, font_type: FontType) -> Option<GlyphId> {
 		match font_type {
-			FontType::Bitmap => {
-				panic!("not surpport bitmap_table!!!")
-				// self.bitmap_table.glyph_id(f, font_info, char)
-			},
+			FontType::Bitmap => self.bitmap_table.glyph_id(f, font_info, char),
 			FontType::Sdf1 => {
 				self.sdf_table.glyph_id(f, font_info, char)
 			},
@@ -215,36 +321,45 @@ impl FontTable {
 	/// 劈分字符串并取得每个字符的字形id, 返回字符迭代器
 	pub fn split<'a>(&mut self, f: FontId, font_info: &mut FontInfo, font_type: FontType, text: &'a str, word_split: bool, merge_whitespace: bool, is_reverse: bool) -> SplitChar2<'a> {
 		match font_type {
-			FontType::Bitmap => {
-				panic!("not surpport bitmap_table!!!")
-				// self.bitmap_table.glyph_id(f, font_info, char)
-			},
-			FontType::Sdf1 => {
-				panic!("not surpport sdf1!!!")
-			},
+			FontType::Bitmap => self.bitmap_table.split(f, font_info, text, word_split, merge_whitespace, is_reverse),
+			FontType::Sdf1 => self.sdf_table.split(f, font_info, text, word_split, merge_whitespace, is_reverse),
 			FontType::Sdf2 => self.sdf2_table.split(f, font_info, text, word_split, merge_whitespace, is_reverse),
 		}
 	}
 
 	pub fn glyph_indexs<'a>(&mut self, f: FontId, font_info: &mut FontInfo, font_type: FontType, text: &'a str, is_reverse: bool) -> (String,Vec<Option<GlyphId>>) {
 		match font_type {
-			FontType::Bitmap => {
-				panic!("not surpport bitmap_table!!!")
-				// self.bitmap_table.glyph_id(f, font_info, char)
-			},
-			FontType::Sdf1 => {
-				panic!("not surpport sdf1!!!")
-			},
+			FontType::Bitmap => self.bitmap_table.glyph_indexs(f, font_info, text, is_reverse),
+			FontType::Sdf1 => self.sdf_table.glyph_indexs(f, font_info, text, is_reverse),
 			FontType::Sdf2 => self.sdf2_table.glyph_indexs(f, font_info, text, is_reverse),
 		}
 	}
+
+	/// 塑形
+	/// 
+	/// Sdf1的字体不含GSUB/GPOS信息， 不经过塑形， 每个字符作为一个字形簇
+	pub fn shape(&mut self, f: FontId, font_info: &mut FontInfo, font_type: FontType, text: &str, is_reverse: bool) -> ShapedText {
+		match font_type {
+			FontType::Bitmap => self.bitmap_table.shape(f, font_info, text, is_reverse),
+			FontType::Sdf1 => self.sdf_table.shape(f, font_info, text, is_reverse),
+			FontType::Sdf2 => self.sdf2_table.shape(f, font_info, text, is_reverse),
+		}
+	}
 	
 
+	/// 获取纹理图集代数
+	pub fn generation(&self) -> usize {
+		self.generation
+	}
+
 	/// 清空所有字体表内容
 	/// 
-	/// # 注意
-	/// 当前实现待完善，需要具体清理逻辑
+	/// 重置所有纹理装箱器，丢弃字形描述及轮廓信息，并将纹理图集代数加1
+	/// 已加载的字体数据保留
 	pub fn clear(&mut self) {
-		// TODO: 实现具体的清理逻辑
+		self.bitmap_table.clear();
+		self.sdf_table.clear();
+		self.sdf2_table.clear();
+		self.generation += 1;
 	}
 }
@@ -1,151 +1,374 @@
-//! 文字装箱算法（货架算法）
-//! 
-//! 用于管理文字在纹理图集中的空间分配，实现高效的纹理空间利用率
+//! 文字装箱算法（guillotine算法）
+//!
+//! 用于管理文字在纹理图集中的空间分配，支持释放空间后复用、占用统计及碎片整理
+//! 空间不足时，纹理尺寸成倍增长直到上限，之后再增加新的纹理页
 
-use nalgebra::Point2;
+use guillotiere::{size2, AllocId, AllocatorOptions, AtlasAllocator};
 use pi_hash::XHashMap;
 
+/// 纹理图集中的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct AtlasPos {
+    /// 纹理页索引
+    pub page: usize,
+    /// 页内X坐标（像素）
+    pub x: usize,
+    /// 页内Y坐标（像素）
+    pub y: usize,
+}
+
 /// 纹理装箱管理器
-/// 
-/// 负责管理纹理空间的分配和行布局优化
-#[derive(Debug)]
+///
+/// 负责管理纹理空间的分配与释放
 pub struct TextPacker {
-    /// 纹理图集的总宽度（像素）
+    /// 纹理页的宽度（像素），所有页尺寸相同，纹理增长时变大
     pub width: usize,
-    /// 纹理图集的总高度（像素）
+    /// 纹理页的高度（像素），所有页尺寸相同，纹理增长时变大
     pub height: usize,
-    /// 当前已分配的最后垂直位置
-    pub last_v: usize,
-    /// 行高度到行信息的映射表
-    /// - Key: 行高度
-    /// - Value: (起始坐标, 已分配字符数)
-    line_map: XHashMap<usize, (Point2<usize>, usize)>,
+    /// 纹理页宽度上限
+    max_width: usize,
+    /// 纹理页高度上限
+    max_height: usize,
+    /// 纹理页数量上限
+    max_pages: usize,
+    pages: Vec<AtlasAllocator>,
+    /// 已分配的区域
+    /// - Key: 起始位置
+    /// - Value: (分配id, 宽度, 高度)
+    allocs: XHashMap<AtlasPos, (AllocId, usize, usize)>,
+    /// 已分配区域的总面积（像素）
+    used_area: usize,
+    /// 纹理尺寸或页数是否在上次`take_resized`之后发生了变化
+    resized: bool,
+}
+
+impl std::fmt::Debug for TextPacker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TextPacker")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("pages", &self.pages.len())
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// 装箱占用统计
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PackerStats {
+    /// 所有纹理页的总面积（像素）
+    pub total_area: usize,
+    /// 已分配区域的总面积（像素）
+    pub used_area: usize,
+    /// 已分配区域的数量
+    pub alloc_count: usize,
+    /// 最大空闲矩形的面积（像素），用于判断碎片化程度
+    pub largest_free_area: usize,
+    /// 纹理页数量
+    pub page_count: usize,
+}
+
+impl PackerStats {
+    /// 占用率（0.0~1.0）
+    pub fn occupancy(&self) -> f32 {
+        if self.total_area == 0 {
+            return 0.0;
+        }
+        self.used_area as f32 / self.total_area as f32
+    }
+
+    /// 碎片率（0.0~1.0），空闲空间中不属于最大空闲矩形的比例，越大说明越需要整理
+    pub fn fragmentation(&self) -> f32 {
+        let free = self.total_area - self.used_area;
+        if free == 0 {
+            return 0.0;
+        }
+        1.0 - self.largest_free_area as f32 / free as f32
+    }
+}
+
+/// 碎片整理中，一个区域的移动（只在页内移动）
+#[derive(Debug, Clone, Copy)]
+pub struct PackerMove {
+    /// 原位置
+    pub from: AtlasPos,
+    /// 新位置
+    pub to: AtlasPos,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// 碎片整理结果
+///
+/// 调用方需要根据`moves`将纹理中的内容从原位置拷贝到新位置（或重新绘制），
+/// `dropped`中的区域在整理后无法放入纹理，已被释放，其内容需要重新分配
+#[derive(Debug, Default)]
+pub struct DefragResult {
+    pub moves: Vec<PackerMove>,
+    pub dropped: Vec<AtlasPos>,
+}
+
+fn new_allocator(width: usize, height: usize) -> AtlasAllocator {
+    AtlasAllocator::with_options(size2(width as i32, height as i32), &AllocatorOptions {
+        // 文字纹理通常较小，调低阈值，提高小矩形的查找效率
+        small_size_threshold: 16,
+        large_size_threshold: 128,
+        ..AllocatorOptions::default()
+    })
 }
 
 impl TextPacker {
-    /// 清空所有分配记录，重置状态
+    /// 清空所有分配记录，只保留第一页（保留已增长的尺寸）
     pub fn clear(&mut self) {
-        self.line_map.clear();
-        self.last_v = 0;
+        if self.pages.len() > 1 {
+            self.resized = true;
+        }
+        self.pages.truncate(1);
+        self.pages[0].clear();
+        self.allocs.clear();
+        self.used_area = 0;
     }
 
     /// 创建新的纹理装箱管理器
-    /// 
+    ///
+    /// 默认不增长，也只有一页，可通过`set_limit`设置增长上限和页数上限
+    ///
     /// # 参数
-    /// - `width`: 纹理图集宽度
-    /// - `height`: 纹理图集高度
+    /// - `width`: 纹理图集初始宽度
+    /// - `height`: 纹理图集初始高度
     pub fn new(width: usize, height: usize) -> Self {
         TextPacker {
             width,
             height,
-            line_map: XHashMap::default(),
-            last_v: 0,
+            max_width: width,
+            max_height: height,
+            max_pages: 1,
+            pages: vec![new_allocator(width, height)],
+            allocs: XHashMap::default(),
+            used_area: 0,
+            resized: false,
         }
     }
-    /// 分配指定高度的新行
-    /// 
+
+    /// 设置纹理增长上限
+    ///
     /// # 参数
-    /// - `line_height`: 请求的行高度
-    /// 
-    /// # 返回值
-    /// 返回TexLine实例用于具体字符分配
-    /// 
-    /// # 注意
-    /// 实际分配高度会进行对齐优化：
-    /// - 小于等于42像素的行按42像素对齐
-    /// - 大于42像素的行按32像素步进对齐
-    pub fn alloc_line(&mut self, mut line_height: usize) -> TexLine {
-        //每行可容纳四种字号，提高利用率
-        line_height = if line_height <= 42 {
-            42
-        } else {
-            (line_height - 42) / 32 * 32 + 32 + 42
-        };
-
-        let v = self.last_v;
-        let mut is_new = false;
-        let line = self.line_map.entry(line_height).or_insert_with(|| {
-            is_new = true;
-            (Point2::new(0, v), 0)
-        });
-        // 如果是新分配的行， self.last_v + line_height
-        if is_new {
-            // self.last_v += line_height as f32 + 1.0; // 行与行之间间隔两个个像素，以免过界采样，出现细线；如果纹理不够时，先清空纹理为蓝色，重新更新纹理，则不会出现这个问题，因为文字周围本身就有空白
-            self.last_v += line_height;
-        }
-        TexLine {
-            line: line,
-            last_v: &mut self.last_v,
-            tex_width: self.width,
-            line_height: line_height,
-        }
+    /// - `max_size`: 纹理页宽高的上限（通常为设备的max_texture_dimension_2d）
+    /// - `max_pages`: 纹理页数量上限（至少为1）
+    pub fn set_limit(&mut self, max_size: usize, max_pages: usize) {
+        self.max_width = max_size.max(self.width);
+        self.max_height = max_size.max(self.height);
+        self.max_pages = max_pages.max(1);
+    }
+
+    /// 纹理页数量
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// 取出并重置纹理变化通知
+    ///
+    /// 纹理尺寸增长、增加新页或清空时减少页数后返回true，外部应据此重建纹理（保留原有内容）
+    pub fn take_resized(&mut self) -> bool {
+        std::mem::take(&mut self.resized)
     }
 
     /// 分配指定尺寸的字符空间
-    /// 
+    ///
+    /// 所有页都放不下时，先将纹理尺寸翻倍（不超过上限），已达上限时再增加新页
+    ///
     /// # 参数
     /// - `width`: 字符宽度
     /// - `height`: 字符高度
-    /// 
+    ///
     /// # 返回值
-    /// 返回Option包装的起始坐标，None表示分配失败（超出纹理范围）
-    pub fn alloc(&mut self, width: usize, height: usize) -> Option<Point2<usize>> {
-        let mut line = self.alloc_line(height);
-        let p = line.alloc(width);
-
-        // 超出最大纹理范围，需要清空所有文字，重新布局
-        if *(line.last_v) > self.height {
-            return None; // 0表示异常情况，不能计算字形
-        } else {
-            Some(p)
+    /// 返回Option包装的位置，None表示分配失败（纹理尺寸和页数都已达上限，或宽高为0）
+    pub fn alloc(&mut self, width: usize, height: usize) -> Option<AtlasPos> {
+        // 空矩形无法分配，不需要增长纹理
+        if width == 0 || height == 0 || width > self.max_width || height > self.max_height {
+            return None;
+        }
+        loop {
+            for (page, allocator) in self.pages.iter_mut().enumerate() {
+                if let Some(allocation) = allocator.allocate(size2(width as i32, height as i32)) {
+                    let pos = AtlasPos {
+                        page,
+                        x: allocation.rectangle.min.x as usize,
+                        y: allocation.rectangle.min.y as usize,
+                    };
+                    self.allocs.insert(pos, (allocation.id, width, height));
+                    self.used_area += width * height;
+                    return Some(pos);
+                }
+            }
+
+            if self.width < self.max_width || self.height < self.max_height {
+                // 增长时只有一页
+                self.width = (self.width * 2).max(1).min(self.max_width);
+                self.height = (self.height * 2).max(1).min(self.max_height);
+                self.pages[0].grow(size2(self.width as i32, self.height as i32));
+                self.resized = true;
+                log::info!("atlas grow to {}x{}", self.width, self.height);
+            } else if self.pages.len() < self.max_pages {
+                self.pages.push(new_allocator(self.width, self.height));
+                self.resized = true;
+                log::info!("atlas add page, page_count: {}", self.pages.len());
+            } else {
+                return None;
+            }
         }
     }
 
-    // fn update(&self, tex: Res<TextureRes>, u: f32, v: f32, w: f32, h: f32, data: &Object) {
-    //     if v + h > self.last_v {
-    //         // 纹理高度扩展1倍
-    //     }
-    //     self.tex.bind.update_webgl(tex, u, v, w, h, data);
-    // }
-}
+    /// 释放之前分配的空间，释放后的空间可被再次分配
+    ///
+    /// # 参数
+    /// - `pos`: 分配时返回的位置
+    pub fn dealloc(&mut self, pos: AtlasPos) {
+        if let Some((id, width, height)) = self.allocs.remove(&pos) {
+            self.pages[pos.page].deallocate(id);
+            self.used_area -= width * height;
+        }
+    }
 
-/// 单行分配管理器
-/// 
-/// 负责单行内的水平空间分配和行状态管理
-#[derive(Debug)]
-pub struct TexLine<'a> {
-    line: &'a mut (Point2<usize>, usize),
-    pub last_v: &'a mut usize,
-    pub tex_width: usize,
-    line_height: usize,
+    /// 占用统计
+    pub fn stats(&self) -> PackerStats {
+        let mut largest_free_area = 0;
+        for allocator in self.pages.iter() {
+            allocator.for_each_free_rectangle(|r| {
+                largest_free_area = largest_free_area.max(r.area() as usize);
+            });
+        }
+        PackerStats {
+            total_area: self.width * self.height * self.pages.len(),
+            used_area: self.used_area,
+            alloc_count: self.allocs.len(),
+            largest_free_area,
+            page_count: self.pages.len(),
+        }
+    }
+
+    /// 碎片整理
+    ///
+    /// 在每一页内，按面积从大到小重新放置所有已分配区域，返回每个区域的移动信息
+    /// 整理后，之前返回的位置全部以`DefragResult`中的新位置为准
+    pub fn defrag(&mut self) -> DefragResult {
+        let mut result = DefragResult::default();
+
+        // 按(页, 分配id)找到原区域
+        let mut by_id = XHashMap::default();
+        for (pos, (id, width, height)) in self.allocs.drain() {
+            by_id.insert((pos.page, id), (pos, width, height));
+        }
+
+        for (page, allocator) in self.pages.iter_mut().enumerate() {
+            let changes = allocator.rearrange();
+            for change in changes.changes {
+                if let Some((from, width, height)) = by_id.remove(&(page, change.old.id)) {
+                    let to = AtlasPos {
+                        page,
+                        x: change.new.rectangle.min.x as usize,
+                        y: change.new.rectangle.min.y as usize,
+                    };
+                    self.allocs.insert(to, (change.new.id, width, height));
+                    result.moves.push(PackerMove { from, to, width, height });
+                }
+            }
+            for failure in changes.failures {
+                if let Some((from, width, height)) = by_id.remove(&(page, failure.id)) {
+                    self.used_area -= width * height;
+                    result.dropped.push(from);
+                }
+            }
+        }
+        result
+    }
 }
-impl<'a> TexLine<'a> {
-    /// 获取当前行的垂直起始位置
-    pub fn get_v(&self) -> usize {
-        self.line.0.y
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alloc_and_reuse() {
+        let mut packer = TextPacker::new(64, 64);
+        let a = packer.alloc(32, 32).unwrap();
+        let b = packer.alloc(32, 32).unwrap();
+        assert_ne!(a, b);
+        assert_eq!(packer.stats().used_area, 2048);
+        packer.dealloc(a);
+        assert_eq!(packer.stats().alloc_count, 1);
+        assert!(packer.alloc(32, 32).is_some());
+        assert!(!packer.take_resized());
     }
-    /// 分配字符的水平空间
-    /// 
-    /// # 参数
-    /// - `char_width`: 字符宽度
-    /// 
-    /// # 返回值
-    /// 返回字符的起始坐标
-    /// 
-    /// # 注意
-    /// 当行空间不足时会自动换行并更新全局垂直位置
-    pub fn alloc(&mut self, char_width: usize) -> Point2<usize> {
-        if self.tex_width >= self.line.0.x + char_width {
-            let r = self.line.0.clone();
-            self.line.0.x += char_width;
-            self.line.1 += 1;
-            r
-        } else {
-            self.line.0.x = char_width;
-            self.line.0.y = *self.last_v;
-            self.line.1 = 1;
-            *self.last_v += self.line_height;
-            Point2::new(0, self.line.0.y)
+
+    #[test]
+    fn no_growth_by_default() {
+        let mut packer = TextPacker::new(32, 32);
+        assert!(packer.alloc(32, 32).is_some());
+        assert!(packer.alloc(8, 8).is_none());
+        assert!(packer.alloc(64, 8).is_none());
+        assert_eq!(packer.page_count(), 1);
+    }
+
+    #[test]
+    fn empty_size() {
+        let mut packer = TextPacker::new(32, 32);
+        packer.set_limit(128, 4);
+        assert!(packer.alloc(0, 8).is_none());
+        assert!(packer.alloc(8, 0).is_none());
+        // 不会为空矩形增长纹理或增加新页
+        assert_eq!((packer.width, packer.height, packer.page_count()), (32, 32, 1));
+        assert!(!packer.take_resized());
+    }
+
+    #[test]
+    fn grow_then_add_page() {
+        let mut packer = TextPacker::new(32, 32);
+        packer.set_limit(64, 2);
+        assert!(packer.alloc(32, 32).is_some());
+        let pos = packer.alloc(32, 32).unwrap();
+        assert_eq!((packer.width, packer.height, pos.page), (64, 64, 0));
+        assert!(packer.take_resized());
+        assert!(!packer.take_resized());
+
+        // 已达尺寸上限， 放不下时增加新页
+        let pos = packer.alloc(64, 64).unwrap();
+        assert_eq!((pos.page, packer.page_count()), (1, 2));
+        assert!(packer.take_resized());
+        assert!(packer.alloc(64, 64).is_none());
+
+        // 清空后只保留第一页（尺寸不变）
+        packer.clear();
+        assert_eq!((packer.width, packer.page_count()), (64, 1));
+        assert!(packer.take_resized());
+        assert_eq!(packer.stats().used_area, 0);
+    }
+
+    #[test]
+    fn stats() {
+        let mut packer = TextPacker::new(64, 64);
+        assert_eq!(packer.stats().occupancy(), 0.0);
+        packer.alloc(32, 64).unwrap();
+        let stats = packer.stats();
+        assert_eq!(stats.occupancy(), 0.5);
+        assert_eq!(stats.largest_free_area, 2048);
+        assert_eq!(stats.fragmentation(), 0.0);
+    }
+
+    #[test]
+    fn defrag() {
+        let mut packer = TextPacker::new(64, 64);
+        let all = (0..4).map(|_| packer.alloc(32, 32).unwrap()).collect::<Vec<AtlasPos>>();
+        packer.dealloc(all[0]);
+        packer.dealloc(all[3]);
+        let result = packer.defrag();
+        assert!(result.dropped.is_empty());
+        assert_eq!(result.moves.len(), 2);
+        assert_eq!(packer.stats().alloc_count, 2);
+        // 整理后以新位置为准， 可以释放
+        for m in result.moves.iter() {
+            packer.dealloc(m.to);
         }
+        assert_eq!(packer.stats().used_area, 0);
     }
 }
@@ -1,11 +1,11 @@
 //! 文字劈分算法
-use std::str::Chars;
+use std::{iter::Map, ops::Range, str::Chars};
 
 use image::Primitive;
 use pi_ucd::Codepoint;
-use unicode_segmentation::UnicodeSegmentation;
+use unicode_segmentation::{Graphemes, UnicodeSegmentation};
 
-use super::font::GlyphId;
+use super::{bidi::BidiMap, font::GlyphId, line_break::{break_table, is_breaking_whitespace, is_mandatory_char, BreakKind, LineBreakStrictness}, shape::{ShapedCluster, ShapedText}};
 
 #[derive(Debug)]
 // 劈分结果
@@ -18,19 +18,119 @@ pub enum SplitResult {
     WordEnd(isize),         // 单词字符结束
 }
 
+/// 字素簇（用户感知的一个字符， 如表情序列、国旗、基字符加组合标记）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grapheme {
+    /// 字素簇的第一个字符（"\r\n"为'\n'）
+    pub char: char,
+    /// 在原文本中的字节范围
+    pub byte_range: Range<usize>,
+    /// 在原文本中的字符范围
+    pub char_range: Range<usize>,
+}
+
+/// 将文本劈分为扩展字素簇（UAX #29）
+pub fn graphemes(text: &str) -> Vec<Grapheme> {
+    let mut char_start = 0;
+    text.grapheme_indices(true).map(|(i, g)| {
+        let count = g.chars().count();
+        let r = Grapheme {
+            char: first_char(g),
+            byte_range: i..i + g.len(),
+            char_range: char_start..char_start + count,
+        };
+        char_start += count;
+        r
+    }).collect()
+}
+
+/// 字素簇的代表字符， 即第一个字符， "\r\n"作为一个换行符
+pub fn first_char(s: &str) -> char {
+    if s.starts_with("\r\n") {
+        return '\n';
+    }
+    s.chars().next().unwrap_or(' ')
+}
+
 // 劈分字符迭代器
+// 以字素簇为单位劈分， 劈分结果中的索引为字素簇的索引， 字符为字素簇的第一个字符
 pub struct SplitChar<'a> {
 	cur_index: usize,
-    iter: Chars<'a>,
+    iter: Map<Graphemes<'a>, fn(&'a str) -> char>,
     word_split: bool,
     merge_whitespace: bool,
     last: Option<char>,
     type_id: usize, // 0表示单字词, 1表示ascii字母 2及以上代表字符的type_id, MAX表示数字
+    graphemes: Vec<Grapheme>, // 每个字素簇在原文本中的范围
+    line_breaks: Option<Vec<Option<BreakKind>>>, // 按换行算法劈分时， 每个字素簇之前的换行机会
+}
+
+impl<'a> SplitChar<'a> {
+    /// 劈分结果中索引对应的字素簇（包括其在原文本中的字节范围及字符范围）
+    pub fn cluster(&self, index: usize) -> Option<&Grapheme> {
+        self.graphemes.get(index)
+    }
+
+    // 按换行算法劈分， 两个换行机会之间的字符组成一个单词
+    fn next_line_break(&mut self) -> Option<SplitResult> {
+        match self.last {
+            Some(c) if self.type_id == 0 => {
+                if is_mandatory_char(c) && self.break_before(self.cur_index + 1) == Some(BreakKind::Mandatory) {
+                    self.last = self.iter.next();
+                    self.cur_index += 1;
+                    Some(SplitResult::Newline((self.cur_index - 1) as isize))
+                } else if is_breaking_whitespace(c) {
+                    loop {
+                        self.last = self.iter.next();
+                        self.cur_index += 1;
+                        match self.last {
+                            Some(cc) if self.merge_whitespace && is_breaking_whitespace(cc) && !is_mandatory_char(cc) => continue,
+                            _ => break,
+                        }
+                    }
+                    Some(SplitResult::Whitespace((self.cur_index - 1) as isize))
+                } else if self.iter.clone().next().is_some_and(|next| self.joins(self.cur_index + 1, next)) {
+                    // 单词开始，不读取下个字符
+                    self.type_id = 1;
+                    Some(SplitResult::WordStart(self.cur_index as isize, c))
+                } else {
+                    self.last = self.iter.next();
+                    self.cur_index += 1;
+                    Some(SplitResult::Word((self.cur_index - 1) as isize, c))
+                }
+            }
+            Some(_) => {
+                self.last = self.iter.next();
+                self.cur_index += 1;
+                match self.last {
+                    Some(c) if self.joins(self.cur_index, c) => Some(SplitResult::WordNext(self.cur_index as isize, c)),
+                    _ => {
+                        self.type_id = 0;
+                        Some(SplitResult::WordEnd(-1))
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // 第index个字符之前的换行机会
+    fn break_before(&self, index: usize) -> Option<BreakKind> {
+        self.line_breaks.as_ref().and_then(|r| r.get(index).copied().unwrap_or(Some(BreakKind::Mandatory)))
+    }
+
+    // 第index个字符c是否与之前的字符在同一单词中
+    fn joins(&self, index: usize, c: char) -> bool {
+        self.break_before(index).is_none() && !is_breaking_whitespace(c) && !is_mandatory_char(c)
+    }
 }
 
 impl<'a> Iterator for SplitChar<'a> {
     type Item = SplitResult;
     fn next(&mut self) -> Option<Self::Item> {
+        if self.line_breaks.is_some() {
+            return self.next_line_break();
+        }
         match self.last {
             Some(c) if self.type_id == 0 => {
                 if c == '\n' {
@@ -123,9 +223,9 @@ fn get_type_id(c: char, prev: char) -> usize {
     }
     0
 }
-/// 劈分字符串, 返回字符迭代器
+/// 劈分字符串, 返回字符迭代器（以字素簇为单位）
 pub fn split<'a>(s: &'a str, word_split: bool, merge_whitespace: bool) -> SplitChar<'a> {
-    let mut i = s.chars();
+    let mut i = s.graphemes(true).map(first_char as fn(&'a str) -> char);
     let last = i.next();
     SplitChar {
 		cur_index: 0,
@@ -134,9 +234,23 @@ pub fn split<'a>(s: &'a str, word_split: bool, merge_whitespace: bool) -> SplitC
         merge_whitespace: merge_whitespace,
         last: last,
         type_id: 0,
+        graphemes: graphemes(s),
+        line_breaks: None,
     }
 }
 
+/// 按Unicode换行算法（UAX #14）劈分字符串, 返回字符迭代器
+///
+/// 两个换行机会之间的字符组成一个单词（不含其后的空白）， 必须换行处返回Newline
+///
+/// # 参数
+/// - `strictness`: 换行严格程度（如日文的小假名、长音符之前是否可以换行）
+pub fn split_line_break<'a>(s: &'a str, strictness: LineBreakStrictness, merge_whitespace: bool) -> SplitChar<'a> {
+    let mut r = split(s, true, merge_whitespace);
+    r.line_breaks = Some(break_table(s, r.graphemes.iter().map(|g| g.byte_range.start), strictness));
+    r
+}
+
 #[derive(Debug)]
 // 劈分结果
 pub enum SplitResult2 {
@@ -158,11 +272,106 @@ pub struct SplitChar2<'a> {
     pub(crate) last: Option<char>,
     pub(crate) type_id: usize, // 0表示单字词, 1表示ascii字母 2及以上代表字符的type_id, MAX表示数字
     pub(crate) glyph_ids: Vec<Option<GlyphId>>, // 每个字符的字形id
+    pub(crate) shaped: Option<ShapedText>, // 塑形结果， 存在时， text及glyph_ids为每个字形簇的第一个字符及其第一个字形
+    pub(crate) line_breaks: Option<Vec<Option<BreakKind>>>, // 按换行算法劈分时， 每个劈分单元之前的换行机会
+}
+
+impl<'a> SplitChar2<'a> {
+    /// 塑形结果， 存在时， 劈分结果中的索引为字形簇的索引
+    pub fn shaped(&self) -> Option<&ShapedText> {
+        self.shaped.as_ref()
+    }
+
+    /// 劈分结果中索引对应的字形簇（包括字形簇在原文本中的范围， 及其所有字形）
+    pub fn cluster(&self, index: usize) -> Option<&ShapedCluster> {
+        self.shaped.as_ref().and_then(|r| r.clusters.get(index))
+    }
+
+    /// 一行的逻辑与视觉索引映射
+    ///
+    /// 劈分结果按逻辑顺序排列， 换行后， 用每行的劈分索引范围取得该行的视觉顺序
+    /// 映射中的索引相对于`line.start`
+    pub fn line_map(&self, line: Range<usize>) -> BidiMap {
+        match &self.shaped {
+            Some(r) => r.line_map(line),
+            None => BidiMap::new(vec![0; line.len()]),
+        }
+    }
+
+    /// 按Unicode换行算法（UAX #14）劈分
+    ///
+    /// 两个换行机会之间的字形簇组成一个单词（不含其后的空白）， 必须换行处返回Newline
+    ///
+    /// # 参数
+    /// - `text`: 创建本迭代器时的原文本
+    /// - `strictness`: 换行严格程度
+    pub fn with_line_break(mut self, text: &str, strictness: LineBreakStrictness) -> Self {
+        let table = match &self.shaped {
+            Some(r) => break_table(text, r.clusters.iter().map(|c| c.byte_range.start), strictness),
+            None => break_table(text, graphemes(text).into_iter().map(|g| g.byte_range.start), strictness),
+        };
+        self.line_breaks = Some(table);
+        self
+    }
+
+    /// 劈分单元之前的换行机会， 未按换行算法劈分时返回None
+    pub fn break_before(&self, index: usize) -> Option<BreakKind> {
+        self.line_breaks.as_ref().and_then(|r| r.get(index).copied().unwrap_or(Some(BreakKind::Mandatory)))
+    }
+
+    fn glyph_id(&self, index: usize) -> Option<GlyphId> {
+        self.glyph_ids.get(index).copied().flatten()
+    }
+
+    // 第index个劈分单元c是否与之前的单元在同一单词中
+    fn joins(&self, index: usize, c: char) -> bool {
+        self.break_before(index).is_none() && !is_breaking_whitespace(c) && !is_mandatory_char(c)
+    }
+
+    // 按换行算法劈分， 两个换行机会之间的劈分单元组成一个单词
+    fn next_line_break(&mut self) -> Option<SplitResult2> {
+        let c = *self.text.get(self.cur_index)?;
+        if self.type_id == 0 {
+            let index = self.cur_index;
+            if is_mandatory_char(c) && self.break_before(index + 1) == Some(BreakKind::Mandatory) {
+                self.cur_index += 1;
+                Some(SplitResult2::Newline(index as isize))
+            } else if is_breaking_whitespace(c) {
+                self.cur_index += 1;
+                while self.merge_whitespace {
+                    match self.text.get(self.cur_index) {
+                        Some(cc) if is_breaking_whitespace(*cc) && !is_mandatory_char(*cc) => self.cur_index += 1,
+                        _ => break,
+                    }
+                }
+                Some(SplitResult2::Whitespace((self.cur_index - 1) as isize, self.glyph_id(self.cur_index - 1)))
+            } else if self.text.get(index + 1).is_some_and(|next| self.joins(index + 1, *next)) {
+                // 单词开始，不读取下个字符
+                self.type_id = 1;
+                Some(SplitResult2::WordStart(index as isize, c, self.glyph_id(index)))
+            } else {
+                self.cur_index += 1;
+                Some(SplitResult2::Word(index as isize, c, self.glyph_id(index)))
+            }
+        } else {
+            self.cur_index += 1;
+            match self.text.get(self.cur_index) {
+                Some(c) if self.joins(self.cur_index, *c) => Some(SplitResult2::WordNext(self.cur_index as isize, *c, self.glyph_id(self.cur_index))),
+                _ => {
+                    self.type_id = 0;
+                    Some(SplitResult2::WordEnd(-1))
+                }
+            }
+        }
+    }
 }
 
 impl<'a> Iterator for SplitChar2<'a> {
     type Item = SplitResult2;
     fn next(&mut self) -> Option<Self::Item> {
+        if self.line_breaks.is_some() {
+            return self.next_line_break();
+        }
         match self.text.get(self.cur_index) {
             Some(c) if self.type_id == 0 => {
                 let c = *c;
@@ -235,6 +444,7 @@ impl<'a> Iterator for SplitChar2<'a> {
     }
 }
 
+/// 仅反转阿拉伯文单词边界的旧重排方式， 已由`bidi`模块的双向算法取代
 pub fn text_split(text: &str, reverse: bool)->String{
     let g = text.split_word_bounds().collect::<Vec<&str>>();
 
@@ -0,0 +1,751 @@
+//! WOFF、WOFF2网络字体解码
+//!
+//! 添加字体数据时检测格式， 将WOFF（zlib压缩）、WOFF2（Brotli压缩及glyf、loca、hmtx表变换）还原为sfnt（ttf、otf、ttc），
+//! 字体表只处理sfnt数据
+
+use std::io::Read;
+
+use pi_hash::XHashMap;
+use pi_share::Share;
+
+/// 字体数据格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontFormat {
+    /// ttf、otf及字体集合ttc、otc
+    Sfnt,
+    Woff,
+    Woff2,
+    Unknown,
+}
+
+impl FontFormat {
+    /// 按文件头检测格式
+    pub fn detect(data: &[u8]) -> Self {
+        match data.get(0..4) {
+            Some([0, 1, 0, 0] | b"OTTO" | b"true" | b"typ1" | b"ttcf") => FontFormat::Sfnt,
+            Some(b"wOFF") => FontFormat::Woff,
+            Some(b"wOF2") => FontFormat::Woff2,
+            _ => FontFormat::Unknown,
+        }
+    }
+}
+
+/// 将WOFF、WOFF2数据解码为sfnt， 其他格式原样返回
+///
+/// 解码失败时记录警告并返回原数据
+pub fn to_sfnt(buffer: Share<Vec<u8>>) -> Share<Vec<u8>> {
+    let format = FontFormat::detect(&buffer);
+    let r = match format {
+        FontFormat::Woff => decode_woff(&buffer),
+        FontFormat::Woff2 => decode_woff2(&buffer),
+        _ => return buffer,
+    };
+    match r {
+        Some(r) => Share::new(r),
+        None => {
+            log::warn!("decode {:?} font fail, len: {}", format, buffer.len());
+            buffer
+        }
+    }
+}
+
+/// 解码WOFF、WOFF2数据， 不是WOFF、WOFF2或数据错误时返回None
+pub fn decode(data: &[u8]) -> Option<Vec<u8>> {
+    match FontFormat::detect(data) {
+        FontFormat::Woff => decode_woff(data),
+        FontFormat::Woff2 => decode_woff2(data),
+        _ => None,
+    }
+}
+
+const TTCF: u32 = u32::from_be_bytes(*b"ttcf");
+const GLYF: [u8; 4] = *b"glyf";
+const LOCA: [u8; 4] = *b"loca";
+const HMTX: [u8; 4] = *b"hmtx";
+const HHEA: [u8; 4] = *b"hhea";
+
+// 解压后数据的大小上限， 避免错误数据申请过多内存
+const MAX_SFNT_SIZE: usize = 256 * 1024 * 1024;
+
+// WOFF1: 表逐个用zlib压缩
+fn decode_woff(data: &[u8]) -> Option<Vec<u8>> {
+    let mut r = Reader::new(data);
+    r.skip(4)?;
+    let flavor = r.u32()?;
+    let _length = r.u32()?;
+    let num_tables = r.u16()? as usize;
+    r.skip(2)?;
+    let total_sfnt_size = r.u32()? as usize;
+    if total_sfnt_size > MAX_SFNT_SIZE {
+        return None;
+    }
+    // majorVersion、minorVersion、元数据及私有数据
+    r.skip(24)?;
+
+    let mut tables = Vec::with_capacity(num_tables);
+    for _ in 0..num_tables {
+        let tag = r.tag()?;
+        let offset = r.u32()? as usize;
+        let comp_length = r.u32()? as usize;
+        let orig_length = r.u32()? as usize;
+        let _checksum = r.u32()?;
+        let src = data.get(offset..offset.checked_add(comp_length)?)?;
+        let table = if comp_length < orig_length {
+            let r = miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(src, orig_length).ok()?;
+            if r.len() != orig_length {
+                return None;
+            }
+            r
+        } else if comp_length == orig_length {
+            src.to_vec()
+        } else {
+            return None;
+        };
+        tables.push((tag, table));
+    }
+    let fonts = [(flavor, (0..tables.len()).collect())];
+    Some(build_sfnt(&fonts, &tables, false))
+}
+
+// WOFF2表目录中的已知表
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm", b"glyf", b"loca", b"prep",
+    b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern", b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE",
+    b"GDEF", b"GPOS", b"GSUB", b"EBSC", b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt",
+    b"avar", b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty", b"just", b"lcar",
+    b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat", b"Gloc", b"Feat", b"Sill",
+];
+
+// WOFF2表目录项
+struct Woff2Table {
+    tag: [u8; 4],
+    transformed: bool,
+    orig_length: usize,
+    // 在解压数据中的范围
+    start: usize,
+    end: usize,
+}
+
+// WOFF2: 所有表整体用Brotli压缩， glyf、loca、hmtx可以变换后存储
+fn decode_woff2(data: &[u8]) -> Option<Vec<u8>> {
+    let mut r = Reader::new(data);
+    r.skip(4)?;
+    let flavor = r.u32()?;
+    let _length = r.u32()?;
+    let num_tables = r.u16()? as usize;
+    r.skip(2)?;
+    let total_sfnt_size = r.u32()? as usize;
+    let total_compressed_size = r.u32()? as usize;
+    if total_sfnt_size > MAX_SFNT_SIZE {
+        return None;
+    }
+    // majorVersion、minorVersion、元数据及私有数据
+    r.skip(24)?;
+
+    // 表目录
+    let mut tables = Vec::with_capacity(num_tables);
+    let mut offset = 0usize;
+    for _ in 0..num_tables {
+        let flags = r.u8()?;
+        let tag = match flags & 0x3f {
+            63 => r.tag()?,
+            i => *KNOWN_TAGS[i as usize],
+        };
+        let version = flags >> 6;
+        // glyf、loca的变换版本0为变换， 3为不变换； 其他表版本0为不变换
+        let transformed = if tag == GLYF || tag == LOCA { version == 0 } else { version != 0 };
+        let orig_length = r.base128()? as usize;
+        let length = if transformed { r.base128()? as usize } else { orig_length };
+        if transformed && tag != GLYF && tag != LOCA && tag != HMTX {
+            return None;
+        }
+        let end = offset.checked_add(length)?;
+        tables.push(Woff2Table { tag, transformed, orig_length, start: offset, end });
+        offset = end;
+    }
+    if offset > MAX_SFNT_SIZE {
+        return None;
+    }
+
+    // 字体集合目录
+    let fonts = if flavor == TTCF {
+        let _version = r.u32()?;
+        let num_fonts = r.u255_16()? as usize;
+        let mut fonts = Vec::with_capacity(num_fonts);
+        for _ in 0..num_fonts {
+            let n = r.u255_16()? as usize;
+            let flavor = r.u32()?;
+            let mut indices = Vec::with_capacity(n);
+            for _ in 0..n {
+                let i = r.u255_16()? as usize;
+                if i >= tables.len() {
+                    return None;
+                }
+                indices.push(i);
+            }
+            fonts.push((flavor, indices));
+        }
+        fonts
+    } else {
+        vec![(flavor, (0..tables.len()).collect::<Vec<usize>>())]
+    };
+
+    // 解压
+    let src = r.bytes(total_compressed_size)?;
+    let mut stream = Vec::with_capacity(offset);
+    brotli_decompressor::Decompressor::new(src, 4096)
+        .take(offset as u64 + 1)
+        .read_to_end(&mut stream)
+        .ok()?;
+    if stream.len() != offset {
+        return None;
+    }
+
+    // 还原各表， glyf、loca一起还原， hmtx依赖glyf中字形的包围盒
+    let mut out: Vec<Option<Vec<u8>>> = vec![None; tables.len()];
+    let mut x_mins: XHashMap<usize, Vec<i16>> = XHashMap::default();
+    for (_, indices) in fonts.iter() {
+        let find = |tag: [u8; 4]| indices.iter().copied().find(|i| tables[*i].tag == tag);
+        if let (Some(glyf), Some(loca)) = (find(GLYF), find(LOCA)) {
+            if tables[glyf].transformed != tables[loca].transformed {
+                return None;
+            }
+            if tables[glyf].transformed && out[glyf].is_none() {
+                let (glyf_data, loca_data, mins) = reconstruct_glyf(&stream[tables[glyf].start..tables[glyf].end])?;
+                if loca_data.len() != tables[loca].orig_length {
+                    return None;
+                }
+                out[glyf] = Some(glyf_data);
+                out[loca] = Some(loca_data);
+                x_mins.insert(glyf, mins);
+            }
+        }
+        for i in indices.iter().copied() {
+            let table = &tables[i];
+            if out[i].is_some() || (table.transformed && table.tag != HMTX) {
+                continue;
+            }
+            if !table.transformed {
+                out[i] = Some(stream[table.start..table.end].to_vec());
+                continue;
+            }
+            // 变换的hmtx
+            let hhea = find(HHEA).map(|i| &stream[tables[i].start..tables[i].end])?;
+            let mins = find(GLYF).and_then(|i| x_mins.get(&i))?;
+            let hmtx = reconstruct_hmtx(&stream[table.start..table.end], hhea, mins)?;
+            if hmtx.len() != table.orig_length {
+                return None;
+            }
+            out[i] = Some(hmtx);
+        }
+    }
+
+    let tables = tables.iter().zip(out).map(|(t, data)| Some((t.tag, data?))).collect::<Option<Vec<_>>>()?;
+    Some(build_sfnt(&fonts, &tables, flavor == TTCF))
+}
+
+// 还原变换的glyf表， 返回glyf表、loca表及每个字形的xMin
+fn reconstruct_glyf(data: &[u8]) -> Option<(Vec<u8>, Vec<u8>, Vec<i16>)> {
+    let mut r = Reader::new(data);
+    r.skip(2)?;
+    let option_flags = r.u16()?;
+    let num_glyphs = r.u16()? as usize;
+    let index_format = r.u16()?;
+    let mut sizes = [0usize; 7];
+    for size in sizes.iter_mut() {
+        *size = r.u32()? as usize;
+    }
+    let [n_contour, n_points, flag, glyph, composite, bbox, instruction] = sizes.map(|size| r.bytes(size).map(Reader::new));
+    let (mut n_contour, mut n_points, mut flag, mut glyph, mut composite, mut bbox, mut instruction) =
+        (n_contour?, n_points?, flag?, glyph?, composite?, bbox?, instruction?);
+    let overlap = if option_flags & 1 != 0 { Some(r.bytes((num_glyphs + 7) >> 3)?) } else { None };
+    let bbox_bitmap = bbox.bytes(((num_glyphs + 31) >> 5) * 4)?;
+
+    let mut glyf = Vec::new();
+    let mut offsets = Vec::with_capacity(num_glyphs + 1);
+    let mut x_mins = Vec::with_capacity(num_glyphs);
+    let mut points: Vec<(i32, i32, bool)> = Vec::new();
+    for i in 0..num_glyphs {
+        offsets.push(glyf.len());
+        let has_bbox = bbox_bitmap[i >> 3] & (0x80 >> (i & 7)) != 0;
+        let contours = n_contour.u16()? as i16;
+        if contours == 0 {
+            // 空字形
+            if has_bbox {
+                return None;
+            }
+            x_mins.push(0);
+            continue;
+        }
+
+        if contours < 0 {
+            // 复合字形， 必须有包围盒
+            if !has_bbox {
+                return None;
+            }
+            let rect = bbox.bytes(8)?;
+            x_mins.push(i16::from_be_bytes([rect[0], rect[1]]));
+            put_u16(&mut glyf, contours as u16);
+            glyf.extend_from_slice(rect);
+
+            let start = composite.pos;
+            let mut have_instructions = false;
+            loop {
+                let flags = composite.u16()?;
+                composite.skip(2)?;
+                let args = if flags & 0x0001 != 0 { 4 } else { 2 };
+                let scale = if flags & 0x0008 != 0 {
+                    2
+                } else if flags & 0x0040 != 0 {
+                    4
+                } else if flags & 0x0080 != 0 {
+                    8
+                } else {
+                    0
+                };
+                composite.skip(args + scale)?;
+                have_instructions |= flags & 0x0100 != 0;
+                if flags & 0x0020 == 0 {
+                    break;
+                }
+            }
+            glyf.extend_from_slice(&composite.data[start..composite.pos]);
+            if have_instructions {
+                let len = glyph.u255_16()? as usize;
+                put_u16(&mut glyf, len as u16);
+                glyf.extend_from_slice(instruction.bytes(len)?);
+            }
+        } else {
+            // 简单字形
+            let mut end_points = Vec::with_capacity(contours as usize);
+            let mut total = 0usize;
+            for _ in 0..contours {
+                total = total.checked_add(n_points.u255_16()? as usize)?;
+                end_points.push(total.checked_sub(1)?);
+            }
+            if total > u16::MAX as usize + 1 {
+                return None;
+            }
+
+            points.clear();
+            let (mut x, mut y) = (0i32, 0i32);
+            for _ in 0..total {
+                let f = flag.u8()?;
+                let (dx, dy) = triplet(f & 0x7f, &mut glyph)?;
+                x += dx;
+                y += dy;
+                points.push((x, y, f & 0x80 == 0));
+            }
+            let instruction_length = glyph.u255_16()? as usize;
+
+            let rect = if has_bbox {
+                let r = bbox.bytes(8)?;
+                [0, 2, 4, 6].map(|k| i16::from_be_bytes([r[k], r[k + 1]]))
+            } else {
+                let first = points.first().map_or((0, 0), |p| (p.0, p.1));
+                let r = points.iter().fold([first.0, first.1, first.0, first.1], |r, p| {
+                    [r[0].min(p.0), r[1].min(p.1), r[2].max(p.0), r[3].max(p.1)]
+                });
+                r.map(|v| v as i16)
+            };
+            x_mins.push(rect[0]);
+            put_u16(&mut glyf, contours as u16);
+            for v in rect {
+                put_u16(&mut glyf, v as u16);
+            }
+            for e in end_points {
+                put_u16(&mut glyf, e as u16);
+            }
+            put_u16(&mut glyf, instruction_length as u16);
+            glyf.extend_from_slice(instruction.bytes(instruction_length)?);
+
+            let overlap = overlap.is_some_and(|r| r[i >> 3] & (0x80 >> (i & 7)) != 0);
+            write_points(&mut glyf, &points, overlap);
+        }
+        // 字形按4字节对齐
+        while glyf.len() % 4 != 0 {
+            glyf.push(0);
+        }
+    }
+    offsets.push(glyf.len());
+
+    let mut loca = Vec::with_capacity(offsets.len() * 4);
+    for offset in offsets {
+        if index_format == 0 {
+            put_u16(&mut loca, u16::try_from(offset / 2).ok()?);
+        } else {
+            put_u32(&mut loca, offset as u32);
+        }
+    }
+    Some((glyf, loca, x_mins))
+}
+
+// 解码一个点的坐标增量
+fn triplet(flag: u8, r: &mut Reader) -> Option<(i32, i32)> {
+    let with_sign = |flag: u8, v: i32| if flag & 1 != 0 { v } else { -v };
+    let flag_i = flag as i32;
+    Some(if flag < 10 {
+        let b0 = r.u8()? as i32;
+        (0, with_sign(flag, ((flag_i & 14) << 7) + b0))
+    } else if flag < 20 {
+        let b0 = r.u8()? as i32;
+        (with_sign(flag, (((flag_i - 10) & 14) << 7) + b0), 0)
+    } else if flag < 84 {
+        let b0 = flag_i - 20;
+        let b1 = r.u8()? as i32;
+        (with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)), with_sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)))
+    } else if flag < 120 {
+        let b0 = flag_i - 84;
+        let (b1, b2) = (r.u8()? as i32, r.u8()? as i32);
+        (with_sign(flag, 1 + ((b0 / 12) << 8) + b1), with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2))
+    } else if flag < 124 {
+        let (b1, b2, b3) = (r.u8()? as i32, r.u8()? as i32, r.u8()? as i32);
+        (with_sign(flag, (b1 << 4) + (b2 >> 4)), with_sign(flag >> 1, ((b2 & 0x0f) << 8) + b3))
+    } else {
+        let (b1, b2, b3, b4) = (r.u8()? as i32, r.u8()? as i32, r.u8()? as i32, r.u8()? as i32);
+        (with_sign(flag, (b1 << 8) + b2), with_sign(flag >> 1, (b3 << 8) + b4))
+    })
+}
+
+// 按TrueType简单字形的格式写入点的标志及坐标
+fn write_points(out: &mut Vec<u8>, points: &[(i32, i32, bool)], overlap: bool) {
+    let mut flags = Vec::with_capacity(points.len());
+    let mut xs = Vec::new();
+    let mut ys = Vec::new();
+    let (mut last_x, mut last_y) = (0, 0);
+    for (i, (x, y, on_curve)) in points.iter().copied().enumerate() {
+        let mut f = if on_curve { 0x01u8 } else { 0 };
+        if overlap && i == 0 {
+            f |= 0x40;
+        }
+        let (dx, dy) = (x - last_x, y - last_y);
+        (last_x, last_y) = (x, y);
+        if dx == 0 {
+            f |= 0x10;
+        } else if dx.abs() < 256 {
+            f |= 0x02 | if dx > 0 { 0x10 } else { 0 };
+            xs.push(dx.unsigned_abs() as u8);
+        } else {
+            xs.extend_from_slice(&(dx as i16).to_be_bytes());
+        }
+        if dy == 0 {
+            f |= 0x20;
+        } else if dy.abs() < 256 {
+            f |= 0x04 | if dy > 0 { 0x20 } else { 0 };
+            ys.push(dy.unsigned_abs() as u8);
+        } else {
+            ys.extend_from_slice(&(dy as i16).to_be_bytes());
+        }
+        flags.push(f);
+    }
+    out.extend_from_slice(&flags);
+    out.extend_from_slice(&xs);
+    out.extend_from_slice(&ys);
+}
+
+// 还原变换的hmtx表， 省略的左侧留白取字形的xMin
+fn reconstruct_hmtx(data: &[u8], hhea: &[u8], x_mins: &[i16]) -> Option<Vec<u8>> {
+    let num_h_metrics = u16::from_be_bytes([*hhea.get(34)?, *hhea.get(35)?]) as usize;
+    let num_glyphs = x_mins.len();
+    if num_h_metrics == 0 || num_h_metrics > num_glyphs {
+        return None;
+    }
+    let mut r = Reader::new(data);
+    let flags = r.u8()?;
+    let advances = (0..num_h_metrics).map(|_| r.u16()).collect::<Option<Vec<u16>>>()?;
+    let lsbs = (0..num_glyphs).map(|i| {
+        // bit0: 比例字形的左侧留白省略， bit1: 等宽字形的左侧留白省略
+        let omitted = if i < num_h_metrics { flags & 1 != 0 } else { flags & 2 != 0 };
+        if omitted { Some(x_mins[i]) } else { r.u16().map(|v| v as i16) }
+    }).collect::<Option<Vec<i16>>>()?;
+
+    let mut out = Vec::with_capacity(num_h_metrics * 2 + num_glyphs * 2);
+    for (i, lsb) in lsbs.into_iter().enumerate() {
+        if i < num_h_metrics {
+            put_u16(&mut out, advances[i]);
+        }
+        put_u16(&mut out, lsb as u16);
+    }
+    Some(out)
+}
+
+// 组装sfnt， 字体集合时写入ttc头， 多个字体共用的表只写入一次
+pub(crate) fn build_sfnt(fonts: &[(u32, Vec<usize>)], tables: &[([u8; 4], Vec<u8>)], collection: bool) -> Vec<u8> {
+    let header_size = |n: usize| 12 + 16 * n;
+    let mut offset = if collection { 12 + 4 * fonts.len() } else { 0 };
+    let font_offsets = fonts.iter().map(|(_, indices)| {
+        let r = offset;
+        offset += header_size(indices.len());
+        r
+    }).collect::<Vec<usize>>();
+    let table_offsets = tables.iter().map(|(_, data)| {
+        let r = offset;
+        offset += (data.len() + 3) & !3;
+        r
+    }).collect::<Vec<usize>>();
+
+    let mut out = Vec::with_capacity(offset);
+    if collection {
+        out.extend_from_slice(b"ttcf");
+        put_u32(&mut out, 0x00010000);
+        put_u32(&mut out, fonts.len() as u32);
+        for r in font_offsets.iter() {
+            put_u32(&mut out, *r as u32);
+        }
+    }
+    for (flavor, indices) in fonts.iter() {
+        let n = indices.len();
+        let entry_selector = n.max(1).ilog2();
+        let search_range = (1usize << entry_selector) * 16;
+        put_u32(&mut out, *flavor);
+        put_u16(&mut out, n as u16);
+        put_u16(&mut out, search_range as u16);
+        put_u16(&mut out, entry_selector as u16);
+        put_u16(&mut out, (n * 16).saturating_sub(search_range) as u16);
+        // 表记录按标签排序
+        let mut indices = indices.clone();
+        indices.sort_by_key(|i| tables[*i].0);
+        for i in indices {
+            let (tag, data) = &tables[i];
+            out.extend_from_slice(tag);
+            put_u32(&mut out, checksum(tag, data));
+            put_u32(&mut out, table_offsets[i] as u32);
+            put_u32(&mut out, data.len() as u32);
+        }
+    }
+    for (_, data) in tables.iter() {
+        out.extend_from_slice(data);
+        out.resize((out.len() + 3) & !3, 0);
+    }
+    out
+}
+
+// 表的校验和， head表计算时checkSumAdjustment视为0
+fn checksum(tag: &[u8; 4], data: &[u8]) -> u32 {
+    let mut sum = 0u32;
+    for (i, chunk) in data.chunks(4).enumerate() {
+        if tag == b"head" && i == 2 {
+            continue;
+        }
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum = sum.wrapping_add(u32::from_be_bytes(word));
+    }
+    sum
+}
+
+fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+// 大端字节读取
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let r = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(r)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|r| r[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|r| u16::from_be_bytes([r[0], r[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4).map(|r| u32::from_be_bytes([r[0], r[1], r[2], r[3]]))
+    }
+
+    fn tag(&mut self) -> Option<[u8; 4]> {
+        self.bytes(4).map(|r| [r[0], r[1], r[2], r[3]])
+    }
+
+    // UIntBase128， 最多5字节， 不允许前导0
+    fn base128(&mut self) -> Option<u32> {
+        let mut r = 0u32;
+        for i in 0..5 {
+            let b = self.u8()?;
+            if i == 0 && b == 0x80 {
+                return None;
+            }
+            if r & 0xfe00_0000 != 0 {
+                return None;
+            }
+            r = (r << 7) | (b & 0x7f) as u32;
+            if b & 0x80 == 0 {
+                return Some(r);
+            }
+        }
+        None
+    }
+
+    // 255UInt16
+    fn u255_16(&mut self) -> Option<u16> {
+        match self.u8()? {
+            253 => self.u16(),
+            254 => self.u8().map(|r| r as u16 + 253 * 2),
+            255 => self.u8().map(|r| r as u16 + 253),
+            r => Some(r as u16),
+        }
+    }
+}
+
+// 测试字体（tests/fonts）： mini.ttf为手工构造的5个字形的字体（含两个轮廓的字形、带控制点及指令的字形、空字形、复合字形），
+// mini.woff为其zlib压缩的WOFF， mini.woff2为glyf、loca、hmtx变换后的WOFF2（Brotli未压缩元块）
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTF: &[u8] = include_bytes!("../../tests/fonts/mini.ttf");
+    const WOFF: &[u8] = include_bytes!("../../tests/fonts/mini.woff");
+    const WOFF2: &[u8] = include_bytes!("../../tests/fonts/mini.woff2");
+
+    fn u32_at(data: &[u8], pos: usize) -> usize {
+        u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize
+    }
+
+    // sfnt的所有表
+    fn tables(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        let count = u16::from_be_bytes([data[4], data[5]]) as usize;
+        (0..count).map(|i| {
+            let record = 12 + 16 * i;
+            let (offset, len) = (u32_at(data, record + 8), u32_at(data, record + 12));
+            (data[record..record + 4].try_into().unwrap(), &data[offset..offset + len])
+        }).collect()
+    }
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Outline(Vec<(u8, f32, f32)>);
+
+    impl ttf_parser::OutlineBuilder for Outline {
+        fn move_to(&mut self, x: f32, y: f32) {
+            self.0.push((0, x, y));
+        }
+
+        fn line_to(&mut self, x: f32, y: f32) {
+            self.0.push((1, x, y));
+        }
+
+        fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+            self.0.extend([(2, x1, y1), (2, x, y)]);
+        }
+
+        fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+            self.0.extend([(3, x1, y1), (3, x2, y2), (3, x, y)]);
+        }
+
+        fn close(&mut self) {
+            self.0.push((4, 0.0, 0.0));
+        }
+    }
+
+    // 两个字体的所有字形轮廓、步进宽度及左侧留白相同
+    fn assert_same_glyphs(a: &[u8], b: &[u8]) {
+        let (a, b) = (ttf_parser::Face::parse(a, 0).unwrap(), ttf_parser::Face::parse(b, 0).unwrap());
+        assert_eq!(a.number_of_glyphs(), b.number_of_glyphs());
+        for i in 0..a.number_of_glyphs() {
+            let id = ttf_parser::GlyphId(i);
+            let (mut outline_a, mut outline_b) = (Outline::default(), Outline::default());
+            assert_eq!(a.outline_glyph(id, &mut outline_a), b.outline_glyph(id, &mut outline_b), "glyph {}", i);
+            assert_eq!(outline_a, outline_b, "glyph {}", i);
+            assert_eq!(a.glyph_hor_advance(id), b.glyph_hor_advance(id));
+            assert_eq!(a.glyph_hor_side_bearing(id), b.glyph_hor_side_bearing(id));
+        }
+    }
+
+    #[test]
+    fn detect_format() {
+        assert_eq!(FontFormat::detect(TTF), FontFormat::Sfnt);
+        assert_eq!(FontFormat::detect(WOFF), FontFormat::Woff);
+        assert_eq!(FontFormat::detect(WOFF2), FontFormat::Woff2);
+        assert_eq!(FontFormat::detect(b"wOF"), FontFormat::Unknown);
+        assert!(decode(TTF).is_none());
+    }
+
+    #[test]
+    fn woff_round_trip() {
+        let sfnt = decode(WOFF).unwrap();
+        assert_eq!(tables(&sfnt), tables(TTF));
+        assert_same_glyphs(&sfnt, TTF);
+    }
+
+    #[test]
+    fn woff2_round_trip() {
+        let sfnt = decode(WOFF2).unwrap();
+        let (decoded, expected) = (tables(&sfnt), tables(TTF));
+        assert_eq!(decoded.iter().map(|r| r.0).collect::<Vec<_>>(), expected.iter().map(|r| r.0).collect::<Vec<_>>());
+        // glyf、loca还原后的编码可以不同， 其他表（包括变换的hmtx）与原数据相同
+        for (a, b) in decoded.iter().zip(expected.iter()) {
+            if a.0 != GLYF && a.0 != LOCA {
+                assert_eq!(a, b);
+            }
+        }
+        assert_same_glyphs(&sfnt, TTF);
+    }
+
+    #[test]
+    fn truncated() {
+        // 去掉末尾的填充后， 任意截断都返回None
+        let woff_end = (0..u16::from_be_bytes([WOFF[12], WOFF[13]]) as usize)
+            .map(|i| u32_at(WOFF, 44 + 20 * i + 4) + u32_at(WOFF, 44 + 20 * i + 8))
+            .max()
+            .unwrap();
+        // WOFF2压缩数据之后只有0填充
+        let woff2_end = WOFF2.iter().rposition(|b| *b != 0).unwrap() + 1;
+        for (data, end) in [(WOFF, woff_end), (WOFF2, woff2_end)] {
+            for len in 0..end {
+                assert!(decode(&data[..len]).is_none(), "len {}", len);
+            }
+        }
+
+        let buffer = Share::new(WOFF2[..WOFF2.len() / 2].to_vec());
+        assert!(Share::ptr_eq(&to_sfnt(buffer.clone()), &buffer));
+    }
+
+    #[test]
+    fn malformed() {
+        // WOFF： 压缩后比原数据大
+        let mut data = WOFF.to_vec();
+        let record = 44;
+        let orig_length = u32_at(&data, record + 12) as u32;
+        data[record + 8..record + 12].copy_from_slice(&(orig_length + 1).to_be_bytes());
+        assert!(decode(&data).is_none());
+
+        // WOFF： 错误的zlib数据
+        let mut data = WOFF.to_vec();
+        let count = u16::from_be_bytes([data[12], data[13]]) as usize;
+        let record = (0..count).map(|i| 44 + 20 * i).find(|r| u32_at(&data, r + 8) < u32_at(&data, r + 12)).unwrap();
+        let (offset, len) = (u32_at(&data, record + 4), u32_at(&data, record + 8));
+        data[offset..offset + len].fill(0);
+        assert!(decode(&data).is_none());
+
+        // WOFF2： 只有glyf、loca、hmtx可以变换（第一个表为cmap）
+        let mut data = WOFF2.to_vec();
+        data[48] |= 0x40;
+        assert!(decode(&data).is_none());
+
+        // WOFF2： 解压后的大小超过上限
+        let mut data = WOFF2.to_vec();
+        data[16..20].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(decode(&data).is_none());
+    }
+}
@@ -7,13 +7,12 @@ use pi_assets::{
     asset::{Asset, Size, Handle},
     mgr::{AssetMgr, LoadResult},
 };
-use pi_async_rt::rt::AsyncRuntime;
 use pi_atom::Atom;
 use pi_share::Share;
 
 use crate::{create_async_value, Arg};
 
-use super::runtime::MULTI_MEDIA_RUNTIME;
+use super::runtime;
 
 pub struct ImageRes {
     value: DynamicImage,
@@ -82,24 +81,41 @@ pub async fn load_from_path(
             Err(e) => Err(LoadError::IoError(e)),
         },
         LoadResult::Receiver(recv) => {
+            if runtime::is_stopped() {
+                return Err(LoadError::IoError(runtime::stopped_error()));
+            }
             let k1 = k.clone();
-            let wait = MULTI_MEDIA_RUNTIME.wait();
-            wait.spawn(MULTI_MEDIA_RUNTIME.clone(), None, async move {
+
+            // 单线程多媒体运行时，直接在当前任务中解码，由外部推动
+            #[cfg(feature = "single_thread_media")]
+            {
+                match image::open(k1.as_str()) {
+                    Ok(image) => {
+                        if let Err(e) = recv.receive(k1, Ok(ImageRes::new(image))).await {
+                            log::error!("load image fail, {:?}", e);
+                        }
+                    }
+                    Err(e) => log::error!("load image fail, {:?}", e),
+                }
+            }
+
+            // 在被跟踪的多媒体任务中解码，停止任务后不再派发，被取消时直接返回错误
+            #[cfg(not(feature = "single_thread_media"))]
+            runtime::run_media("image_load", async move {
                 let image = match image::open(k1.as_str()) {
                     Ok(r) => r,
                     Err(e) => {
                         log::error!("load image fail, {:?}", e);
-                        return Ok(());
+                        return;
                     }
                 };
 
                 if let Err(e) = recv.receive(k1, Ok(ImageRes::new(image))).await {
                     log::error!("load image fail, {:?}", e);
                 }
-                Ok(())
             })
-            .unwrap();
-            wait.wait_result().await.unwrap();
+            .await
+            .map_err(LoadError::IoError)?;
             match AssetMgr::get(mgr, k) {
                 Some(r) => Ok(r),
                 None => Err(LoadError::Other("load fail".to_string())),
@@ -135,20 +151,27 @@ pub async fn from_path_or_url(path: &str) -> DynamicImage {
 }
 
 pub async fn load_from_url(path: &Atom) -> Result<DynamicImage, ImageError> {
+	if runtime::is_stopped() {
+		return Err(ImageError::IoError(runtime::stopped_error()));
+	}
 	let mut hasher = DefaultHasher::new();
     path.hash(&mut hasher);
     let v = create_async_value("file", "", hasher.finish(), vec![Arg::String(path.to_string())]);
-	// 此处需要放在多线程运行时中解码(当前运行时可能不是一个多线程运行时)
-	let wait = MULTI_MEDIA_RUNTIME.wait::<Result<DynamicImage, ImageError>>();
-	wait.spawn(MULTI_MEDIA_RUNTIME.clone(), None, async move {
-        let r = match v.await {
-            Ok(r) => r,
-            Err(e) => {
-                return Ok(Err(ImageError::IoError(std::io::Error::other(e))));
-            }
-        };
-		Ok(image::load_from_memory(&r))
+
+	// 单线程多媒体运行时，直接在当前任务中解码，由外部推动
+	#[cfg(feature = "single_thread_media")]
+	let r = {
+		let r = v.await.map_err(|e| ImageError::IoError(std::io::Error::other(e)))?;
+		image::load_from_memory(&r)
+	};
+
+	// 此处需要放在多线程运行时中解码(当前运行时可能不是一个多线程运行时)，解码任务受stop_tasks管理
+	#[cfg(not(feature = "single_thread_media"))]
+	let r = runtime::run_media("image_decode", async move {
+		let r = v.await.map_err(|e| ImageError::IoError(std::io::Error::other(e)))?;
+		image::load_from_memory(&r)
 	})
-	.unwrap();
-	wait.wait_result().await.unwrap()
+	.await
+	.map_err(ImageError::IoError)?;
+	r
 }
@@ -70,8 +70,22 @@ pub mod image_texture_load;
 // /// SDF字体处理模块
 // pub mod sdf2_info;
 
+/// 宿主加载协议版本号
+///
+/// 宿主通过[`init_load_msg_cb`]注册回调后，每条[`LoadMsg`]都会携带该版本号。
+/// 当`LoadMsg`或`Arg`发生不兼容的变化时递增，新增`Arg`变体或新增宿主操作不改变版本号。
+///
+/// | 版本 | 变化                                                    |
+/// |------|-------------------------------------------------------|
+/// | 1    | 旧回调`Fn(String, String, String, Vec<Arg>)`，hash为十进制字符串 |
+/// | 2    | `LoadMsg`，hash为u64；`Arg`新增Float/Bool/List/Map         |
+pub const LOAD_PROTOCOL_VERSION: u32 = 2;
+
 /// 异步操作参数枚举
-#[derive(Debug)]
+///
+/// 以后可能继续新增变体，匹配时需要保留通配分支
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Arg {
     /// 数值参数
     Number(u64),
@@ -80,12 +94,64 @@ pub enum Arg {
     /// 二进制数据参数
     Buffer(Vec<u8>),
     /// 空参数
-    None
+    None,
+    /// 浮点参数
+    Float(f64),
+    /// 布尔参数
+    Bool(bool),
+    /// 列表参数
+    List(Vec<Arg>),
+    /// 键值参数，保持插入顺序
+    Map(Vec<(String, Arg)>),
+}
+
+impl Arg {
+    /// 在Map参数中按键查找值
+    pub fn get(&self, key: &str) -> Option<&Arg> {
+        match self {
+            Arg::Map(r) => r.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// 是否为协议版本1中已有的变体（Number/String/Buffer/None），只有这些变体可以传给旧回调
+    pub fn is_legacy(&self) -> bool {
+        matches!(self, Arg::Number(_) | Arg::String(_) | Arg::Buffer(_) | Arg::None)
+    }
+}
+
+/// 宿主加载消息
+///
+/// 描述一次“模块/函数/参数”调用，宿主完成后以`hash`调用[`on_load`]返回结果。
+///
+/// 已定义的调用：
+///
+/// | module | func            | args                 | 返回           |
+/// |--------|-----------------|----------------------|---------------|
+/// | file   | ""              | [String(路径或url)]   | 文件全部内容    |
+/// | file   | "load_font_sdf" | []                   | 预生成的sdf数据 |
+///
+/// 新增宿主操作时，只需约定新的`module`/`func`及参数结构，不识别该操作的宿主应以`Err`回应。
+#[derive(Debug, Clone)]
+pub struct LoadMsg {
+    /// 协议版本，见[`LOAD_PROTOCOL_VERSION`]
+    pub version: u32,
+    /// 宿主模块名
+    pub module: String,
+    /// 模块内函数名，空字符串表示模块的默认操作
+    pub func: String,
+    /// 请求唯一标识
+    pub hash: u64,
+    /// 调用参数
+    pub args: Vec<Arg>,
 }
 
 lazy_static! {
     /// 全局加载回调注册器
     pub static ref LOAD_CB: RwLock<Option<Arc<dyn Fn(String, String, String, Vec<Arg>) + Send + Sync>>> = RwLock::new(None);
+
+    /// 全局加载消息回调注册器（协议版本2及以上）
+    pub static ref LOAD_MSG_CB: RwLock<Option<Arc<dyn Fn(LoadMsg) + Send + Sync>>> = RwLock::new(None);
     
     /// 异步加载任务映射表
     pub static ref LOAD_MAP: Mutex<HashMap<u64, Vec<AsyncValue<Result<Share<Vec<u8>>, String>>>>> =
@@ -96,10 +162,22 @@ lazy_static! {
 /// 
 /// # 参数
 /// - `cb`: 实现加载逻辑的回调函数
+/// 
+/// # 注意
+/// 旧版回调，hash以十进制字符串传入；若同时注册了[`init_load_msg_cb`]，则只调用后者
+/// 参数中含有协议版本2新增变体（Float/Bool/List/Map）的调用不会传给旧版回调，直接以错误结束
 pub fn init_load_cb(cb: Arc<dyn Fn(String, String, String, Vec<Arg>) + Send + Sync>) {
     *LOAD_CB.write().unwrap() = Some(cb);
 }
 
+/// 初始化加载消息回调函数
+/// 
+/// # 参数
+/// - `cb`: 接收[`LoadMsg`]的回调函数
+pub fn init_load_msg_cb(cb: Arc<dyn Fn(LoadMsg) + Send + Sync>) {
+    *LOAD_MSG_CB.write().unwrap() = Some(cb);
+}
+
 /// 资源加载完成回调
 /// 
 /// # 参数
@@ -121,8 +199,15 @@ pub fn on_load(hash: u64, data: Result<Share<Vec<u8>>, String>) {
 /// - `args`: 调用参数
 /// 
 /// # 返回值
-/// 返回异步值句柄，可用于等待加载结果
+/// 返回异步值句柄，可用于等待加载结果；已停止接受任务时，句柄立即得到错误
 pub fn create_async_value(modules: &str, func: &str, hash: u64, args: Vec<Arg>) -> AsyncValue<Result<Share<Vec<u8>>, String>> {
+    // 已停止接受任务，不再向宿主发起加载
+    if runtime::is_stopped() {
+        let v = AsyncValue::new();
+        v.set(Err(runtime::stopped_error().to_string()));
+        return v;
+    }
+
     let mut is_first = false;
     let r = {
         let mut lock = LOAD_MAP.lock();
@@ -141,8 +226,21 @@ pub fn create_async_value(modules: &str, func: &str, hash: u64, args: Vec<Arg>)
     };
 
     if is_first{
-        if let Some(cb) = LOAD_CB.read().unwrap().as_ref() {
-            cb(modules.to_string(), func.to_string(), hash.to_string(), args);
+        if let Some(cb) = LOAD_MSG_CB.read().unwrap().as_ref() {
+            cb(LoadMsg {
+                version: LOAD_PROTOCOL_VERSION,
+                module: modules.to_string(),
+                func: func.to_string(),
+                hash,
+                args,
+            });
+        } else if let Some(cb) = LOAD_CB.read().unwrap().as_ref() {
+            // 旧回调不认识新增的变体，不向其传递，直接以错误结束本次加载
+            if args.iter().all(Arg::is_legacy) {
+                cb(modules.to_string(), func.to_string(), hash.to_string(), args);
+            } else {
+                on_load(hash, Err(format!("legacy load callback does not support args: {}/{}", modules, func)));
+            }
         }
     }
     r
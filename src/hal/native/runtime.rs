@@ -1,54 +1,451 @@
-use std::env;
+//! 异步运行时
+//!
+//! 提供多媒体运行时`MULTI_MEDIA_RUNTIME`与渲染运行时`RENDER_RUNTIME`。
+//! 两者在第一次被访问时按[`RuntimeConfig`]创建，如需定制线程数量、栈大小等参数，
+//! 应在访问运行时之前调用[`init_runtime_config`]。
+//!
+//! 通过[`spawn_media`]、[`spawn_render`]派发的任务会被跟踪，调用[`stop_tasks`]后不再接受新任务，
+//! 在期限内等待已派发的任务完成，并取消期限到达时仍未完成的任务。
+
+use std::{
+    env, fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Condvar, Mutex, OnceLock,
+    },
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use pi_async_rt::prelude::AsyncRuntime;
+use pi_async_rt::rt::AsyncValue;
+use pi_slotmap::{DefaultKey, SlotMap};
 // use std::time::Instant;
 
 // use pi_async_rt::{rt::multi_thread::{MultiTaskRuntime, StealableTaskPool, MultiTaskRuntimeBuilder}};
 // use pi_share::ShareMutex;
 
+/// 运行时配置
+#[derive(Debug, Clone)]
+pub struct RuntimeConfig {
+    /// 工作线程数量，None表示取环境变量`_ver`，未设置时取cpu核数
+    pub worker_count: Option<usize>,
+    /// 工作线程栈大小（字节），None表示使用运行时默认值
+    pub stack_size: Option<usize>,
+    /// 工作线程名前缀
+    pub thread_prefix: String,
+    /// 定时器间隔（毫秒），None表示使用运行时默认值
+    pub timer_interval: Option<usize>,
+    /// 任务池公共队列容量
+    pub queue_capacity: usize,
+    /// 工作线程空闲休眠时间（毫秒）
+    pub idle_timeout: u64,
+}
+
+impl RuntimeConfig {
+    /// 以指定线程名前缀创建默认配置
+    pub fn new(thread_prefix: &str) -> Self {
+        Self {
+            worker_count: None,
+            stack_size: None,
+            thread_prefix: thread_prefix.to_string(),
+            timer_interval: None,
+            queue_capacity: 0x8000,
+            idle_timeout: 3000,
+        }
+    }
+
+    /// 设置工作线程数量
+    pub fn worker_count(mut self, count: usize) -> Self {
+        self.worker_count = Some(count);
+        self
+    }
+
+    /// 设置工作线程栈大小
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// 设置定时器间隔
+    pub fn timer_interval(mut self, interval: usize) -> Self {
+        self.timer_interval = Some(interval);
+        self
+    }
+
+    /// 解析最终的工作线程数量
+    ///
+    /// 优先级：`worker_count` > 环境变量`_ver` > cpu核数
+    pub fn resolve_worker_count(&self) -> Result<usize, RuntimeConfigError> {
+        let count = match self.worker_count {
+            Some(r) => r,
+            None => match env::var("_ver") {
+                Ok(r) => match usize::from_str_radix(r.trim(), 10) {
+                    Ok(r) => r,
+                    Err(_) => return Err(RuntimeConfigError::InvalidEnv(r)),
+                },
+                _ => num_cpus::get(),
+            },
+        };
+        if count == 0 {
+            return Err(RuntimeConfigError::InvalidWorkerCount(count));
+        }
+        Ok(count)
+    }
+
+    fn validate(&self) -> Result<(), RuntimeConfigError> {
+        self.resolve_worker_count()?;
+        if let Some(size) = self.stack_size {
+            if size == 0 {
+                return Err(RuntimeConfigError::InvalidStackSize(size));
+            }
+        }
+        Ok(())
+    }
+
+    // 运行时创建时使用
+    // 配置已在init_runtime_config中校验，只有未设置配置且环境变量`_ver`非法时才会失败，
+    // 此时忽略环境变量，使用cpu核数；需要得到错误的宿主，应先调用init_runtime_config
+    #[cfg(not(feature = "single_thread_media"))]
+    fn build_multi(&self) -> pi_async_rt::prelude::MultiTaskRuntime<()> {
+        let count = match self.resolve_worker_count() {
+            Ok(r) => r,
+            Err(e) => {
+                log::warn!("runtime config invalid, {}: {}, use cpu count", self.thread_prefix, e);
+                num_cpus::get()
+            }
+        };
+        let pool = pi_async_rt::prelude::StealableTaskPool::with(count, self.queue_capacity, [1, 1], self.idle_timeout);
+        let mut builder = pi_async_rt::prelude::MultiTaskRuntimeBuilder::new(pool)
+            .thread_prefix(self.thread_prefix.as_str())
+            .init_worker_size(count)
+            .set_worker_limit(count, count);
+        if let Some(size) = self.stack_size {
+            builder = builder.thread_stack_size(size);
+        }
+        if let Some(interval) = self.timer_interval {
+            builder = builder.set_timer_interval(interval);
+        }
+        builder.build()
+    }
+}
+
+/// 运行时配置错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeConfigError {
+    /// 运行时已经创建，配置不再生效
+    AlreadyStarted,
+    /// 配置已经设置过
+    AlreadyInit,
+    /// 工作线程数量非法
+    InvalidWorkerCount(usize),
+    /// 栈大小非法
+    InvalidStackSize(usize),
+    /// 环境变量`_ver`不是合法的数字
+    InvalidEnv(String),
+}
+
+impl fmt::Display for RuntimeConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeConfigError::AlreadyStarted => write!(f, "runtime already started"),
+            RuntimeConfigError::AlreadyInit => write!(f, "runtime config already init"),
+            RuntimeConfigError::InvalidWorkerCount(r) => write!(f, "invalid worker count: {}", r),
+            RuntimeConfigError::InvalidStackSize(r) => write!(f, "invalid stack size: {}", r),
+            RuntimeConfigError::InvalidEnv(r) => write!(f, "invalid env _ver: {:?}", r),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeConfigError {}
+
+// (多媒体运行时配置, 渲染运行时配置)
+// 运行时创建时若仍未设置，以默认配置初始化，之后init_runtime_config的设置会失败，因此不存在检查与设置之间的竞争
+static CONFIG: OnceLock<(RuntimeConfig, RuntimeConfig)> = OnceLock::new();
+static RUNTIME_STARTED: AtomicBool = AtomicBool::new(false);
+
+fn default_config() -> (RuntimeConfig, RuntimeConfig) {
+    (RuntimeConfig::new("MULTI_MEDIA_RUNTIME"), RuntimeConfig::new("RENDER_RUNTIME"))
+}
+
+// 运行时创建时调用，标记运行时已创建，并固定配置
+fn started_config() -> &'static (RuntimeConfig, RuntimeConfig) {
+    RUNTIME_STARTED.store(true, Ordering::SeqCst);
+    CONFIG.get_or_init(default_config)
+}
+
+/// 设置运行时配置
+///
+/// 必须在第一次访问`MULTI_MEDIA_RUNTIME`或`RENDER_RUNTIME`之前调用，且只能调用一次。
+/// 未调用时，运行时使用[`RuntimeConfig::new`]的默认配置，此时若环境变量`_ver`非法，只记录警告并使用cpu核数。
+/// 配置在此校验（包括环境变量`_ver`），非法时返回错误，且不会被设置。
+///
+/// # 参数
+/// - `multi_media`: 多媒体运行时配置
+/// - `render`: 渲染运行时配置（`single_thread`特性下渲染运行时为单线程，仅线程数以外的配置无意义）
+pub fn init_runtime_config(mut multi_media: RuntimeConfig, mut render: RuntimeConfig) -> Result<(), RuntimeConfigError> {
+    multi_media.validate()?;
+    render.validate()?;
+    // 固定线程数量，运行时创建时不再读取环境变量
+    multi_media.worker_count = Some(multi_media.resolve_worker_count()?);
+    render.worker_count = Some(render.resolve_worker_count()?);
+    CONFIG.set((multi_media, render)).map_err(|_| {
+        if RUNTIME_STARTED.load(Ordering::SeqCst) {
+            RuntimeConfigError::AlreadyStarted
+        } else {
+            RuntimeConfigError::AlreadyInit
+        }
+    })
+}
+
+/// 多媒体运行时配置
+pub fn multi_media_config() -> RuntimeConfig {
+    CONFIG.get().map_or_else(|| default_config().0, |r| r.0.clone())
+}
+
+/// 渲染运行时配置
+pub fn render_config() -> RuntimeConfig {
+    CONFIG.get().map_or_else(|| default_config().1, |r| r.1.clone())
+}
+
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+// 被跟踪的任务
+struct Task {
+    name: &'static str,
+    // 最近一次poll时的waker，取消任务时用于唤醒
+    waker: Option<Waker>,
+    // 已被取消，下次poll时直接结束
+    aborted: bool,
+}
+
+lazy_static! {
+    // 正在执行的任务，及任务全部结束时的通知
+    static ref IN_FLIGHT: (Mutex<SlotMap<DefaultKey, Task>>, Condvar) = (Mutex::new(SlotMap::new()), Condvar::new());
+}
+
+/// 停止任务的结果
+#[derive(Debug, Clone, Default)]
+pub struct StopReport {
+    /// 期限内完成的任务数量
+    pub drained: usize,
+    /// 期限到达时仍未完成、已被取消的任务名
+    pub abandoned: Vec<&'static str>,
+}
+
+// 任务存活期间在IN_FLIGHT中占一个位置，销毁时移除并通知等待方
+struct TaskGuard(DefaultKey);
+
+impl TaskGuard {
+    fn new(name: &'static str) -> std::io::Result<Self> {
+        if is_stopped() {
+            return Err(stopped_error());
+        }
+        Ok(TaskGuard(IN_FLIGHT.0.lock().unwrap().insert(Task { name, waker: None, aborted: false })))
+    }
+
+    // 记录waker，返回任务是否已被取消
+    fn poll_aborted(&self, cx: &Context<'_>) -> bool {
+        let mut lock = IN_FLIGHT.0.lock().unwrap();
+        match lock.get_mut(self.0) {
+            Some(r) if !r.aborted => {
+                r.waker = Some(cx.waker().clone());
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        let mut lock = IN_FLIGHT.0.lock().unwrap();
+        lock.remove(self.0);
+        if lock.is_empty() {
+            IN_FLIGHT.1.notify_all();
+        }
+    }
+}
+
+// 可取消的任务，被取消后在下次poll时丢弃内部的future（不再执行其后续部分）
+struct Tracked<F> {
+    future: Option<Pin<Box<F>>>,
+    guard: TaskGuard,
+}
+
+impl<F: Future<Output = ()>> Future for Tracked<F> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.guard.poll_aborted(cx) {
+            self.future = None;
+            return Poll::Ready(());
+        }
+        match self.future.as_mut() {
+            Some(future) => match future.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    self.future = None;
+                    Poll::Ready(())
+                }
+                Poll::Pending => Poll::Pending,
+            },
+            None => Poll::Ready(()),
+        }
+    }
+}
+
+fn tracked<F>(name: &'static str, future: F) -> std::io::Result<Tracked<F>>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    Ok(Tracked { future: Some(Box::pin(future)), guard: TaskGuard::new(name)? })
+}
+
+/// 停止接受任务后，派发任务或加载资源时返回的错误
+pub fn stopped_error() -> std::io::Error {
+    std::io::Error::other("runtime tasks are stopped")
+}
+
+/// 是否已停止接受任务
+pub fn is_stopped() -> bool {
+    STOPPED.load(Ordering::Acquire)
+}
+
+/// 在多媒体运行时上派发一个被跟踪的任务
+///
+/// # 参数
+/// - `name`: 任务名，停止任务时用于报告未完成的任务
+/// - `future`: 任务
+///
+/// # 返回值
+/// 已停止接受任务时返回[`stopped_error`]
+pub fn spawn_media<F>(name: &'static str, future: F) -> std::io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    MULTI_MEDIA_RUNTIME.spawn(tracked(name, future)?)
+}
+
+/// 在渲染运行时上派发一个被跟踪的任务，参数及返回值同[`spawn_media`]
+pub fn spawn_render<F>(name: &'static str, future: F) -> std::io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    RENDER_RUNTIME.spawn(tracked(name, future)?)
+}
+
+// 任务结果的回复，任务未完成就被丢弃（取消或派发失败）时回复stopped_error，等待方不会永远等待
+struct Reply<T: Send + 'static> {
+    value: AsyncValue<std::io::Result<T>>,
+    done: bool,
+}
+
+impl<T: Send + 'static> Reply<T> {
+    fn set(mut self, r: T) {
+        self.done = true;
+        self.value.set(Ok(r));
+    }
+}
+
+impl<T: Send + 'static> Drop for Reply<T> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.value.set(Err(stopped_error()));
+        }
+    }
+}
+
+/// 在多媒体运行时上派发一个被跟踪的任务，并等待其结果
+///
+/// 用于在当前运行时之外执行耗时的计算（如图片解码），任务同样受[`stop_tasks`]管理
+///
+/// # 返回值
+/// 已停止接受任务，或任务在完成前被[`stop_tasks`]取消时返回[`stopped_error`]
+pub async fn run_media<F, T>(name: &'static str, future: F) -> std::io::Result<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let value = AsyncValue::new();
+    let reply = Reply { value: value.clone(), done: false };
+    spawn_media(name, async move {
+        reply.set(future.await);
+    })?;
+    value.await
+}
+
+/// 停止任务
+///
+/// 立即停止接受新任务（[`spawn_media`]、[`spawn_render`]及资源加载返回错误），
+/// 并阻塞等待已派发的任务，最多等待`deadline`；期限到达时仍未完成的任务被取消，
+/// 在下次被运行时poll时直接结束，不再执行其后续部分（正在执行的同步计算无法被打断，会在其结束后取消）。
+///
+/// 限制：本函数只停止任务，不回收线程。`MULTI_MEDIA_RUNTIME`、`RENDER_RUNTIME`是进程内唯一的静态运行时，
+/// 其工作线程在进程退出前不会结束（也无法join），停止后保持空闲，可通过[`resume`]重新接受任务；
+/// 反复创建进程内环境的宿主（测试、热重载）不会因此得到新的线程，但已创建的线程也不会被释放。
+///
+/// # 参数
+/// - `deadline`: 最长等待时间
+pub fn stop_tasks(deadline: Duration) -> StopReport {
+    STOPPED.store(true, Ordering::Release);
+    let end = Instant::now() + deadline;
+    let mut lock = IN_FLIGHT.0.lock().unwrap();
+    let total = lock.len();
+    while !lock.is_empty() {
+        let now = Instant::now();
+        if now >= end {
+            break;
+        }
+        lock = IN_FLIGHT.1.wait_timeout(lock, end - now).unwrap().0;
+    }
+    let (mut abandoned, mut wakers) = (Vec::new(), Vec::new());
+    for task in lock.values_mut() {
+        abandoned.push(task.name);
+        task.aborted = true;
+        wakers.extend(task.waker.take());
+    }
+    // 释放锁后再唤醒，被唤醒的任务在poll时需要获取该锁
+    drop(lock);
+    for waker in wakers {
+        waker.wake();
+    }
+    if abandoned.len() > 0 {
+        log::warn!("runtime tasks stopped, abandoned tasks: {:?}", abandoned);
+    }
+    StopReport {
+        drained: total.saturating_sub(abandoned.len()),
+        abandoned,
+    }
+}
+
+/// 重新接受任务（用于测试或热重载后恢复）
+///
+/// 之前被取消的任务仍然保持取消
+pub fn resume() {
+    STOPPED.store(false, Ordering::Release);
+}
+
 #[cfg(not(feature = "single_thread"))]
 lazy_static! {
 	// pub static ref LOGS: ShareMutex<(Vec<String>, Instant)> = ShareMutex::new((Vec::new(), Instant::now()));
 
     // 多媒体运行时，多线程，不需要主动推
-    pub static ref MULTI_MEDIA_RUNTIME: pi_async_rt::prelude::MultiTaskRuntime<()>  = {
-        let count = match env::var("_ver") {
-            Ok(r) => usize::from_str_radix(r.as_str(), 10).unwrap(),
-            _ => num_cpus::get()
-        };
-		// let count = 8;
-        let pool = pi_async_rt::prelude::StealableTaskPool::with(count, 0x8000, [1, 1], 3000);
-        // 线程池：每个线程1M的栈空间，10ms 休眠，10毫秒的定时器间隔
-        let builder = pi_async_rt::prelude::MultiTaskRuntimeBuilder::new(pool).thread_prefix("MULTI_MEDIA_RUNTIME").init_worker_size(count).set_worker_limit(count, count);
-        builder.build()
-    };
+    pub static ref MULTI_MEDIA_RUNTIME: pi_async_rt::prelude::MultiTaskRuntime<()>  = started_config().0.build_multi();
 
 	// 渲染运行时，多线程，不需要主动推
     pub static ref RENDER_RUNTIME: pi_async_rt::prelude::MultiTaskRuntime<()>  = {
-		let count = match env::var("_ver") {
-            Ok(r) => usize::from_str_radix(r.as_str(), 10).unwrap(),
-            _ => num_cpus::get()
-        };
-		// let count = 8;
-        let pool = pi_async_rt::prelude::StealableTaskPool::with(count, 0x8000, [1, 1], 3000);
 		// let pool = pi_async_rt::prelude::ComputationalTaskPool::new(count);
-        // 线程池：每个线程1M的栈空间，10ms 休眠，10毫秒的定时器间隔
-        let builder = pi_async_rt::prelude::MultiTaskRuntimeBuilder::new(pool).thread_prefix("RENDER_RUNTIME").init_worker_size(count).set_worker_limit(count, count);
-        builder.build()
+        started_config().1.build_multi()
 
-        // let count = match env::var("_ver") {
-        //     Ok(r) => usize::from_str_radix(r.as_str(), 10).unwrap(),
-        //     _ => num_cpus::get()
-        // };
-        // let pool = pi_async_rt::prelude::StealableTaskPool::with(count, 0x8000, [1, 1], 3000);
-        // // 线程池：每个线程1M的栈空间，10ms 休眠，10毫秒的定时器间隔
-        // let builder = pi_async_rt::prelude::MultiTaskRuntimeBuilder::new(pool).init_worker_size(count).set_worker_limit(count, count);
-        // builder.build()
 		// let rt = pi_async_rt::prelude::AsyncRuntimeBuilder::default_multi_thread(Some("RENDER_RUNTIME"), None, None, None);
     	// rt
     };
 }
 
 
-#[cfg(feature = "single_thread")]
+#[cfg(all(feature = "single_thread", not(feature = "single_thread_media")))]
 lazy_static! {
 	// pub static ref LOGS: ShareMutex<(Vec<String>, Instant)> = ShareMutex::new((Vec::new(), Instant::now()));
 
@@ -59,24 +456,95 @@ lazy_static! {
 	// 	// runner.startup().unwrap()
     // };
 	// 多媒体运行时，多线程，不需要主动推(单线程指的是渲染， 多媒体运行时还是需要用多线程)
-    pub static ref MULTI_MEDIA_RUNTIME: pi_async_rt::prelude::MultiTaskRuntime<()>  = {
-        let count = match env::var("_ver") {
-            Ok(r) => usize::from_str_radix(r.as_str(), 10).unwrap(),
-            _ => num_cpus::get()
-        };
-		// let count = 8;
-        let pool = pi_async_rt::prelude::StealableTaskPool::with(count, 0x8000, [1, 1], 3000);
-        // 线程池：每个线程1M的栈空间，10ms 休眠，10毫秒的定时器间隔
-        let builder = pi_async_rt::prelude::MultiTaskRuntimeBuilder::new(pool).thread_prefix("MULTI_MEDIA_RUNTIME").init_worker_size(count).set_worker_limit(count, count);
-        builder.build()
-    };
+    pub static ref MULTI_MEDIA_RUNTIME: pi_async_rt::prelude::MultiTaskRuntime<()>  = started_config().0.build_multi();
+}
+
+// 多媒体运行时，单线程，需要通过run_media_once/run_until_stalled主动推
+#[cfg(feature = "single_thread_media")]
+lazy_static! {
+	static ref MULTI_MEDIA_RUNNER: Mutex<pi_async_rt::prelude::SingleTaskRunner<()>> = {
+		started_config();
+		Mutex::new(pi_async_rt::prelude::SingleTaskRunner::default())
+	};
 
-	// 渲染运行时，单线程，不需要主动推
-	pub static ref RENDER_RUNTIME:  pi_async_rt::prelude::SingleTaskRuntime = {
-		// 渲染运行时，多线程，不需要主动推
-		let runner:  pi_async_rt::prelude::SingleTaskRunner<()> = pi_async_rt::prelude::SingleTaskRunner::default();
-		runner.into_local()
+	pub static ref MULTI_MEDIA_RUNTIME: pi_async_rt::prelude::SingleTaskRuntime = MULTI_MEDIA_RUNNER.lock().unwrap().startup().unwrap();
+}
+
+// 渲染运行时，单线程，需要通过run_once/run_until_stalled主动推
+#[cfg(feature = "single_thread")]
+lazy_static! {
+	static ref RENDER_RUNNER: Mutex<pi_async_rt::prelude::SingleTaskRunner<()>> = {
+		started_config();
+		Mutex::new(pi_async_rt::prelude::SingleTaskRunner::default())
 	};
+
+	pub static ref RENDER_RUNTIME:  pi_async_rt::prelude::SingleTaskRuntime = RENDER_RUNNER.lock().unwrap().startup().unwrap();
+}
+
+/// 执行渲染运行时中的一个就绪任务
+///
+/// # 返回值
+/// 执行后队列中剩余的就绪任务数量
+#[cfg(feature = "single_thread")]
+pub fn run_once() -> std::io::Result<usize> {
+	lazy_static::initialize(&RENDER_RUNTIME);
+	RENDER_RUNNER.lock().unwrap().run_once()
+}
+
+/// 执行多媒体运行时中的一个就绪任务
+///
+/// # 返回值
+/// 执行后队列中剩余的就绪任务数量
+#[cfg(feature = "single_thread_media")]
+pub fn run_media_once() -> std::io::Result<usize> {
+	lazy_static::initialize(&MULTI_MEDIA_RUNTIME);
+	MULTI_MEDIA_RUNNER.lock().unwrap().run_once()
+}
+
+/// 反复推动单线程运行时，直到没有就绪任务
+///
+/// 启用`single_thread_media`时同时推动多媒体运行时，两者交替执行，
+/// 直到某一轮中渲染队列与多媒体队列都没有执行任何任务才返回，
+/// 因此sdf计算、图片解码等任务可以在测试中一步步确定地完成。
+///
+/// # 返回值
+/// 本次执行的任务数量
+#[cfg(feature = "single_thread")]
+pub fn run_until_stalled() -> std::io::Result<usize> {
+	#[cfg(feature = "single_thread_media")]
+	let media = || pump(|| MULTI_MEDIA_RUNTIME.len(), run_media_once);
+	#[cfg(not(feature = "single_thread_media"))]
+	let media = || Ok(0);
+	until_stalled(|| pump(|| RENDER_RUNTIME.len(), run_once), media)
+}
+
+// 执行队列中的就绪任务（包括执行期间新加入的），直到队列为空，返回执行的任务数量
+// 以执行前的队列长度判断是否执行了任务，run_once的返回值（执行后的剩余数量）为0时仍可能执行了最后一个任务
+#[cfg(any(feature = "single_thread", test))]
+fn pump(len: impl Fn() -> usize, mut run: impl FnMut() -> std::io::Result<usize>) -> std::io::Result<usize> {
+	let mut count = 0;
+	while len() > 0 {
+		run()?;
+		count += 1;
+	}
+	Ok(count)
+}
+
+// 交替推动渲染队列与多媒体队列，直到一整轮中两者都没有执行任何任务
+// 渲染任务可能投递多媒体任务，多媒体任务也可能唤醒渲染任务，只要本轮执行过任务，就需要再来一轮
+#[cfg(any(feature = "single_thread", test))]
+fn until_stalled(
+	mut render: impl FnMut() -> std::io::Result<usize>,
+	mut media: impl FnMut() -> std::io::Result<usize>,
+) -> std::io::Result<usize> {
+	let mut count = 0;
+	loop {
+		let executed = render()? + media()?;
+		if executed == 0 {
+			return Ok(count);
+		}
+		count += executed;
+	}
 }
 
 
@@ -105,3 +573,39 @@ lazy_static! {
 
 //     rt
 
+
+#[cfg(test)]
+mod tests {
+	use std::{cell::RefCell, collections::VecDeque};
+
+	use super::*;
+
+	// 执行队列中的第一个任务：任务n向另一个队列投递任务n-1，与run_once一样返回执行后的剩余数量
+	fn run(from: &RefCell<VecDeque<usize>>, to: &RefCell<VecDeque<usize>>) -> std::io::Result<usize> {
+		let n = from.borrow_mut().pop_front().unwrap();
+		if n > 0 {
+			to.borrow_mut().push_back(n - 1);
+		}
+		Ok(from.borrow().len())
+	}
+
+	#[test]
+	fn ping_pong() {
+		// 每个任务执行后自己的队列都为空，只有另一个队列中有刚投递的任务
+		let render = RefCell::new(VecDeque::from([5]));
+		let media = RefCell::new(VecDeque::new());
+		let count = until_stalled(
+			|| pump(|| render.borrow().len(), || run(&render, &media)),
+			|| pump(|| media.borrow().len(), || run(&media, &render)),
+		)
+		.unwrap();
+		assert_eq!(count, 6);
+		assert!(render.borrow().is_empty());
+		assert!(media.borrow().is_empty());
+	}
+
+	#[test]
+	fn stalled_when_empty() {
+		assert_eq!(until_stalled(|| pump(|| 0, || unreachable!()), || Ok(0)).unwrap(), 0);
+	}
+}
@@ -21,6 +21,9 @@ pub struct FileLoadErr(JsValue);
 /// 当未启用`web_local_load`特性时使用此实现
 #[cfg(not(feature="web_local_load"))]
 pub async fn load_from_url(path: &Atom) -> Result<Share<Vec<u8>>, FileLoadErr> {
+	if super::runtime::is_stopped() {
+		return Err(FileLoadErr(JsValue::from_str(&super::runtime::stopped_error().to_string())));
+	}
 	let id = unsafe {transmute::<_, f64>(path.str_hash())};
 	if hasAtom(id) == false {
 		setAtom(id, path.to_string());
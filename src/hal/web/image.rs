@@ -26,6 +26,10 @@ pub async fn load_from_url(path: &Atom) -> Result<DynamicImage, ImageError> {
 	// 	false
 	// };
 	
+	if super::runtime::is_stopped() {
+		return Err(ImageError::IoError(super::runtime::stopped_error()));
+	}
+
 	let id = unsafe {transmute::<_, f64>(path.str_hash())};
 	if hasAtom(id) == false {
 		setAtom(id, path.to_string());
@@ -1,5 +1,13 @@
 use std::{sync::Arc, ops::Deref, marker::Sync};
 use std::cell::OnceCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Mutex, atomic::{AtomicBool, Ordering}};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use pi_slotmap::{DefaultKey, SlotMap};
+use wasm_bindgen::{closure::Closure, JsCast};
 
 use pi_async_rt::rt::serial_local_compatible_wasm_runtime::{LocalTaskRuntime, LocalTaskRunner};
 // use std::future::Future;
@@ -39,6 +47,163 @@ impl Deref for OnceCellWrap {
 pub static MULTI_MEDIA_RUNTIME: OnceCellWrap = OnceCellWrap(OnceCell::new());
 pub static RENDER_RUNTIME: OnceCellWrap = OnceCellWrap(OnceCell::new());
 
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+// 被跟踪的任务
+struct Task {
+	name: &'static str,
+	// 最近一次poll时的waker，取消任务时用于唤醒
+	waker: Option<Waker>,
+	// 已被取消，下次poll时直接结束
+	aborted: bool,
+}
+
+lazy_static! {
+	// 正在执行的任务
+	static ref IN_FLIGHT: Mutex<SlotMap<DefaultKey, Task>> = Mutex::new(SlotMap::new());
+}
+
+/// 停止任务的结果
+#[derive(Debug, Clone, Default)]
+pub struct StopReport {
+	/// 期限内完成的任务数量
+	pub drained: usize,
+	/// 未完成的任务名
+	pub abandoned: Vec<&'static str>,
+}
+
+// 任务存活期间在IN_FLIGHT中占一个位置，销毁时移除
+struct TaskGuard(DefaultKey);
+
+impl TaskGuard {
+	fn new(name: &'static str) -> std::io::Result<Self> {
+		if is_stopped() {
+			return Err(stopped_error());
+		}
+		Ok(TaskGuard(IN_FLIGHT.lock().unwrap().insert(Task { name, waker: None, aborted: false })))
+	}
+
+	// 记录waker，返回任务是否已被取消
+	fn poll_aborted(&self, cx: &Context<'_>) -> bool {
+		let mut lock = IN_FLIGHT.lock().unwrap();
+		match lock.get_mut(self.0) {
+			Some(r) if !r.aborted => {
+				r.waker = Some(cx.waker().clone());
+				false
+			}
+			_ => true,
+		}
+	}
+}
+
+impl Drop for TaskGuard {
+	fn drop(&mut self) {
+		IN_FLIGHT.lock().unwrap().remove(self.0);
+	}
+}
+
+// 可取消的任务，被取消后在下次poll时丢弃内部的future（不再执行其后续部分）
+struct Tracked<F> {
+	future: Option<Pin<Box<F>>>,
+	guard: TaskGuard,
+}
+
+impl<F: Future<Output = ()>> Future for Tracked<F> {
+	type Output = ();
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+		if self.guard.poll_aborted(cx) {
+			self.future = None;
+			return Poll::Ready(());
+		}
+		match self.future.as_mut() {
+			Some(future) => match future.as_mut().poll(cx) {
+				Poll::Ready(()) => {
+					self.future = None;
+					Poll::Ready(())
+				}
+				Poll::Pending => Poll::Pending,
+			},
+			None => Poll::Ready(()),
+		}
+	}
+}
+
+/// 停止接受任务后，派发任务或加载资源时返回的错误
+pub fn stopped_error() -> std::io::Error {
+	std::io::Error::other("runtime tasks are stopped")
+}
+
+/// 是否已停止接受任务
+pub fn is_stopped() -> bool {
+	STOPPED.load(Ordering::Acquire)
+}
+
+/// 在多媒体运行时上派发一个被跟踪的任务
+pub fn spawn_media<F>(name: &'static str, future: F) -> std::io::Result<()>
+where
+	F: Future<Output = ()> + 'static,
+{
+	MULTI_MEDIA_RUNTIME.spawn(Tracked { future: Some(Box::pin(future)), guard: TaskGuard::new(name)? })
+}
+
+/// 在渲染运行时上派发一个被跟踪的任务
+pub fn spawn_render<F>(name: &'static str, future: F) -> std::io::Result<()>
+where
+	F: Future<Output = ()> + 'static,
+{
+	RENDER_RUNTIME.spawn(Tracked { future: Some(Box::pin(future)), guard: TaskGuard::new(name)? })
+}
+
+/// 停止任务
+///
+/// web平台运行时由外部推动，无法阻塞等待，因此立即返回：`drained`为0，`abandoned`为调用时仍未完成的任务。
+/// 这些任务在`deadline`内仍可由外部继续推动完成，期限到达时（通过setTimeout）仍未完成的任务被取消，
+/// 在下次被poll时直接结束，不再执行其后续部分。没有window（如在worker中）时，未完成的任务立即被取消。
+///
+/// # 参数
+/// - `deadline`: 未完成的任务最多还能执行的时间
+pub fn stop_tasks(deadline: Duration) -> StopReport {
+	STOPPED.store(true, Ordering::Release);
+	let abandoned = IN_FLIGHT.lock().unwrap().values().map(|r| r.name).collect::<Vec<&'static str>>();
+	if !abandoned.is_empty() {
+		log::warn!("runtime tasks stopped, unfinished tasks: {:?}", abandoned);
+		let abort = Closure::once_into_js(abort_all);
+		let timeout = web_sys::window().map(|w| {
+			w.set_timeout_with_callback_and_timeout_and_arguments_0(abort.unchecked_ref(), deadline.as_millis().min(i32::MAX as u128) as i32)
+		});
+		if !matches!(timeout, Some(Ok(_))) {
+			abort_all();
+		}
+	}
+	StopReport {
+		drained: 0,
+		abandoned,
+	}
+}
+
+// 取消所有未完成的任务，并唤醒它们以便结束
+fn abort_all() {
+	let mut wakers = Vec::new();
+	{
+		let mut lock = IN_FLIGHT.lock().unwrap();
+		for task in lock.values_mut() {
+			task.aborted = true;
+			wakers.extend(task.waker.take());
+		}
+	}
+	for waker in wakers {
+		waker.wake();
+	}
+}
+
+/// 重新接受任务（用于测试或热重载后恢复）
+///
+/// 之前被取消的任务仍然保持取消
+pub fn resume() {
+	STOPPED.store(false, Ordering::Release);
+}
+
 // lazy_static! {
 // 	pub static ref RUNNER_MULTI: Arc<ShareMutex<LocalTaskRunner<()>>> = Arc::new(ShareMutex::new(LocalTaskRunner::new()));
 // 	// 多媒体运行时，多线程，需要主动推